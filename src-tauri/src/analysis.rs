@@ -0,0 +1,288 @@
+//! Spectrum and level metering of native playback.
+//!
+//! The output pulls samples through an `AnalysisTap`, which keeps the most recent
+//! second of them. The playback thread then runs a `Meter` over the samples
+//! being heard at that moment, allowing for output latency, so the meters follow
+//! the speakers rather than the decoder.
+
+use parking_lot::Mutex;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Floor for every reported level, in dBFS.
+pub const MIN_DB: f32 = -100.0;
+/// Lowest frequency shown in the spectrum.
+const LOWEST_HZ: f64 = 20.0;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AnalysisOptions {
+    pub enabled: bool,
+    /// Frames per FFT, a power of two from 256 to 16384.
+    pub fft_size: usize,
+    /// Spectrum bands, spaced logarithmically from 20 Hz to Nyquist.
+    pub bands: usize,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            fft_size: 2048,
+            bands: 64,
+        }
+    }
+}
+
+impl AnalysisOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.fft_size.is_power_of_two() || !(256..=16384).contains(&self.fft_size) {
+            return Err(format!("Invalid FFT size: {} (expected a power of two from 256 to 16384)", self.fft_size));
+        }
+        if !(1..=512).contains(&self.bands) {
+            return Err(format!("Invalid spectrum band count: {} (expected 1-512)", self.bands));
+        }
+        Ok(())
+    }
+}
+
+/// Levels of a block of audio, in dBFS per channel.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Levels {
+    pub peak_db: Vec<f32>,
+    pub rms_db: Vec<f32>,
+    /// Correlation of the first two channels: 1 for mono, 0 for unrelated, -1 for
+    /// out of phase. 1 for single-channel audio and 0 for silence.
+    pub correlation: f32,
+}
+
+impl Levels {
+    pub fn measure(samples: &[i16], channels: u16) -> Self {
+        let channels = channels as usize;
+        let mut peaks = vec![0i32; channels];
+        let mut squares = vec![0f64; channels];
+        let mut cross = 0f64;
+        for frame in samples.chunks_exact(channels) {
+            for (c, &s) in frame.iter().enumerate() {
+                peaks[c] = peaks[c].max((s as i32).abs());
+                squares[c] += s as f64 * s as f64;
+            }
+            if channels > 1 {
+                cross += frame[0] as f64 * frame[1] as f64;
+            }
+        }
+
+        let frames = (samples.len() / channels).max(1) as f64;
+        let correlation = if channels == 1 {
+            1.0
+        } else if squares[0] > 0.0 && squares[1] > 0.0 {
+            (cross / (squares[0] * squares[1]).sqrt()) as f32
+        } else {
+            0.0
+        };
+        Self {
+            peak_db: peaks.iter().map(|&p| to_db(p as f64 / 32768.0)).collect(),
+            rms_db: squares.iter().map(|&s| to_db((s / frames).sqrt() / 32768.0)).collect(),
+            correlation,
+        }
+    }
+}
+
+fn to_db(amplitude: f64) -> f32 {
+    if amplitude <= 0.0 {
+        return MIN_DB;
+    }
+    ((20.0 * amplitude.log10()) as f32).max(MIN_DB)
+}
+
+/// Spectrum of the channels mixed to mono, through a Hann window.
+pub struct Spectrum {
+    sample_rate: u32,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    /// Amplitude of a full-scale sine at its bin, for scaling to dBFS.
+    full_scale: f32,
+    /// Bins `start..end` making up each band.
+    bands: Vec<(usize, usize)>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Spectrum {
+    pub fn new(sample_rate: u32, options: &AnalysisOptions) -> Result<Self, String> {
+        options.validate()?;
+        let size = options.fft_size;
+        let fft = FftPlanner::new().plan_fft_forward(size);
+        let window: Vec<f32> = (0..size)
+            .map(|i| (0.5 - 0.5 * (std::f64::consts::TAU * i as f64 / size as f64).cos()) as f32)
+            .collect();
+        let full_scale = window.iter().sum::<f32>() / 2.0;
+
+        // Log-spaced bands; those narrower than a bin read the bin at their centre
+        let nyquist = sample_rate as f64 / 2.0;
+        let bins = size / 2;
+        let edge = |band: usize| {
+            let hz = LOWEST_HZ * (nyquist / LOWEST_HZ).powf(band as f64 / options.bands as f64);
+            hz / nyquist * bins as f64
+        };
+        let bands = (0..options.bands)
+            .map(|band| {
+                let (low, high) = (edge(band), edge(band + 1));
+                let (start, end) = ((low.ceil() as usize).max(1), (high.ceil() as usize).min(bins));
+                if end > start {
+                    (start, end)
+                } else {
+                    let centre = ((low * high).sqrt().round() as usize).clamp(1, bins - 1);
+                    (centre, centre + 1)
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            sample_rate,
+            scratch: vec![Complex::default(); fft.get_inplace_scratch_len()],
+            buffer: vec![Complex::default(); size],
+            fft,
+            window,
+            full_scale,
+            bands,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.window.len()
+    }
+
+    /// Level of each band in dBFS, the loudest bin in the band, over the last
+    /// `size()` frames of `samples` (interleaved). Shorter input is padded with
+    /// silence at the front.
+    pub fn compute(&mut self, samples: &[i16], channels: u16) -> Vec<f32> {
+        let channels = channels as usize;
+        let size = self.size();
+        let frames = samples.len() / channels;
+        let padding = size.saturating_sub(frames);
+        let first = frames.saturating_sub(size);
+
+        self.buffer[..padding].fill(Complex::default());
+        for (i, frame) in samples[first * channels..].chunks_exact(channels).enumerate() {
+            let mono = frame.iter().map(|&s| s as f32).sum::<f32>() / (channels as f32 * 32768.0);
+            self.buffer[padding + i] = Complex::new(mono * self.window[padding + i], 0.0);
+        }
+        self.fft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        self.bands
+            .iter()
+            .map(|&(start, end)| {
+                let loudest = self.buffer[start..end]
+                    .iter()
+                    .map(|bin| bin.norm())
+                    .fold(0.0f32, f32::max);
+                to_db((loudest / self.full_scale) as f64)
+            })
+            .collect()
+    }
+}
+
+/// The most recent samples the output pulled, shared between the audio thread
+/// and the playback thread. Samples are numbered from the start of playback.
+pub struct AnalysisTap {
+    ring: Mutex<Ring>,
+}
+
+struct Ring {
+    samples: Vec<i16>,
+    channels: usize,
+    written: usize,
+}
+
+impl AnalysisTap {
+    /// A tap remembering the last `frames` frames.
+    pub fn new(frames: usize, channels: u16) -> Self {
+        Self {
+            ring: Mutex::new(Ring {
+                samples: vec![0; frames.max(1) * channels as usize],
+                channels: channels as usize,
+                written: 0,
+            }),
+        }
+    }
+
+    pub fn push(&self, samples: &[i16]) {
+        let mut ring = self.ring.lock();
+        let capacity = ring.samples.len();
+        for &sample in samples {
+            let at = ring.written % capacity;
+            ring.samples[at] = sample;
+            ring.written += 1;
+        }
+    }
+
+    /// Samples pushed so far.
+    pub fn written(&self) -> usize {
+        self.ring.lock().written
+    }
+
+    /// Replace `out` with the whole frames in samples `start..end`, leaving out any
+    /// no longer held.
+    pub fn copy(&self, start: usize, end: usize, out: &mut Vec<i16>) {
+        let ring = self.ring.lock();
+        let (capacity, channels) = (ring.samples.len(), ring.channels);
+        let oldest = ring.written.saturating_sub(capacity).next_multiple_of(channels);
+        let end = end.min(ring.written) / channels * channels;
+        let start = start.next_multiple_of(channels).max(oldest).min(end);
+        out.clear();
+        out.extend((start..end).map(|i| ring.samples[i % capacity]));
+    }
+}
+
+/// Turns what the tap has heard into spectrum and level readings.
+pub struct Meter {
+    channels: u16,
+    spectrum: Spectrum,
+    options: AnalysisOptions,
+    /// Tap index the next reading's levels start from.
+    next: usize,
+    block: Vec<i16>,
+}
+
+impl Meter {
+    pub fn new(sample_rate: u32, channels: u16, options: &AnalysisOptions) -> Result<Self, String> {
+        Ok(Self {
+            channels,
+            spectrum: Spectrum::new(sample_rate, options)?,
+            options: options.clone(),
+            next: 0,
+            block: Vec::new(),
+        })
+    }
+
+    pub fn options(&self) -> &AnalysisOptions {
+        &self.options
+    }
+
+    pub fn set_options(&mut self, options: &AnalysisOptions) -> Result<(), String> {
+        self.spectrum = Spectrum::new(self.spectrum.sample_rate, options)?;
+        self.options = options.clone();
+        Ok(())
+    }
+
+    /// Levels of everything heard since the last reading, and the spectrum of the
+    /// moment ending at tap index `heard`. `None` if nothing new has been heard.
+    pub fn read(&mut self, tap: &AnalysisTap, heard: usize) -> Option<(Levels, Vec<f32>)> {
+        let channels = self.channels as usize;
+        let heard = heard / channels * channels;
+        if heard <= self.next {
+            return None;
+        }
+
+        tap.copy(self.next, heard, &mut self.block);
+        let levels = Levels::measure(&self.block, self.channels);
+        let window = self.spectrum.size() * channels;
+        tap.copy(heard.saturating_sub(window), heard, &mut self.block);
+        let spectrum = self.spectrum.compute(&self.block, self.channels);
+        self.next = heard;
+        Some((levels, spectrum))
+    }
+}
@@ -1,91 +1,160 @@
 use parking_lot::Mutex;
-use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
-use std::mem::MaybeUninit;
-use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::ops::Range;
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 use tempfile::TempDir;
-use mp3lame_encoder::{Builder, FlushNoGap, InterleavedPcm};
+
+use crate::analysis::{AnalysisOptions, AnalysisTap, Meter};
+use crate::detection::{Detection, Detector};
+use crate::edits::{Crossfade, EditList, EditedReader, Fade, Timeline};
+use crate::events::{self, ChunkEvent, LoudnessEvent, MeterEvent, PlaybackPositionEvent, PlaybackStateEvent, Throttle};
+use crate::flac::{FlacOptions, FlacWriter};
+use crate::loops::{find_loops, render_loop, smpl_chunk, LoopCandidate, LoopExportOptions, LoopSearch};
+use crate::loudness::{LoudnessMeter, LoudnessReport, Normalization};
+use crate::lyria_ws::GenerationConfig;
+use crate::mixer::{to_samples, Mixer, TrackSettings, VocalTrack};
+use crate::metadata::{append_wav_chunks, now, riff_chunk, Provenance, ProvenanceChange, TrackMetadata};
+use crate::mp3::{Mp3Options, Mp3Writer};
+use crate::opus::{OpusOptions, OpusWriter};
+use crate::pcm_store::{PcmReader, PcmStore};
+use crate::peaks::{PeakCache, Peaks, BASE_FRAMES};
+use crate::playback::{Counting, StreamState, StreamingSource, Tapped};
+use crate::resample::resample_loop;
+use crate::transfer::{samples_to_bytes, wav_header, AudioResource};
+use crate::vorbis::{VorbisOptions, VorbisWriter};
+use crate::wav::{Dither, WavOptions, WavWriter};
+use crate::PromptWeight;
+
+/// Seconds of the most recent audio kept in memory for live playback.
+const LIVE_TAIL_SECONDS: usize = 10;
+/// Interleaved samples moved per read when playing back or exporting.
+const BLOCK_SAMPLES: usize = 48000 * 2;
+/// Widest waveform overview one request can ask for.
+const MAX_PEAK_PIXELS: usize = 16384;
+/// Seconds of played audio the analysis tap keeps for metering.
+const TAP_SECONDS: usize = 1;
+/// Assumed delay between handing samples to the device and hearing them.
+const DEFAULT_OUTPUT_LATENCY_MS: u64 = 50;
 
 pub struct AudioStreamer {
-    temp_dir: TempDir,
-    chunk_files: Vec<PathBuf>,
-    total_samples: usize,
+    pcm: PcmStore,
+    peaks: PeakCache,
+    // Holds the spill file; declared after `pcm` so the file is closed before removal
+    _temp_dir: Arc<TempDir>,
+    chunk_count: usize,
     sample_rate: u32,
     channels: u16,
     is_playing: Arc<AtomicBool>,
-    should_stop: Arc<AtomicBool>,
-    playback_position: Arc<Mutex<f64>>,
+    is_paused: Arc<AtomicBool>,
+    stream: Arc<StreamState>,
+    input_finished: bool,
+    input_paused: bool,
+    /// Interleaved index playback starts from; set by seeking.
+    cue: usize,
+    loop_region: Option<(usize, usize)>,
+    edits: EditList,
+    timeline: Arc<Timeline>,
+    output_latency: Duration,
+    metadata: TrackMetadata,
+    /// Export setting, kept across sessions.
+    normalization: Option<Normalization>,
+    /// Metering setting, kept across sessions and read by the playback thread.
+    analysis: Arc<Mutex<AnalysisOptions>>,
+    /// Vocal tracks mixed into exports, kept across sessions.
+    mixer: Mixer,
     playback_thread: Option<thread::JoinHandle<()>>,
+    chunk_throttle: Throttle,
 }
 
 impl AudioStreamer {
     pub fn new() -> Result<Self, String> {
         let temp_dir = TempDir::new().map_err(|e| format!("Failed to create temp dir: {}", e))?;
         log::info!("Audio streamer temp dir: {:?}", temp_dir.path());
-        
+
+        let sample_rate = 48000;
+        let channels = 2;
+        let pcm = PcmStore::create(temp_dir.path(), LIVE_TAIL_SECONDS * sample_rate as usize * channels as usize)?;
+
         Ok(Self {
-            temp_dir,
-            chunk_files: Vec::new(),
-            total_samples: 0,
-            sample_rate: 48000,
-            channels: 2,
+            pcm,
+            peaks: PeakCache::new(channels),
+            _temp_dir: Arc::new(temp_dir),
+            chunk_count: 0,
+            sample_rate,
+            channels,
             is_playing: Arc::new(AtomicBool::new(false)),
-            should_stop: Arc::new(AtomicBool::new(false)),
-            playback_position: Arc::new(Mutex::new(0.0)),
+            is_paused: Arc::new(AtomicBool::new(false)),
+            stream: Arc::new(StreamState::new(0)),
+            input_finished: false,
+            input_paused: false,
+            cue: 0,
+            loop_region: None,
+            edits: EditList::default(),
+            timeline: Arc::new(Timeline::default()),
+            output_latency: Duration::from_millis(DEFAULT_OUTPUT_LATENCY_MS),
+            metadata: TrackMetadata::default(),
+            normalization: None,
+            analysis: Arc::new(Mutex::new(AnalysisOptions::default())),
+            mixer: Mixer::new(sample_rate),
             playback_thread: None,
+            chunk_throttle: Throttle::new(events::EVENT_INTERVAL),
         })
     }
 
     pub fn write_chunk(&mut self, audio_data: &[i16]) -> Result<usize, String> {
-        let chunk_index = self.chunk_files.len();
-        let chunk_path = self.temp_dir.path().join(format!("chunk_{:04}.wav", chunk_index));
-        
-        let spec = hound::WavSpec {
-            channels: self.channels,
-            sample_rate: self.sample_rate,
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
-        };
-
-        let mut writer = hound::WavWriter::create(&chunk_path, spec)
-            .map_err(|e| format!("Failed to create WAV writer: {}", e))?;
-
-        for sample in audio_data {
-            writer.write_sample(*sample)
-                .map_err(|e| format!("Failed to write sample: {}", e))?;
-        }
-
-        writer.finalize()
-            .map_err(|e| format!("Failed to finalize WAV: {}", e))?;
-
-        self.chunk_files.push(chunk_path);
-        self.total_samples += audio_data.len() / self.channels as usize;
+        let chunk_index = self.chunk_count;
+        self.pcm.append(audio_data)?;
+        self.peaks.add(audio_data);
+        self.chunk_count += 1;
+        self.input_finished = false;
+        self.stream.input_finished.store(false, Ordering::SeqCst);
+        self.input_paused = false;
+        self.stream.input_paused.store(false, Ordering::SeqCst);
 
         if chunk_index % 10 == 0 {
-            log::info!("Wrote chunk {} ({} total samples)", chunk_index, self.total_samples);
+            log::info!("Wrote chunk {} ({} total samples)", chunk_index, self.total_frames());
+        }
+        if self.chunk_throttle.ready() {
+            events::emit(events::CHUNK_RECEIVED, ChunkEvent {
+                chunk_index,
+                samples: audio_data.len() / self.channels as usize,
+                total_samples: self.total_frames(),
+                duration_seconds: self.get_duration(),
+            });
         }
         Ok(chunk_index)
     }
 
+    /// Play from the last seek position, or the start of the session. Playback
+    /// follows chunks written after it starts and only ends once `end_of_stream` has
+    /// been called and everything stored has been played, unless a loop is set.
     pub fn start_playback(&mut self) -> Result<(), String> {
-        if self.chunk_files.is_empty() {
+        if self.pcm.is_empty() {
             return Err("No audio chunks to play".to_string());
         }
 
         self.stop_playback();
 
-        let chunk_files = self.chunk_files.clone();
+        let stream = Arc::new(StreamState::new(self.cue));
+        stream.input_finished.store(self.input_finished, Ordering::SeqCst);
+        stream.input_paused.store(self.input_paused, Ordering::SeqCst);
+        stream.set_loop(self.loop_region);
+        self.stream = stream.clone();
+
+        let tap = Arc::new(AnalysisTap::new(TAP_SECONDS * self.sample_rate as usize, self.channels));
+        let source = StreamingSource::new(self.edited_reader(), stream.clone(), self.channels, self.sample_rate);
+        let source = Counting::new(Tapped::new(source, tap.clone()), stream.clone());
         let is_playing = self.is_playing.clone();
-        let should_stop = self.should_stop.clone();
-        let playback_position = self.playback_position.clone();
-        let sample_rate = self.sample_rate;
+        let is_paused = self.is_paused.clone();
+        let samples_per_second = self.samples_per_second();
+        let latency = self.output_latency;
+        let analysis = self.analysis.clone();
+        let (sample_rate, channels) = (self.sample_rate, self.channels);
 
-        self.should_stop.store(false, Ordering::SeqCst);
+        self.is_paused.store(false, Ordering::SeqCst);
         self.is_playing.store(true, Ordering::SeqCst);
-        *self.playback_position.lock() = 0.0;
 
         let handle = thread::spawn(move || {
             let result = (|| -> Result<(), String> {
@@ -95,35 +164,45 @@ impl AudioStreamer {
                 let sink = rodio::Sink::try_new(&stream_handle)
                     .map_err(|e| format!("Failed to create sink: {}", e))?;
 
-                let mut samples_played: usize = 0;
+                sink.append(source);
 
-                for chunk_path in &chunk_files {
-                    if should_stop.load(Ordering::SeqCst) {
-                        break;
+                let position_throttle = Throttle::new(events::EVENT_INTERVAL);
+                let meter_throttle = Throttle::new(events::METER_INTERVAL);
+                let mut meter = Meter::new(sample_rate, channels, &analysis.lock())?;
+                while !sink.empty() && !stream.stop.load(Ordering::SeqCst) {
+                    // The sink holds its place while paused, so resuming continues from the same sample
+                    let paused = is_paused.load(Ordering::SeqCst);
+                    if paused != sink.is_paused() {
+                        if paused {
+                            sink.pause();
+                        } else {
+                            sink.play();
+                        }
                     }
 
-                    let file = File::open(chunk_path)
-                        .map_err(|e| format!("Failed to open chunk: {}", e))?;
-                    
-                    let reader = hound::WavReader::new(BufReader::new(file))
-                        .map_err(|e| format!("Failed to read WAV: {}", e))?;
-                    
-                    let chunk_samples = reader.len() as usize / 2; // stereo
-                    
-                    let file = File::open(chunk_path)
-                        .map_err(|e| format!("Failed to reopen chunk: {}", e))?;
-                    
-                    let source = rodio::Decoder::new(BufReader::new(file))
-                        .map_err(|e| format!("Failed to decode: {}", e))?;
-                    
-                    sink.append(source);
-                    samples_played += chunk_samples;
-                    
-                    *playback_position.lock() = samples_played as f64 / sample_rate as f64;
-                }
+                    if !paused && position_throttle.ready() {
+                        events::emit(events::PLAYBACK_POSITION, PlaybackPositionEvent {
+                            position: stream.position() as f64 / samples_per_second,
+                            heard_position: stream.heard_position(samples_per_second, latency) as f64 / samples_per_second,
+                        });
+                    }
 
-                while !sink.empty() && !should_stop.load(Ordering::SeqCst) {
-                    thread::sleep(std::time::Duration::from_millis(100));
+                    if !paused && meter_throttle.ready() {
+                        let options = analysis.lock().clone();
+                        if meter.options() != &options {
+                            meter.set_options(&options)?;
+                        }
+                        let heard = stream.heard_samples(samples_per_second, latency).min(tap.written());
+                        let reading = if options.enabled { meter.read(&tap, heard) } else { None };
+                        if let Some((levels, spectrum)) = reading {
+                            events::emit(events::METER, MeterEvent {
+                                position: stream.heard_position(samples_per_second, latency) as f64 / samples_per_second,
+                                levels,
+                                spectrum,
+                            });
+                        }
+                    }
+                    thread::sleep(std::time::Duration::from_millis(20));
                 }
 
                 Ok(())
@@ -133,244 +212,900 @@ impl AudioStreamer {
                 log::error!("Playback error: {}", e);
             }
 
+            let underruns = stream.underruns.load(Ordering::Relaxed);
+            if underruns > 0 {
+                log::info!("Playback had {} underruns", underruns);
+            }
+
             is_playing.store(false, Ordering::SeqCst);
+            events::emit(events::PLAYBACK_STATE, PlaybackStateEvent { is_playing: false, is_paused: false });
             log::info!("Playback finished");
         });
 
         self.playback_thread = Some(handle);
-        log::info!("Started playback of {} chunks", self.chunk_files.len());
+        self.emit_state();
+        log::info!("Started streaming playback ({} chunks buffered)", self.chunk_count);
         Ok(())
     }
 
     pub fn stop_playback(&mut self) {
-        self.should_stop.store(true, Ordering::SeqCst);
+        self.stream.stop.store(true, Ordering::SeqCst);
         
         if let Some(handle) = self.playback_thread.take() {
             let _ = handle.join();
         }
         
         self.is_playing.store(false, Ordering::SeqCst);
+        self.is_paused.store(false, Ordering::SeqCst);
+        self.emit_state();
         log::info!("Stopped playback");
     }
 
+    /// Mark that no more chunks will be written, so playback ends when it runs out
+    /// instead of waiting for more.
+    pub fn end_of_stream(&mut self) {
+        self.input_finished = true;
+        self.stream.input_finished.store(true, Ordering::SeqCst);
+    }
+
+    /// Mark that chunks have stopped for a while but will resume, as while the
+    /// generator reconnects, so playback waits for them however long it takes.
+    /// Cleared by the next chunk.
+    pub fn pause_input(&mut self) {
+        self.input_paused = true;
+        self.stream.input_paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_input_paused(&self) -> bool {
+        self.input_paused
+    }
+
+    /// Times the current playback ran dry and had to insert silence.
+    pub fn get_underruns(&self) -> usize {
+        self.stream.underruns.load(Ordering::Relaxed)
+    }
+
+    fn emit_state(&self) {
+        events::emit(events::PLAYBACK_STATE, PlaybackStateEvent {
+            is_playing: self.is_playing(),
+            is_paused: self.is_paused(),
+        });
+    }
+
+    pub fn pause_playback(&mut self) {
+        if self.is_playing.load(Ordering::SeqCst) {
+            self.is_paused.store(true, Ordering::SeqCst);
+            self.emit_state();
+            log::info!("Paused playback at {:.2}s", self.get_position());
+        }
+    }
+
+    /// Resume paused playback where it left off, or start from the beginning if
+    /// nothing is playing.
+    pub fn resume_playback(&mut self) -> Result<(), String> {
+        if self.is_playing.load(Ordering::SeqCst) {
+            if self.is_paused.swap(false, Ordering::SeqCst) {
+                self.emit_state();
+                log::info!("Resumed playback at {:.2}s", self.get_position());
+            }
+            return Ok(());
+        }
+        self.start_playback()
+    }
+
     pub fn is_playing(&self) -> bool {
-        self.is_playing.load(Ordering::SeqCst)
+        self.is_playing.load(Ordering::SeqCst) && !self.is_paused.load(Ordering::SeqCst)
     }
 
+    pub fn is_paused(&self) -> bool {
+        self.is_paused.load(Ordering::SeqCst)
+    }
+
+    /// Seconds of audio handed to the output device.
     pub fn get_position(&self) -> f64 {
-        *self.playback_position.lock()
+        self.stream.position() as f64 / self.samples_per_second()
+    }
+
+    /// Seconds of audio actually heard, allowing for the output latency. Moves
+    /// smoothly between device callbacks, so it suits the visualizer and transport.
+    pub fn get_heard_position(&self) -> f64 {
+        let samples_per_second = self.samples_per_second();
+        self.stream.heard_position(samples_per_second, self.output_latency) as f64 / samples_per_second
+    }
+
+    pub fn get_output_latency_ms(&self) -> u64 {
+        self.output_latency.as_millis() as u64
+    }
+
+    /// Calibrate the delay subtracted from the heard position.
+    pub fn set_output_latency_ms(&mut self, latency_ms: u64) {
+        self.output_latency = Duration::from_millis(latency_ms);
+    }
+
+    /// Move playback to `seconds`, or cue it for the next start when stopped.
+    pub fn seek(&mut self, seconds: f64) -> Result<(), String> {
+        let offset = self.offset_at(seconds)?;
+        self.cue = offset;
+        if self.is_playing.load(Ordering::SeqCst) {
+            self.stream.seek(offset);
+        } else {
+            let stream = Arc::new(StreamState::new(offset));
+            stream.set_loop(self.loop_region);
+            self.stream = stream;
+        }
+        log::info!("Seeked to {:.2}s", seconds);
+        Ok(())
+    }
+
+    /// Repeat `start..end` seconds once playback reaches `end`. The end may lie
+    /// beyond the audio generated so far.
+    pub fn set_loop(&mut self, start: f64, end: f64) -> Result<(), String> {
+        if !end.is_finite() || end <= start {
+            return Err(format!("Invalid loop region: {:.2}s to {:.2}s", start, end));
+        }
+        let region = (self.offset_at(start)?, self.frame_offset(end));
+        self.loop_region = Some(region);
+        self.stream.set_loop(self.loop_region);
+        log::info!("Looping {:.2}s to {:.2}s", start, end);
+        Ok(())
+    }
+
+    pub fn clear_loop(&mut self) {
+        self.loop_region = None;
+        self.stream.set_loop(None);
+    }
+
+    /// Loop region in seconds.
+    pub fn get_loop(&self) -> Option<(f64, f64)> {
+        let samples_per_second = self.samples_per_second();
+        self.loop_region
+            .map(|(start, end)| (start as f64 / samples_per_second, end as f64 / samples_per_second))
+    }
+
+    /// Interleaved index of the frame at `seconds`, which must lie within the edited audio.
+    fn offset_at(&self, seconds: f64) -> Result<usize, String> {
+        let duration = self.get_edited_duration();
+        if !seconds.is_finite() || seconds < 0.0 || seconds > duration {
+            return Err(format!("Position {:.2}s is outside the audio ({:.2}s)", seconds, duration));
+        }
+        Ok(self.frame_offset(seconds).min(self.edited_reader().available()))
+    }
+
+    fn frame_offset(&self, seconds: f64) -> usize {
+        (seconds * self.sample_rate as f64).round() as usize * self.channels as usize
+    }
+
+    fn samples_per_second(&self) -> f64 {
+        self.sample_rate as f64 * self.channels as f64
+    }
+
+    /// Stereo frames stored so far.
+    fn total_frames(&self) -> usize {
+        self.pcm.len() / self.channels as usize
     }
 
+    /// Seconds recorded.
     pub fn get_duration(&self) -> f64 {
-        self.total_samples as f64 / self.sample_rate as f64
+        self.total_frames() as f64 / self.sample_rate as f64
+    }
+
+    /// Seconds of the session with its edits applied, which is what playback and
+    /// exports cover.
+    pub fn get_edited_duration(&self) -> f64 {
+        self.edited_reader().available() as f64 / self.samples_per_second()
     }
 
     pub fn get_chunk_count(&self) -> usize {
-        self.chunk_files.len()
+        self.chunk_count
+    }
+
+    /// Waveform overview of `start..end` seconds of the recording, `pixels` wide.
+    /// Served from the peak cache, or from the samples when zoomed in past it.
+    pub fn get_peaks(&self, start: f64, end: f64, pixels: usize) -> Result<Peaks, String> {
+        if pixels == 0 || pixels > MAX_PEAK_PIXELS {
+            return Err(format!("Invalid waveform width: {} pixels (expected 1-{})", pixels, MAX_PEAK_PIXELS));
+        }
+        let duration = self.get_duration();
+        if !start.is_finite() || !end.is_finite() || start < 0.0 || end <= start || start >= duration {
+            return Err(format!("Invalid waveform range: {:.2}s to {:.2}s of {:.2}s", start, end, duration));
+        }
+
+        let channels = self.channels as usize;
+        let first = (self.frame_offset(start) / channels).min(self.total_frames() - 1);
+        let last = (self.frame_offset(end.min(duration)) / channels).clamp(first + 1, self.total_frames());
+        if (last - first) / pixels >= BASE_FRAMES {
+            return Ok(self.peaks.peaks(first, last, pixels));
+        }
+
+        let mut samples = vec![0i16; (last - first) * channels];
+        let read = self.pcm.reader().read(first * channels, &mut samples)?;
+        samples.truncate(read);
+        Ok(Peaks::from_samples(&samples, self.channels, pixels))
     }
 
-    /// Get all audio data as interleaved i16 samples for JavaScript playback
+    /// Get all audio data as interleaved i16 samples for JavaScript playback, with
+    /// the edits applied
     pub fn get_all_samples(&self) -> Result<Vec<i16>, String> {
-        if self.chunk_files.is_empty() {
+        if self.pcm.is_empty() {
             return Err("No audio data".to_string());
         }
 
-        let mut all_samples: Vec<i16> = Vec::new();
-        
-        for chunk_path in &self.chunk_files {
-            let mut reader = hound::WavReader::open(chunk_path)
-                .map_err(|e| format!("Failed to open chunk: {}", e))?;
-            
-            for sample in reader.samples::<i16>() {
-                let sample = sample.map_err(|e| format!("Failed to read sample: {}", e))?;
-                all_samples.push(sample);
+        let all_samples = self.edited_samples()?;
+        log::info!("Retrieved {} samples for JS playback", all_samples.len());
+        Ok(all_samples)
+    }
+
+    /// Edited samples from `start` seconds to `end` (or the end of the session) as
+    /// little-endian 16-bit PCM, for binary IPC.
+    pub fn read_pcm(&self, start: f64, end: Option<f64>) -> Result<Vec<u8>, String> {
+        if self.pcm.is_empty() {
+            return Err("No audio data".to_string());
+        }
+        let end = end.unwrap_or_else(|| self.get_edited_duration().max(start));
+        if !start.is_finite() || !end.is_finite() || start < 0.0 || end < start {
+            return Err(format!("Invalid audio range: {:.2}s to {:.2}s", start, end));
+        }
+
+        let (from, to) = (self.frame_offset(start) as u64 * 2, self.frame_offset(end) as u64 * 2);
+        let bytes = self.read_resource(AudioResource::Pcm, from, to)?;
+        log::info!("Read {} bytes of PCM for JS playback", bytes.len());
+        Ok(bytes)
+    }
+
+    /// Size in bytes of the edited session served as `resource`.
+    pub fn resource_len(&self, resource: AudioResource) -> u64 {
+        resource.header_len() + self.edited_reader().available() as u64 * 2
+    }
+
+    /// Bytes `start..end` of the edited session served as `resource`, cut short at
+    /// the end of what has been recorded. A WAV header describes the audio
+    /// recorded at the time of the read.
+    pub fn read_resource(&self, resource: AudioResource, start: u64, end: u64) -> Result<Vec<u8>, String> {
+        let mut reader = self.edited_reader();
+        let header_len = resource.header_len();
+        let data_len = reader.available() as u64 * 2;
+        let end = end.min(header_len + data_len);
+        if start >= end {
+            return Ok(Vec::new());
+        }
+
+        let mut bytes = Vec::with_capacity((end - start) as usize);
+        if start < header_len {
+            let header = wav_header(data_len, self.sample_rate, self.channels);
+            bytes.extend_from_slice(&header[start as usize..end.min(header_len) as usize]);
+        }
+
+        let (from, to) = (start.saturating_sub(header_len), end.saturating_sub(header_len));
+        if to > from {
+            // Read whole frames and keep the bytes asked for
+            let frame_bytes = self.channels as u64 * 2;
+            let first = from / frame_bytes * frame_bytes;
+            let frames = (to - first).div_ceil(frame_bytes);
+            let mut samples = vec![0i16; (frames * frame_bytes / 2) as usize];
+            let read = reader.read((first / 2) as usize, &mut samples, true)?;
+            let data = samples_to_bytes(&samples[..read]);
+            let skip = (from - first) as usize;
+            bytes.extend_from_slice(&data[skip.min(data.len())..((to - first) as usize).min(data.len())]);
+        }
+        Ok(bytes)
+    }
+
+    fn edited_samples(&self) -> Result<Vec<i16>, String> {
+        if self.timeline.is_identity() {
+            return self.pcm.read_all();
+        }
+        let mut samples = Vec::with_capacity(self.pcm.len());
+        self.for_each_block(|block| {
+            samples.extend_from_slice(block);
+            Ok(())
+        })?;
+        Ok(samples)
+    }
+
+    fn edited_reader(&self) -> EditedReader {
+        EditedReader::new(self.pcm.reader(), self.timeline.clone(), self.channels)
+    }
+
+    /// Feed every sample of the edited session to `f` in order, one block at a time.
+    fn for_each_block(&self, mut f: impl FnMut(&[i16]) -> Result<(), String>) -> Result<(), String> {
+        let mut reader = self.edited_reader();
+        let end = reader.available();
+        let mut block = vec![0i16; BLOCK_SAMPLES];
+        let mut offset = 0;
+
+        while offset < end {
+            let want = BLOCK_SAMPLES.min(end - offset);
+            let read = reader.read(offset, &mut block[..want], true)?;
+            if read == 0 {
+                break;
             }
+            f(&block[..read])?;
+            offset += read;
         }
+        Ok(())
+    }
 
-        log::info!("Retrieved {} samples for JS playback", all_samples.len());
-        Ok(all_samples)
+    /// Normalize every export to a loudness target, or export as recorded.
+    pub fn set_normalization(&mut self, normalization: Option<Normalization>) -> Result<(), String> {
+        if let Some(normalization) = &normalization {
+            normalization.validate()?;
+        }
+        self.normalization = normalization;
+        Ok(())
+    }
+
+    pub fn get_normalization(&self) -> Option<&Normalization> {
+        self.normalization.as_ref()
+    }
+
+    /// Configure the spectrum and level meters of native playback. Takes effect
+    /// at the next reading, including during playback.
+    pub fn set_analysis(&mut self, options: AnalysisOptions) -> Result<(), String> {
+        options.validate()?;
+        *self.analysis.lock() = options;
+        Ok(())
+    }
+
+    pub fn get_analysis(&self) -> AnalysisOptions {
+        self.analysis.lock().clone()
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Mix a decoded vocal track into exports, replacing the track with its id
+    /// but keeping that track's settings.
+    pub fn add_vocal_track(&mut self, track: VocalTrack) -> &VocalTrack {
+        log::info!(
+            "Added vocal track {} ({}, {:.1}s from {} Hz, {} channels)",
+            track.id,
+            track.name,
+            track.duration,
+            track.source_sample_rate,
+            track.source_channels
+        );
+        self.mixer.add(track)
+    }
+
+    pub fn set_vocal_track(&mut self, id: &str, settings: TrackSettings) -> Result<(), String> {
+        self.mixer.set(id, settings)
+    }
+
+    pub fn remove_vocal_track(&mut self, id: &str) -> Result<(), String> {
+        self.mixer.remove(id)?;
+        log::info!("Removed vocal track {}", id);
+        Ok(())
+    }
+
+    pub fn get_vocal_tracks(&self) -> &[VocalTrack] {
+        self.mixer.tracks()
     }
 
     pub fn clear(&mut self) {
         self.stop_playback();
-        
-        // Delete temp chunk files
-        for chunk_path in &self.chunk_files {
-            if chunk_path.exists() {
-                if let Err(e) = std::fs::remove_file(chunk_path) {
-                    log::warn!("Failed to delete temp chunk {:?}: {}", chunk_path, e);
-                }
+
+        if let Err(e) = self.pcm.clear() {
+            log::warn!("Failed to clear PCM store: {}", e);
+        }
+
+        self.peaks.clear();
+        self.chunk_count = 0;
+        self.cue = 0;
+        self.loop_region = None;
+        self.edits = EditList::default();
+        self.timeline = Arc::new(Timeline::default());
+        self.stream = Arc::new(StreamState::new(0));
+        self.metadata = TrackMetadata::default();
+        log::info!("Cleared audio streamer");
+    }
+
+    /// Estimate the tempo and key of the edited session and compare them with the
+    /// BPM and scale requested. Kept in the provenance, so exports carry it.
+    pub fn detect_tempo_and_key(&mut self) -> Result<Detection, String> {
+        let mut detector = Detector::new(self.sample_rate, self.channels)?;
+        self.for_each_block(|block| {
+            detector.add(block);
+            Ok(())
+        })?;
+        let mut detection = detector.finish()?;
+        detection.compare(&self.metadata.requested_config());
+        log::info!(
+            "Detected {:?} and {:?} in {:.1}s (requested {:?} BPM, {:?} {:?})",
+            detection.tempo,
+            detection.key,
+            detection.seconds,
+            detection.requested_bpm,
+            detection.requested_key,
+            detection.requested_scale
+        );
+
+        if let Some(provenance) = &mut self.metadata.provenance {
+            provenance.detection = Some(detection.clone());
+        }
+        Ok(detection)
+    }
+
+    /// Best bar-aligned loops in the edited session, at the session BPM unless the
+    /// search names one.
+    pub fn find_loops(&self, search: &LoopSearch) -> Result<Vec<LoopCandidate>, String> {
+        let bpm = search
+            .bpm
+            .or_else(|| self.metadata.bpm().map(f64::from))
+            .ok_or_else(|| "Set the session BPM to find loops".to_string())?;
+        let candidates = find_loops(&self.edited_samples()?, self.channels, self.sample_rate, bpm, search)?;
+        log::info!("Found {} loop candidates of {} bars at {} BPM", candidates.len(), search.bars, bpm);
+        Ok(candidates)
+    }
+
+    pub fn get_edits(&self) -> &EditList {
+        &self.edits
+    }
+
+    /// Replace the edits applied to playback and export. Playback restarts on the
+    /// new timeline at the same position, and the loop, which was placed on the old
+    /// timeline, is cleared.
+    pub fn set_edits(&mut self, edits: EditList) -> Result<(), String> {
+        let timeline = Timeline::new(&edits, self.sample_rate)?;
+        let was_playing = self.is_playing.load(Ordering::SeqCst);
+        let was_paused = self.is_paused();
+        let position = self.stream.position();
+        if was_playing {
+            self.stop_playback();
+        }
+
+        self.edits = edits;
+        self.timeline = Arc::new(timeline);
+        self.loop_region = None;
+        self.cue = position.min(self.edited_reader().available());
+        self.stream = Arc::new(StreamState::new(self.cue));
+        log::info!("Set edits: {:?}", self.edits);
+
+        if was_playing {
+            self.start_playback()?;
+            if was_paused {
+                self.pause_playback();
             }
         }
-        
-        self.chunk_files.clear();
-        self.total_samples = 0;
-        *self.playback_position.lock() = 0.0;
-        log::info!("Cleared audio streamer (deleted temp files)");
+        Ok(())
+    }
+
+    /// Keep `start..end` seconds of the recording, or from `start` onwards.
+    pub fn set_trim(&mut self, start: f64, end: Option<f64>) -> Result<(), String> {
+        let edits = EditList {
+            trim_start: start,
+            trim_end: end,
+            ..self.edits.clone()
+        };
+        self.set_edits(edits)
+    }
+
+    pub fn set_fade_in(&mut self, fade: Option<Fade>) -> Result<(), String> {
+        let edits = EditList {
+            fade_in: fade,
+            ..self.edits.clone()
+        };
+        self.set_edits(edits)
+    }
+
+    pub fn set_fade_out(&mut self, fade: Option<Fade>) -> Result<(), String> {
+        let edits = EditList {
+            fade_out: fade,
+            ..self.edits.clone()
+        };
+        self.set_edits(edits)
+    }
+
+    pub fn add_crossfade(&mut self, crossfade: Crossfade) -> Result<(), String> {
+        let mut edits = self.edits.clone();
+        edits.crossfades.push(crossfade);
+        self.set_edits(edits)
+    }
+
+    pub fn clear_edits(&mut self) -> Result<(), String> {
+        self.set_edits(EditList::default())
+    }
+
+    pub fn get_metadata(&self) -> &TrackMetadata {
+        &self.metadata
+    }
+
+    /// Replace the tags written into exports. Provenance recorded by a native
+    /// session is kept unless `metadata` brings its own.
+    pub fn set_metadata(&mut self, mut metadata: TrackMetadata) {
+        match &mut metadata.provenance {
+            Some(provenance) if provenance.generated_at.is_empty() => {
+                provenance.generated_at = now();
+            }
+            Some(_) => {}
+            None => metadata.provenance = self.metadata.provenance.take(),
+        }
+        self.metadata = metadata;
+    }
+
+    pub fn set_provenance(&mut self, provenance: Provenance) {
+        self.metadata.provenance = Some(provenance);
+    }
+
+    /// Record steering at the current end of the recording.
+    pub fn record_provenance_change(&mut self, prompts: Option<Vec<PromptWeight>>, config: Option<GenerationConfig>) {
+        let at_seconds = self.get_duration();
+        if let Some(provenance) = &mut self.metadata.provenance {
+            provenance.changes.push(ProvenanceChange { at_seconds, prompts, config });
+        }
+    }
+
+    /// Copy what an export reads, so it can run once the streamer lock is
+    /// released. Audio written after this call is not exported.
+    pub fn export_snapshot(&self) -> ExportSnapshot {
+        ExportSnapshot {
+            pcm: self.pcm.snapshot(),
+            _temp_dir: Arc::clone(&self._temp_dir),
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            timeline: self.timeline.clone(),
+            metadata: self.metadata.clone(),
+            normalization: self.normalization.clone(),
+            mixer: self.mixer.clone(),
+        }
+    }
+
+    // The commands export from a snapshot; these run one under the caller's lock.
+
+    pub fn measure_loudness(&self) -> Result<LoudnessReport, String> {
+        self.export_snapshot().measure_loudness()
+    }
+
+    pub fn export_loop(
+        &self,
+        output_path: &str,
+        start: f64,
+        end: f64,
+        options: &LoopExportOptions,
+        wav: &WavOptions,
+    ) -> Result<(), String> {
+        self.export_snapshot().export_loop(output_path, start, end, options, wav)
     }
 
+    pub fn export_to_file(&self, output_path: &str) -> Result<(), String> {
+        self.export_snapshot().export_to_file(output_path)
+    }
+
+    pub fn export_to_file_with_format(&self, output_path: &str, format: &str, bitrate: u32) -> Result<(), String> {
+        self.export_snapshot().export_to_file_with_format(output_path, format, bitrate)
+    }
+
+    pub fn export_to_wav(&self, output_path: &str, options: &WavOptions) -> Result<(), String> {
+        self.export_snapshot().export_to_wav(output_path, options)
+    }
+
+    pub fn export_to_mp3(&self, output_path: &str, options: &Mp3Options) -> Result<(), String> {
+        self.export_snapshot().export_to_mp3(output_path, options)
+    }
+
+    pub fn export_to_flac(&self, output_path: &str, options: &FlacOptions) -> Result<(), String> {
+        self.export_snapshot().export_to_flac(output_path, options)
+    }
+
+    pub fn export_to_opus(&self, output_path: &str, options: &OpusOptions) -> Result<(), String> {
+        self.export_snapshot().export_to_opus(output_path, options)
+    }
+
+    pub fn export_to_vorbis(&self, output_path: &str, options: &VorbisOptions) -> Result<(), String> {
+        self.export_snapshot().export_to_vorbis(output_path, options)
+    }
+}
+
+/// The session as an export sees it: the samples stored when the snapshot was
+/// taken, with the edits, tags, normalization and vocal tracks in force then.
+pub struct ExportSnapshot {
+    pcm: PcmReader,
+    // Keeps the spill file should the streamer be replaced mid-export; declared
+    // after `pcm` so the file is closed before removal
+    _temp_dir: Arc<TempDir>,
+    sample_rate: u32,
+    channels: u16,
+    timeline: Arc<Timeline>,
+    metadata: TrackMetadata,
+    normalization: Option<Normalization>,
+    mixer: Mixer,
+}
+
+impl ExportSnapshot {
+    fn edited_reader(&self) -> EditedReader {
+        EditedReader::new(self.pcm.clone(), self.timeline.clone(), self.channels)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pcm.available() == 0
+    }
+
+    /// Interleaved index of the frame at `seconds`, which must lie within the edited audio.
+    fn offset_at(&self, seconds: f64) -> Result<usize, String> {
+        let available = self.edited_reader().available();
+        let duration = available as f64 / (self.sample_rate as f64 * self.channels as f64);
+        if !seconds.is_finite() || seconds < 0.0 || seconds > duration {
+            return Err(format!("Position {:.2}s is outside the audio ({:.2}s)", seconds, duration));
+        }
+        let frame = (seconds * self.sample_rate as f64).round() as usize;
+        Ok((frame * self.channels as usize).min(available))
+    }
+
+    /// Like `for_each_mixed_block`, rounded to samples and with the export
+    /// normalization applied.
+    fn for_each_export_block(&self, mut f: impl FnMut(&[i16]) -> Result<(), String>) -> Result<(), String> {
+        let mut rounded = Vec::with_capacity(BLOCK_SAMPLES);
+        self.for_each_mixed_block(self.export_gain()?, |block| {
+            to_samples(block, &mut rounded);
+            f(&rounded)
+        })
+    }
+
+    /// Gain the export normalization calls for, in dB.
+    fn export_gain(&self) -> Result<f64, String> {
+        Ok(match &self.normalization {
+            Some(normalization) => self.normalization_gain(normalization, self.measure_loudness()?),
+            None => 0.0,
+        })
+    }
+
+    /// Feed the edited session to `f` in order, one block at a time, with the
+    /// vocal tracks mixed in and everything scaled by `gain_db`, unrounded. Runs
+    /// on past the end of the session while a track is still playing.
+    fn for_each_mixed_block(&self, gain_db: f64, f: impl FnMut(&[f32]) -> Result<(), String>) -> Result<(), String> {
+        self.for_each_mixed_block_in(0..usize::MAX, gain_db, f)
+    }
+
+    /// Like `for_each_mixed_block`, for only the frames in `frames`.
+    fn for_each_mixed_block_in(
+        &self,
+        frames: Range<usize>,
+        gain_db: f64,
+        mut f: impl FnMut(&[f32]) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let channels = self.channels as usize;
+        let mut reader = self.edited_reader();
+        let mut session_end = reader.available() / channels;
+        let end = frames.end.min(session_end.max(self.mixer.end_frame()));
+        let mut bed = vec![0i16; BLOCK_SAMPLES];
+        let mut mixed = Vec::with_capacity(BLOCK_SAMPLES);
+        let mut frame = frames.start;
+
+        while frame < end {
+            let want = (BLOCK_SAMPLES / channels).min(end - frame);
+            let mut read = 0;
+            if frame < session_end {
+                let len = want.min(session_end - frame) * channels;
+                read = reader.read(frame * channels, &mut bed[..len], true)? / channels;
+            }
+            if read == 0 {
+                // Past the end of the session, a track is still playing over silence
+                session_end = frame;
+                bed[..want * channels].fill(0);
+                read = want;
+            }
+            self.mixer.mix(frame, &bed[..read * channels], gain_db, &mut mixed);
+            frame += read;
+            f(&mixed)?;
+        }
+        Ok(())
+    }
+
+    /// Gain that normalizes audio measured as `report`, announced with the loudness event.
+    fn normalization_gain(&self, normalization: &Normalization, report: LoudnessReport) -> f64 {
+        let result = normalization.apply_to(&report);
+        log::info!(
+            "Normalizing export by {:.2} dB to {:?} LUFS, {:?} dBTP",
+            result.gain_db,
+            result.integrated_lufs,
+            result.true_peak_dbtp
+        );
+        let gain_db = result.gain_db;
+        events::emit(events::LOUDNESS, LoudnessEvent {
+            report,
+            normalization: Some(result),
+        });
+        gain_db
+    }
+
+    /// Measure the loudness of everything recorded so far, as it would be exported
+    /// before normalization.
+    pub fn measure_loudness(&self) -> Result<LoudnessReport, String> {
+        let mut meter = LoudnessMeter::new(self.sample_rate, self.channels)?;
+        let mut rounded = Vec::with_capacity(BLOCK_SAMPLES);
+        self.for_each_mixed_block(0.0, |block| {
+            to_samples(block, &mut rounded);
+            meter.add_samples(&rounded)
+        })?;
+        meter.finish()
+    }
+
+    /// Export `start..end` seconds of the edited session as a WAV loop with a
+    /// treated seam and a `smpl` chunk marking the loop points. The vocal tracks,
+    /// normalization and `wav` format apply as in `export_to_wav`.
+    pub fn export_loop(
+        &self,
+        output_path: &str,
+        start: f64,
+        end: f64,
+        options: &LoopExportOptions,
+        wav: &WavOptions,
+    ) -> Result<(), String> {
+        if end <= start {
+            return Err(format!("Invalid loop region: {:.2}s to {:.2}s", start, end));
+        }
+        wav.validate()?;
+        let channels = self.channels as usize;
+        let (start, end) = (self.offset_at(start)? / channels, self.offset_at(end)? / channels);
+        // Only the loop and the audio its seam can reach are rendered
+        let seam = options.seam_frames(self.sample_rate)?;
+        let (first, last) = (start.saturating_sub(seam), end + seam);
+        let mut region = Vec::with_capacity((last - first) * channels);
+        self.for_each_mixed_block_in(first..last, 0.0, |block| {
+            region.extend_from_slice(block);
+            Ok(())
+        })?;
+        let mut samples = render_loop(&region, self.channels, self.sample_rate, start - first, end - first, options)?;
+
+        let gain_db = match &self.normalization {
+            Some(normalization) => {
+                let mut meter = LoudnessMeter::new(self.sample_rate, self.channels)?;
+                let mut rounded = Vec::with_capacity(samples.len());
+                to_samples(&samples, &mut rounded);
+                meter.add_samples(&rounded)?;
+                self.normalization_gain(normalization, meter.finish()?)
+            }
+            None => 0.0,
+        };
+        let gain = 10f64.powf(gain_db / 20.0) as f32;
+        samples.iter_mut().for_each(|s| *s *= gain);
+        // Resampled as a loop here rather than by the writer, which would fade the ends
+        let looped = resample_loop(&samples, self.sample_rate, wav.sample_rate, self.channels)?;
+
+        let wav = &self.wav_export_options(gain_db, wav);
+        let mut writer = WavWriter::create(output_path, wav.sample_rate, self.channels, wav)?;
+        writer.write_samples(&looped)?;
+        writer.finalize()?;
+
+        let frames = looped.len() / channels;
+        append_wav_chunks(output_path, &riff_chunk(b"smpl", &smpl_chunk(frames, wav.sample_rate)))?;
+        if !self.metadata.is_empty() {
+            self.metadata.append_to_wav(output_path)?;
+        }
+
+        log::info!("Exported {:.2}s loop ({:?} seam) to WAV: {}", frames as f64 / wav.sample_rate as f64, options.seam, output_path);
+        Ok(())
+    }
     pub fn export_to_file(&self, output_path: &str) -> Result<(), String> {
         self.export_to_file_with_format(output_path, "wav", 320)
     }
 
     pub fn export_to_file_with_format(&self, output_path: &str, format: &str, bitrate: u32) -> Result<(), String> {
-        if self.chunk_files.is_empty() {
+        if self.is_empty() {
             return Err("No audio to export".to_string());
         }
 
         match format {
-            "mp3" => self.export_to_mp3(output_path, bitrate),
-            "flac" => self.export_to_flac(output_path),
-            _ => self.export_to_wav(output_path),
+            "mp3" => self.export_to_mp3(output_path, &Mp3Options {
+                bitrate_kbps: bitrate,
+                ..Default::default()
+            }),
+            "flac" => self.export_to_flac(output_path, &FlacOptions::default()),
+            "opus" => self.export_to_opus(output_path, &OpusOptions {
+                bitrate_kbps: bitrate,
+                ..Default::default()
+            }),
+            "ogg" | "vorbis" => self.export_to_vorbis(output_path, &VorbisOptions {
+                quality: None,
+                bitrate_kbps: Some(bitrate),
+                ..Default::default()
+            }),
+            _ => self.export_to_wav(output_path, &WavOptions::default()),
         }
     }
 
-    fn export_to_wav(&self, output_path: &str) -> Result<(), String> {
-        let spec = hound::WavSpec {
-            channels: self.channels,
-            sample_rate: self.sample_rate,
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
-        };
+    /// `options` for an export scaled by `gain_db`. Samples the session holds
+    /// unchanged are already exact at 16 bits, so they are never dithered.
+    fn wav_export_options(&self, gain_db: f64, options: &WavOptions) -> WavOptions {
+        let untouched = gain_db == 0.0 && !self.mixer.has_audible_tracks() && options.sample_rate == self.sample_rate;
+        WavOptions {
+            dither: if untouched { Dither::None } else { options.dither },
+            ..options.clone()
+        }
+    }
 
-        let mut writer = hound::WavWriter::create(output_path, spec)
-            .map_err(|e| format!("Failed to create output file: {}", e))?;
-
-        for chunk_path in &self.chunk_files {
-            let mut reader = hound::WavReader::open(chunk_path)
-                .map_err(|e| format!("Failed to open chunk: {}", e))?;
-            
-            for sample in reader.samples::<i16>() {
-                let sample = sample.map_err(|e| format!("Failed to read sample: {}", e))?;
-                writer.write_sample(sample)
-                    .map_err(|e| format!("Failed to write sample: {}", e))?;
-            }
+    /// Export as WAV at the rate and bit depth asked for.
+    pub fn export_to_wav(&self, output_path: &str, options: &WavOptions) -> Result<(), String> {
+        if self.is_empty() {
+            return Err("No audio to export".to_string());
         }
 
-        writer.finalize()
-            .map_err(|e| format!("Failed to finalize output: {}", e))?;
+        let gain_db = self.export_gain()?;
+        let options = &self.wav_export_options(gain_db, options);
+        let mut writer = WavWriter::create(output_path, self.sample_rate, self.channels, options)?;
+        self.for_each_mixed_block(gain_db, |block| writer.write_samples(block))?;
+        writer.finalize()?;
+        if !self.metadata.is_empty() {
+            self.metadata.append_to_wav(output_path)?;
+        }
 
-        log::info!("Exported audio to WAV: {}", output_path);
+        log::info!(
+            "Exported audio to WAV ({} Hz, {}-bit{}, {:?} dither): {}",
+            options.sample_rate,
+            options.bits_per_sample,
+            if options.is_float() { " float" } else { "" },
+            options.dither,
+            output_path
+        );
         Ok(())
     }
 
-    fn export_to_mp3(&self, output_path: &str, bitrate: u32) -> Result<(), String> {
-        // Collect all samples first
-        let mut all_samples: Vec<i16> = Vec::new();
-        
-        for chunk_path in &self.chunk_files {
-            let mut reader = hound::WavReader::open(chunk_path)
-                .map_err(|e| format!("Failed to open chunk: {}", e))?;
-            
-            for sample in reader.samples::<i16>() {
-                let sample = sample.map_err(|e| format!("Failed to read sample: {}", e))?;
-                all_samples.push(sample);
-            }
+    pub fn export_to_mp3(&self, output_path: &str, options: &Mp3Options) -> Result<(), String> {
+        if self.is_empty() {
+            return Err("No audio to export".to_string());
         }
 
-        log::info!("Encoding {} samples to MP3 at {}kbps", all_samples.len(), bitrate);
-
-        // Build MP3 encoder
-        let mut mp3_encoder = Builder::new()
-            .ok_or("Failed to create MP3 encoder builder")?;
-        
-        mp3_encoder.set_num_channels(self.channels as u8)
-            .map_err(|e| format!("Failed to set channels: {:?}", e))?;
-        mp3_encoder.set_sample_rate(self.sample_rate)
-            .map_err(|e| format!("Failed to set sample rate: {:?}", e))?;
-        
-        // Set bitrate based on parameter
-        let brate = if bitrate >= 320 {
-            mp3lame_encoder::Bitrate::Kbps320
-        } else if bitrate >= 256 {
-            mp3lame_encoder::Bitrate::Kbps256
-        } else if bitrate >= 192 {
-            mp3lame_encoder::Bitrate::Kbps192
-        } else {
-            mp3lame_encoder::Bitrate::Kbps128
-        };
-        mp3_encoder.set_brate(brate)
-            .map_err(|e| format!("Failed to set bitrate: {:?}", e))?;
-        mp3_encoder.set_quality(mp3lame_encoder::Quality::Best)
-            .map_err(|e| format!("Failed to set quality: {:?}", e))?;
-
-        let mut encoder = mp3_encoder.build()
-            .map_err(|e| format!("Failed to build MP3 encoder: {:?}", e))?;
-
-        // Create output file
-        let output_file = File::create(output_path)
-            .map_err(|e| format!("Failed to create MP3 file: {}", e))?;
-        let mut writer = BufWriter::new(output_file);
-
-        // Create MaybeUninit buffer as required by mp3lame_encoder
-        let input = InterleavedPcm(&all_samples);
-        let buffer_size = mp3lame_encoder::max_required_buffer_size(all_samples.len());
-        let mut mp3_buffer: Vec<MaybeUninit<u8>> = vec![MaybeUninit::uninit(); buffer_size];
-
-        let encoded_size = encoder.encode(input, &mut mp3_buffer)
-            .map_err(|e| format!("Failed to encode MP3: {:?}", e))?;
-
-        // Convert MaybeUninit buffer to regular bytes for writing (only initialized portion)
-        let encoded_bytes: Vec<u8> = mp3_buffer[..encoded_size]
-            .iter()
-            .map(|m| unsafe { m.assume_init() })
-            .collect();
-        
-        writer.write_all(&encoded_bytes)
-            .map_err(|e| format!("Failed to write MP3 data: {}", e))?;
+        let mut writer = Mp3Writer::create(output_path, self.sample_rate, self.channels, options, &self.metadata)?;
+        self.for_each_export_block(|block| writer.write_samples(block))?;
+        writer.finalize()?;
 
-        // Flush encoder
-        let flush_size = encoder.flush::<FlushNoGap>(&mut mp3_buffer)
-            .map_err(|e| format!("Failed to flush MP3 encoder: {:?}", e))?;
+        log::info!("Exported audio to MP3 ({:?}): {}", options.mode, output_path);
+        Ok(())
+    }
 
-        if flush_size > 0 {
-            let flush_bytes: Vec<u8> = mp3_buffer[..flush_size]
-                .iter()
-                .map(|m| unsafe { m.assume_init() })
-                .collect();
-            writer.write_all(&flush_bytes)
-                .map_err(|e| format!("Failed to write final MP3 data: {}", e))?;
+    pub fn export_to_flac(&self, output_path: &str, options: &FlacOptions) -> Result<(), String> {
+        if self.is_empty() {
+            return Err("No audio to export".to_string());
         }
 
-        writer.flush()
-            .map_err(|e| format!("Failed to flush MP3 writer: {}", e))?;
+        let options = &FlacOptions {
+            tags: self.metadata.vorbis_comments(&options.tags)?,
+            ..options.clone()
+        };
+        let mut writer = FlacWriter::create(output_path, self.sample_rate, self.channels, options)?;
+        self.for_each_export_block(|block| writer.write_samples(block))?;
+        writer.finalize()?;
 
-        log::info!("Exported audio to MP3: {}", output_path);
+        log::info!(
+            "Exported audio to FLAC ({}-bit, level {}): {}",
+            options.bits_per_sample,
+            options.compression_level,
+            output_path
+        );
         Ok(())
     }
 
-    fn export_to_flac(&self, output_path: &str) -> Result<(), String> {
-        // For FLAC, we'll create a 24-bit WAV as a high-quality lossless alternative
-        // True FLAC encoding would require the flacenc crate which has complex setup
-        // For now, export as 24-bit WAV with .flac extension (user can convert)
-        
-        let spec = hound::WavSpec {
-            channels: self.channels,
-            sample_rate: self.sample_rate,
-            bits_per_sample: 24,
-            sample_format: hound::SampleFormat::Int,
+    pub fn export_to_opus(&self, output_path: &str, options: &OpusOptions) -> Result<(), String> {
+        if self.is_empty() {
+            return Err("No audio to export".to_string());
+        }
+
+        let options = &OpusOptions {
+            tags: self.metadata.vorbis_comments(&options.tags)?,
+            ..options.clone()
         };
+        let mut writer = OpusWriter::create(output_path, self.sample_rate, self.channels, options)?;
+        self.for_each_export_block(|block| writer.write_samples(block))?;
+        writer.finalize()?;
 
-        let mut writer = hound::WavWriter::create(output_path, spec)
-            .map_err(|e| format!("Failed to create output file: {}", e))?;
-
-        for chunk_path in &self.chunk_files {
-            let mut reader = hound::WavReader::open(chunk_path)
-                .map_err(|e| format!("Failed to open chunk: {}", e))?;
-            
-            for sample in reader.samples::<i16>() {
-                let sample = sample.map_err(|e| format!("Failed to read sample: {}", e))?;
-                // Convert 16-bit to 24-bit (scale up)
-                let sample_24bit: i32 = (sample as i32) << 8;
-                writer.write_sample(sample_24bit)
-                    .map_err(|e| format!("Failed to write sample: {}", e))?;
-            }
+        log::info!("Exported audio to Opus at {}kbps: {}", options.bitrate_kbps, output_path);
+        Ok(())
+    }
+
+    pub fn export_to_vorbis(&self, output_path: &str, options: &VorbisOptions) -> Result<(), String> {
+        if self.is_empty() {
+            return Err("No audio to export".to_string());
         }
 
-        writer.finalize()
-            .map_err(|e| format!("Failed to finalize output: {}", e))?;
+        let options = &VorbisOptions {
+            tags: self.metadata.vorbis_comments(&options.tags)?,
+            ..options.clone()
+        };
+        let mut writer = VorbisWriter::create(output_path, self.sample_rate, self.channels, options)?;
+        self.for_each_export_block(|block| writer.write_samples(block))?;
+        writer.finalize()?;
 
-        log::info!("Exported audio to FLAC (24-bit WAV): {}", output_path);
+        log::info!("Exported audio to Ogg Vorbis: {}", output_path);
         Ok(())
     }
 }
@@ -381,7 +1116,18 @@ static AUDIO_STREAMER: std::sync::OnceLock<Mutex<Option<AudioStreamer>>> = std::
 pub fn init_streamer() -> Result<(), String> {
     let mutex = AUDIO_STREAMER.get_or_init(|| Mutex::new(None));
     let mut guard = mutex.lock();
-    *guard = Some(AudioStreamer::new()?);
+    let mut previous = guard.take();
+    if let Some(previous) = &mut previous {
+        // Its playback thread would otherwise keep playing the old session
+        previous.stop_playback();
+    }
+    let mut streamer = AudioStreamer::new()?;
+    if let Some(previous) = previous {
+        streamer.normalization = previous.normalization;
+        streamer.analysis = previous.analysis;
+        streamer.mixer = previous.mixer;
+    }
+    *guard = Some(streamer);
     Ok(())
 }
 
@@ -0,0 +1,369 @@
+//! Tempo and key estimation of recorded audio, to check generations against the
+//! BPM and scale they were asked for.
+//!
+//! The audio is mixed to mono and decimated to about 12 kHz as it streams in.
+//! Tempo comes from an onset envelope (spectral flux of short frames) whose
+//! autocorrelation peaks at the beat period. Key comes from a chroma profile
+//! (long-frame spectrum folded into pitch classes) correlated with the
+//! Krumhansl-Kessler major and minor key profiles.
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::lyria_ws::{lyria_scale, GenerationConfig};
+use crate::metadata::now;
+
+/// Rate the audio is decimated to before analysis, roughly.
+const ANALYSIS_RATE: u32 = 12_000;
+/// Onset frames and their spacing, in decimated samples.
+const ONSET_FRAME: usize = 512;
+const ONSET_HOP: usize = 128;
+/// Chroma frames and their spacing, in decimated samples.
+const CHROMA_FRAME: usize = 4096;
+const CHROMA_HOP: usize = 2048;
+/// Pitches folded into the chroma profile, from A1 to about B6.
+const CHROMA_LOW_HZ: f64 = 55.0;
+const CHROMA_HIGH_HZ: f64 = 2000.0;
+/// Tempo range searched; also the range Lyria accepts.
+const MIN_BPM: f64 = 60.0;
+const MAX_BPM: f64 = 200.0;
+/// Seconds either side of each onset frame averaged to find its background level.
+const ONSET_BACKGROUND_SECONDS: f64 = 0.25;
+/// Least audio worth analysing.
+const MIN_SECONDS: f64 = 5.0;
+/// How far a detected tempo may be from the request and still match, as a fraction.
+const TEMPO_TOLERANCE: f64 = 0.04;
+
+const PITCH_NAMES: [&str; 12] = ["C", "C#", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B"];
+/// Krumhansl-Kessler probe-tone profiles, from the tonic up.
+const MAJOR_PROFILE: [f64; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+const MINOR_PROFILE: [f64; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TempoEstimate {
+    pub bpm: f64,
+    /// Autocorrelation of the onset envelope at the beat period, 0-1.
+    pub confidence: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyMode {
+    Major,
+    Minor,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyEstimate {
+    /// Tonic, e.g. "F#".
+    pub key: String,
+    pub mode: KeyMode,
+    /// Correlation of the chroma profile with the key's profile, 0-1.
+    pub confidence: f64,
+}
+
+impl KeyEstimate {
+    fn scale(&self) -> &'static str {
+        match self.mode {
+            KeyMode::Major => "major",
+            KeyMode::Minor => "minor",
+        }
+    }
+}
+
+/// Estimated tempo and key of a session next to what was requested. The matches
+/// are unset when either side is unknown.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Detection {
+    pub tempo: Option<TempoEstimate>,
+    pub key: Option<KeyEstimate>,
+    /// Seconds of audio analysed.
+    pub seconds: f64,
+    pub requested_bpm: Option<u32>,
+    pub requested_key: Option<String>,
+    pub requested_scale: Option<String>,
+    /// Within 4% of the requested BPM, allowing for half or double time.
+    pub tempo_matches: Option<bool>,
+    /// Same major/relative-minor pair as the requested scale, which is all Lyria
+    /// is told.
+    pub key_matches: Option<bool>,
+    /// RFC 3339 UTC time of the analysis.
+    pub detected_at: String,
+}
+
+impl Detection {
+    /// Record `config`'s BPM and scale and whether the estimates match them.
+    pub fn compare(&mut self, config: &GenerationConfig) {
+        self.requested_bpm = config.bpm;
+        self.requested_key = config.key.clone();
+        self.requested_scale = config.scale.clone();
+
+        self.tempo_matches = match (&self.tempo, config.bpm) {
+            (Some(tempo), Some(bpm)) => Some([1.0, 2.0, 0.5].iter().any(|ratio| {
+                (tempo.bpm * ratio - bpm as f64).abs() <= bpm as f64 * TEMPO_TOLERANCE
+            })),
+            _ => None,
+        };
+        let requested = lyria_scale(config.key.as_deref(), config.scale.as_deref());
+        self.key_matches = match (&self.key, requested) {
+            (Some(key), Some(requested)) => Some(lyria_scale(Some(&key.key), Some(key.scale())) == Some(requested)),
+            _ => None,
+        };
+    }
+}
+
+/// Accumulates a session block by block and estimates its tempo and key.
+pub struct Detector {
+    channels: usize,
+    /// Input frames averaged into each analysis sample.
+    decimation: usize,
+    rate: f64,
+    /// Sum of the input frames towards the next analysis sample.
+    partial: f32,
+    partial_frames: usize,
+    /// Analysis samples not yet consumed by both kinds of frame, from `pending_start`.
+    pending: Vec<f32>,
+    pending_start: usize,
+    next_onset: usize,
+    next_chroma: usize,
+    onset: Frames,
+    chroma: Frames,
+    previous_spectrum: Vec<f32>,
+    flux: Vec<f32>,
+    chroma_sum: [f64; 12],
+    /// Pitch class of each chroma bin, if it is in range.
+    pitch_classes: Vec<Option<usize>>,
+    frames_seen: usize,
+    input_rate: u32,
+}
+
+/// A windowed FFT of one frame size.
+struct Frames {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Frames {
+    fn new(planner: &mut FftPlanner<f32>, size: usize) -> Self {
+        let fft = planner.plan_fft_forward(size);
+        Self {
+            window: (0..size)
+                .map(|i| (0.5 - 0.5 * (std::f64::consts::TAU * i as f64 / size as f64).cos()) as f32)
+                .collect(),
+            buffer: vec![Complex::default(); size],
+            scratch: vec![Complex::default(); fft.get_inplace_scratch_len()],
+            fft,
+        }
+    }
+
+    /// Magnitudes of the first half of the spectrum of `samples`.
+    fn magnitudes(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        for ((b, &s), &w) in self.buffer.iter_mut().zip(samples).zip(&self.window) {
+            *b = Complex::new(s * w, 0.0);
+        }
+        self.fft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+        out.clear();
+        out.extend(self.buffer[..self.buffer.len() / 2].iter().map(|c| c.norm()));
+    }
+}
+
+impl Detector {
+    pub fn new(sample_rate: u32, channels: u16) -> Result<Self, String> {
+        if sample_rate < ANALYSIS_RATE || channels == 0 {
+            return Err(format!("Cannot detect tempo and key at {} Hz with {} channels", sample_rate, channels));
+        }
+        let decimation = (sample_rate / ANALYSIS_RATE) as usize;
+        let rate = sample_rate as f64 / decimation as f64;
+        let pitch_classes = (0..CHROMA_FRAME / 2)
+            .map(|bin| {
+                let hz = bin as f64 * rate / CHROMA_FRAME as f64;
+                (CHROMA_LOW_HZ..CHROMA_HIGH_HZ)
+                    .contains(&hz)
+                    .then(|| (12.0 * (hz / 440.0).log2() + 69.0).round() as usize % 12)
+            })
+            .collect();
+
+        let mut planner = FftPlanner::new();
+        Ok(Self {
+            channels: channels as usize,
+            decimation,
+            rate,
+            partial: 0.0,
+            partial_frames: 0,
+            pending: Vec::new(),
+            pending_start: 0,
+            next_onset: 0,
+            next_chroma: 0,
+            onset: Frames::new(&mut planner, ONSET_FRAME),
+            chroma: Frames::new(&mut planner, CHROMA_FRAME),
+            previous_spectrum: vec![0.0; ONSET_FRAME / 2],
+            flux: Vec::new(),
+            chroma_sum: [0.0; 12],
+            pitch_classes,
+            frames_seen: 0,
+            input_rate: sample_rate,
+        })
+    }
+
+    /// Add interleaved samples.
+    pub fn add(&mut self, samples: &[i16]) {
+        for frame in samples.chunks_exact(self.channels) {
+            self.partial += frame.iter().map(|&s| s as f32).sum::<f32>() / (self.channels as f32 * 32768.0);
+            self.partial_frames += 1;
+            if self.partial_frames == self.decimation {
+                self.pending.push(self.partial / self.decimation as f32);
+                self.partial = 0.0;
+                self.partial_frames = 0;
+            }
+        }
+        self.frames_seen += samples.len() / self.channels;
+        self.process();
+    }
+
+    /// Run every frame that is complete, then drop samples no frame still needs.
+    fn process(&mut self) {
+        let end = self.pending_start + self.pending.len();
+        let mut spectrum = Vec::with_capacity(CHROMA_FRAME / 2);
+
+        while self.next_onset + ONSET_FRAME <= end {
+            let at = self.next_onset - self.pending_start;
+            self.onset.magnitudes(&self.pending[at..at + ONSET_FRAME], &mut spectrum);
+            let mut flux = 0.0;
+            for (previous, &magnitude) in self.previous_spectrum.iter_mut().zip(&spectrum) {
+                let level = (1.0 + 1000.0 * magnitude).ln();
+                flux += (level - *previous).max(0.0);
+                *previous = level;
+            }
+            self.flux.push(flux);
+            self.next_onset += ONSET_HOP;
+        }
+
+        while self.next_chroma + CHROMA_FRAME <= end {
+            let at = self.next_chroma - self.pending_start;
+            self.chroma.magnitudes(&self.pending[at..at + CHROMA_FRAME], &mut spectrum);
+            let mut chroma = [0.0f64; 12];
+            for (&magnitude, pitch) in spectrum.iter().zip(&self.pitch_classes) {
+                if let Some(pitch) = pitch {
+                    chroma[*pitch] += magnitude as f64;
+                }
+            }
+            // Each frame counts once however loud it is, and silence not at all
+            let total: f64 = chroma.iter().sum();
+            if total > 1e-3 {
+                for (sum, c) in self.chroma_sum.iter_mut().zip(chroma) {
+                    *sum += c / total;
+                }
+            }
+            self.next_chroma += CHROMA_HOP;
+        }
+
+        let keep_from = self.next_onset.min(self.next_chroma);
+        self.pending.drain(..keep_from - self.pending_start);
+        self.pending_start = keep_from;
+    }
+
+    pub fn finish(self) -> Result<Detection, String> {
+        let seconds = self.frames_seen as f64 / self.input_rate as f64;
+        if seconds < MIN_SECONDS {
+            return Err(format!(
+                "Need at least {} seconds of audio to detect tempo and key, have {:.1}",
+                MIN_SECONDS, seconds
+            ));
+        }
+        Ok(Detection {
+            tempo: self.tempo(),
+            key: self.key(),
+            seconds,
+            detected_at: now(),
+            ..Default::default()
+        })
+    }
+
+    fn tempo(&self) -> Option<TempoEstimate> {
+        let frame_rate = self.rate / ONSET_HOP as f64;
+
+        // Onsets are what stands out from the flux around them
+        let reach = (ONSET_BACKGROUND_SECONDS * frame_rate) as usize;
+        let mut prefix = vec![0.0f64; self.flux.len() + 1];
+        for (i, &f) in self.flux.iter().enumerate() {
+            prefix[i + 1] = prefix[i] + f as f64;
+        }
+        let mut onsets: Vec<f64> = (0..self.flux.len())
+            .map(|i| {
+                let (from, to) = (i.saturating_sub(reach), (i + reach + 1).min(self.flux.len()));
+                let background = (prefix[to] - prefix[from]) / (to - from) as f64;
+                (self.flux[i] as f64 - background).max(0.0)
+            })
+            .collect();
+        let mean = onsets.iter().sum::<f64>() / onsets.len().max(1) as f64;
+        onsets.iter_mut().for_each(|o| *o -= mean);
+
+        let autocorrelation = |lag: usize| -> f64 { onsets.iter().zip(&onsets[lag..]).map(|(a, b)| a * b).sum() };
+        let energy = autocorrelation(0);
+        let shortest = (60.0 / MAX_BPM * frame_rate).floor() as usize;
+        let longest = (60.0 / MIN_BPM * frame_rate).ceil() as usize;
+        if energy <= 0.0 || 2 * longest + 2 >= onsets.len() {
+            return None;
+        }
+
+        // Each lag is backed up by the next bar line, which favours the beat over
+        // its subdivisions
+        let acf: Vec<f64> = (0..=2 * longest + 2).map(autocorrelation).collect();
+        let score = |lag: usize| acf[lag] + 0.5 * acf[2 * lag];
+        let best = (shortest..=longest).max_by(|&a, &b| score(a).total_cmp(&score(b)))?;
+
+        // Between-frame beat period from a parabola through the peak
+        let (before, peak, after) = (score(best - 1), score(best), score(best + 1));
+        let curvature = before - 2.0 * peak + after;
+        let offset = if curvature < 0.0 { (0.5 * (before - after) / curvature).clamp(-0.5, 0.5) } else { 0.0 };
+
+        Some(TempoEstimate {
+            bpm: 60.0 * frame_rate / (best as f64 + offset),
+            confidence: (acf[best] / energy).clamp(0.0, 1.0),
+        })
+    }
+
+    fn key(&self) -> Option<KeyEstimate> {
+        if self.chroma_sum.iter().sum::<f64>() <= 0.0 {
+            return None;
+        }
+        let mut best: Option<KeyEstimate> = None;
+        for tonic in 0..12 {
+            for (mode, profile) in [(KeyMode::Major, &MAJOR_PROFILE), (KeyMode::Minor, &MINOR_PROFILE)] {
+                let rotated: Vec<f64> = (0..12).map(|pitch| profile[(pitch + 12 - tonic) % 12]).collect();
+                let r = pearson(&self.chroma_sum, &rotated);
+                if best.as_ref().is_none_or(|b| r > b.confidence) {
+                    best = Some(KeyEstimate {
+                        key: PITCH_NAMES[tonic].to_string(),
+                        mode,
+                        confidence: r,
+                    });
+                }
+            }
+        }
+        best.map(|key| KeyEstimate {
+            confidence: key.confidence.clamp(0.0, 1.0),
+            ..key
+        })
+    }
+}
+
+fn pearson(a: &[f64], b: &[f64]) -> f64 {
+    let n = a.len() as f64;
+    let (mean_a, mean_b) = (a.iter().sum::<f64>() / n, b.iter().sum::<f64>() / n);
+    let (mut ab, mut aa, mut bb) = (0.0, 0.0, 0.0);
+    for (&x, &y) in a.iter().zip(b) {
+        ab += (x - mean_a) * (y - mean_b);
+        aa += (x - mean_a) * (x - mean_a);
+        bb += (y - mean_b) * (y - mean_b);
+    }
+    if aa <= 0.0 || bb <= 0.0 {
+        return 0.0;
+    }
+    ab / (aa * bb).sqrt()
+}
@@ -0,0 +1,347 @@
+//! Non-destructive edits to the session: trim, fades and crossfades.
+//!
+//! The recording is never changed. An `EditList` is compiled into a `Timeline` of
+//! segments of the recording laid end to end, and `EditedReader` renders that
+//! timeline on demand for playback and export. Positions in playback (seek, loop,
+//! position) are on the edited timeline.
+
+use serde::{Deserialize, Serialize};
+use std::f64::consts::FRAC_PI_2;
+use std::sync::Arc;
+
+use crate::pcm_store::PcmReader;
+
+/// Level a log fade starts from, in dB.
+const LOG_FADE_FLOOR_DB: f64 = -60.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    /// Sine/cosine, so a crossfade keeps the combined power constant.
+    EqualPower,
+    /// Linear in dB from -60 dB, which sounds even to the ear.
+    Log,
+}
+
+impl FadeCurve {
+    /// Gain at `progress` (0 to 1) through a fade in.
+    fn gain(self, progress: f64) -> f64 {
+        match self {
+            FadeCurve::Linear => progress,
+            FadeCurve::EqualPower => (progress * FRAC_PI_2).sin(),
+            FadeCurve::Log if progress <= 0.0 => 0.0,
+            FadeCurve::Log => 10f64.powf(LOG_FADE_FLOOR_DB * (1.0 - progress) / 20.0),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Fade {
+    pub seconds: f64,
+    #[serde(default)]
+    pub curve: FadeCurve,
+}
+
+/// Cut from one point of the recording to another, overlapping the two. The last
+/// `seconds` before `from` fade out while the first `seconds` after `to` fade in.
+/// `to` may lie before `from` to repeat a passage.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Crossfade {
+    pub from: f64,
+    pub to: f64,
+    pub seconds: f64,
+    #[serde(default)]
+    pub curve: FadeCurve,
+}
+
+/// Edits to the session, in seconds of the recording.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EditList {
+    pub trim_start: f64,
+    /// End of the last region; the edit follows the recording as it grows when unset.
+    pub trim_end: Option<f64>,
+    pub fade_in: Option<Fade>,
+    pub fade_out: Option<Fade>,
+    /// Applied in order of `from`.
+    pub crossfades: Vec<Crossfade>,
+}
+
+impl EditList {
+    pub fn is_empty(&self) -> bool {
+        self.trim_start == 0.0
+            && self.trim_end.is_none()
+            && self.fade_in.is_none()
+            && self.fade_out.is_none()
+            && self.crossfades.is_empty()
+    }
+}
+
+/// A run of the recording placed on the timeline, in frames.
+#[derive(Clone, Debug)]
+struct Segment {
+    out_start: usize,
+    source_start: usize,
+    /// Runs to the end of the recording when unset; only the last segment can be open.
+    len: Option<usize>,
+    fade_in: usize,
+    fade_in_curve: FadeCurve,
+    fade_out: usize,
+    fade_out_curve: FadeCurve,
+}
+
+impl Segment {
+    /// Frames of the segment that can be rendered from `source_frames` of recording.
+    fn available(&self, source_frames: usize) -> usize {
+        let stored = source_frames.saturating_sub(self.source_start);
+        self.len.map_or(stored, |len| len.min(stored))
+    }
+
+    fn gain(&self, frame: usize, len: usize) -> f64 {
+        let mut gain = 1.0;
+        if frame < self.fade_in {
+            gain *= self.fade_in_curve.gain(frame as f64 / self.fade_in as f64);
+        }
+        if self.fade_out > 0 && frame + self.fade_out >= len {
+            gain *= self.fade_out_curve.gain((len - frame) as f64 / self.fade_out as f64);
+        }
+        gain
+    }
+}
+
+/// An `EditList` resolved to frames.
+#[derive(Clone, Debug)]
+pub struct Timeline {
+    segments: Vec<Segment>,
+    fade_in: Option<(usize, FadeCurve)>,
+    fade_out: Option<(usize, FadeCurve)>,
+}
+
+impl Default for Timeline {
+    /// The recording as it is.
+    fn default() -> Self {
+        Self {
+            segments: vec![Segment {
+                out_start: 0,
+                source_start: 0,
+                len: None,
+                fade_in: 0,
+                fade_in_curve: FadeCurve::Linear,
+                fade_out: 0,
+                fade_out_curve: FadeCurve::Linear,
+            }],
+            fade_in: None,
+            fade_out: None,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn new(edits: &EditList, sample_rate: u32) -> Result<Self, String> {
+        let frames = |what: &str, seconds: f64| -> Result<usize, String> {
+            if !seconds.is_finite() || seconds < 0.0 {
+                return Err(format!("Invalid {}: {}s", what, seconds));
+            }
+            Ok((seconds * sample_rate as f64).round() as usize)
+        };
+        let fade = |what: &str, fade: &Option<Fade>| -> Result<Option<(usize, FadeCurve)>, String> {
+            match fade {
+                Some(fade) => Ok(Some((frames(what, fade.seconds)?, fade.curve)).filter(|(len, _)| *len > 0)),
+                None => Ok(None),
+            }
+        };
+
+        let mut crossfades: Vec<&Crossfade> = edits.crossfades.iter().collect();
+        crossfades.sort_by(|a, b| a.from.total_cmp(&b.from));
+
+        let mut segments = Vec::with_capacity(crossfades.len() + 1);
+        let mut source_start = frames("trim start", edits.trim_start)?;
+        let mut out_start = 0;
+        let mut fade_in = (0, FadeCurve::Linear);
+        for crossfade in crossfades {
+            let from = frames("crossfade start", crossfade.from)?;
+            let overlap = frames("crossfade length", crossfade.seconds)?;
+            if from < source_start + fade_in.0 + overlap {
+                return Err(format!(
+                    "Crossfade at {:.2}s overlaps the trim or another crossfade",
+                    crossfade.from
+                ));
+            }
+            let len = from - source_start;
+            segments.push(Segment {
+                out_start,
+                source_start,
+                len: Some(len),
+                fade_in: fade_in.0,
+                fade_in_curve: fade_in.1,
+                fade_out: overlap,
+                fade_out_curve: crossfade.curve,
+            });
+            out_start += len - overlap;
+            source_start = frames("crossfade target", crossfade.to)?;
+            fade_in = (overlap, crossfade.curve);
+        }
+
+        let len = match edits.trim_end {
+            Some(seconds) => {
+                let end = frames("trim end", seconds)?;
+                if end < source_start + fade_in.0.max(1) {
+                    return Err(format!("Trim end {:.2}s leaves no audio", seconds));
+                }
+                Some(end - source_start)
+            }
+            None => None,
+        };
+        segments.push(Segment {
+            out_start,
+            source_start,
+            len,
+            fade_in: fade_in.0,
+            fade_in_curve: fade_in.1,
+            fade_out: 0,
+            fade_out_curve: FadeCurve::Linear,
+        });
+
+        Ok(Self {
+            segments,
+            fade_in: fade("fade-in length", &edits.fade_in)?,
+            fade_out: fade("fade-out length", &edits.fade_out)?,
+        })
+    }
+
+    /// Plays the recording unchanged.
+    pub fn is_identity(&self) -> bool {
+        let segment = &self.segments[0];
+        self.segments.len() == 1
+            && segment.source_start == 0
+            && segment.len.is_none()
+            && self.fade_in.is_none()
+            && self.fade_out.is_none()
+    }
+
+    /// Frames of the timeline that can be rendered from `source_frames` of recording.
+    /// Each segment needs its audio recorded before anything after its start can play.
+    pub fn available(&self, source_frames: usize) -> usize {
+        let last = self.segments.last().expect("a timeline has a segment");
+        let mut available = last.out_start + last.available(source_frames);
+        for segment in &self.segments {
+            let stored = segment.available(source_frames);
+            if segment.len != Some(stored) {
+                available = available.min(segment.out_start + stored);
+            }
+        }
+        available
+    }
+
+    /// Frames in the finished edit, known once its end is fixed by a trim.
+    fn fixed_len(&self) -> Option<usize> {
+        let last = self.segments.last().expect("a timeline has a segment");
+        last.len.map(|len| last.out_start + len)
+    }
+}
+
+/// Reads the edited timeline from a `PcmStore`, with the same interface as
+/// `PcmReader` but in timeline samples.
+pub struct EditedReader {
+    reader: PcmReader,
+    timeline: Arc<Timeline>,
+    channels: usize,
+    source: Vec<i16>,
+    mix: Vec<f32>,
+}
+
+impl EditedReader {
+    pub fn new(reader: PcmReader, timeline: Arc<Timeline>, channels: u16) -> Self {
+        Self {
+            reader,
+            timeline,
+            channels: channels as usize,
+            source: Vec::new(),
+            mix: Vec::new(),
+        }
+    }
+
+    /// Interleaved timeline samples available to read.
+    pub fn available(&self) -> usize {
+        if self.timeline.is_identity() {
+            return self.reader.available();
+        }
+        self.timeline.available(self.reader.available() / self.channels) * self.channels
+    }
+
+    /// Render timeline samples starting at interleaved index `start` into `out`,
+    /// returning how many were available. `start` must be frame-aligned. The fade
+    /// out is placed at the end of what is stored once `finished` is set, or at
+    /// the trim end.
+    pub fn read(&mut self, start: usize, out: &mut [i16], finished: bool) -> Result<usize, String> {
+        if self.timeline.is_identity() {
+            return self.reader.read(start, out);
+        }
+
+        let channels = self.channels;
+        let source_frames = self.reader.available() / channels;
+        let available = self.timeline.available(source_frames);
+        let first = start / channels;
+        let end = available.min(first + out.len() / channels);
+        if end <= first {
+            return Ok(0);
+        }
+        let frames = end - first;
+
+        self.mix.clear();
+        self.mix.resize(frames * channels, 0.0);
+        for segment in &self.timeline.segments {
+            let len = segment.len.unwrap_or_else(|| segment.available(source_frames));
+            let lo = first.max(segment.out_start);
+            let hi = end.min(segment.out_start + len);
+            if lo >= hi {
+                continue;
+            }
+
+            self.source.resize((hi - lo) * channels, 0);
+            let offset = lo - segment.out_start;
+            let read = self.reader.read((segment.source_start + offset) * channels, &mut self.source)?;
+            self.source[read..].fill(0);
+
+            let mix = &mut self.mix[(lo - first) * channels..(hi - first) * channels];
+            for (i, (mixed, source)) in mix
+                .chunks_exact_mut(channels)
+                .zip(self.source.chunks_exact(channels))
+                .enumerate()
+            {
+                let gain = segment.gain(offset + i, len) as f32;
+                for (m, &s) in mixed.iter_mut().zip(source) {
+                    *m += s as f32 * gain;
+                }
+            }
+        }
+
+        let timeline = &self.timeline;
+        let total = timeline.fixed_len().or(finished.then_some(available));
+        for (i, frame) in self.mix.chunks_exact_mut(channels).enumerate() {
+            let position = first + i;
+            let mut gain = 1.0;
+            if let Some((len, curve)) = timeline.fade_in {
+                if position < len {
+                    gain *= curve.gain(position as f64 / len as f64);
+                }
+            }
+            if let (Some((len, curve)), Some(total)) = (timeline.fade_out, total) {
+                if position < total && position + len >= total {
+                    gain *= curve.gain((total - position) as f64 / len as f64);
+                }
+            }
+            if gain != 1.0 {
+                frame.iter_mut().for_each(|s| *s *= gain as f32);
+            }
+        }
+
+        let out = &mut out[..frames * channels];
+        for (o, &m) in out.iter_mut().zip(&self.mix) {
+            *o = m.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        }
+        Ok(out.len())
+    }
+}
@@ -0,0 +1,116 @@
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+use crate::analysis::Levels;
+use crate::loudness::{LoudnessReport, NormalizationResult};
+
+// Event names the frontend subscribes to with `listen()`
+pub const GENERATION_STATUS: &str = "lyria:status";
+pub const GENERATION_ERROR: &str = "lyria:error";
+pub const FILTERED_PROMPT: &str = "lyria:filtered-prompt";
+pub const CHUNK_RECEIVED: &str = "audio:chunk";
+pub const PLAYBACK_STATE: &str = "audio:state";
+pub const PLAYBACK_POSITION: &str = "audio:position";
+pub const LOUDNESS: &str = "audio:loudness";
+pub const METER: &str = "audio:meter";
+
+/// Minimum spacing between high-frequency events (status, chunk and position ticks).
+pub const EVENT_INTERVAL: Duration = Duration::from_millis(100);
+/// Minimum spacing between meter readings, short enough for meters to move smoothly.
+pub const METER_INTERVAL: Duration = Duration::from_millis(50);
+
+static APP_HANDLE: OnceLock<AppHandle> = OnceLock::new();
+
+pub fn init(app: AppHandle) {
+    let _ = APP_HANDLE.set(app);
+}
+
+/// Emit an event to every window. A no-op until `init` has been called.
+pub fn emit<S: Serialize + Clone>(event: &str, payload: S) {
+    if let Some(app) = APP_HANDLE.get() {
+        if let Err(e) = app.emit(event, payload) {
+            log::warn!("Failed to emit {}: {}", event, e);
+        }
+    }
+}
+
+/// Lets an event through at most once per interval.
+pub struct Throttle {
+    interval: Duration,
+    last: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: Mutex::new(None),
+        }
+    }
+
+    pub fn ready(&self) -> bool {
+        let now = Instant::now();
+        let mut last = self.last.lock();
+        match *last {
+            Some(t) if now.duration_since(t) < self.interval => false,
+            _ => {
+                *last = Some(now);
+                true
+            }
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct ErrorEvent {
+    pub message: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct FilteredPromptEvent {
+    pub text: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ChunkEvent {
+    pub chunk_index: usize,
+    pub samples: usize,
+    pub total_samples: usize,
+    pub duration_seconds: f64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct PlaybackStateEvent {
+    pub is_playing: bool,
+    pub is_paused: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub struct PlaybackPositionEvent {
+    /// Seconds handed to the output device.
+    pub position: f64,
+    /// Seconds heard, compensated for output latency.
+    pub heard_position: f64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct LoudnessEvent {
+    /// Loudness of the session as recorded.
+    pub report: LoudnessReport,
+    /// Set when an export was normalized.
+    pub normalization: Option<NormalizationResult>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct MeterEvent {
+    /// Seconds heard when the reading was taken.
+    pub position: f64,
+    /// Levels since the previous reading.
+    pub levels: Levels,
+    /// dBFS per band, lowest first.
+    pub spectrum: Vec<f32>,
+}
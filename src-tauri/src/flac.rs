@@ -0,0 +1,262 @@
+//! Streaming FLAC encoder built on flacenc's frame encoder.
+//!
+//! Frames are encoded and written as samples arrive, so memory use is bounded by
+//! one block whatever the session length. STREAMINFO is written as a placeholder
+//! first and rewritten once the sample count, frame sizes and MD5 are known.
+
+use flacenc::bitsink::ByteSink;
+use flacenc::component::{BitRepr, StreamInfo};
+use flacenc::config;
+use flacenc::error::{Verified, Verify};
+use flacenc::source::{Fill, FrameBuf};
+use md5::{Digest, Md5};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+/// Largest block size allowed in FLAC's streamable subset at 48 kHz and below.
+const MAX_SUBSET_BLOCK_SIZE: usize = 16384;
+const STREAMINFO_LEN: usize = 34;
+const BLOCK_TYPE_STREAMINFO: u8 = 0;
+const BLOCK_TYPE_VORBIS_COMMENT: u8 = 4;
+const LAST_BLOCK: u8 = 0x80;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct FlacOptions {
+    /// 0 (fastest) to 8 (smallest), roughly following the `flac` command line.
+    pub compression_level: u8,
+    /// Samples per channel in each frame.
+    pub block_size: usize,
+    /// 16 or 24. 24-bit output carries the 16-bit session audio in its top bits.
+    pub bits_per_sample: u16,
+    /// Vorbis comments as (field, value) pairs, e.g. ("TITLE", "Night drive").
+    pub tags: Vec<(String, String)>,
+}
+
+impl Default for FlacOptions {
+    fn default() -> Self {
+        Self {
+            compression_level: 5,
+            block_size: 4096,
+            bits_per_sample: 16,
+            tags: Vec::new(),
+        }
+    }
+}
+
+impl FlacOptions {
+    fn encoder_config(&self) -> Result<Verified<config::Encoder>, String> {
+        let mut encoder = config::Encoder::default();
+        encoder.block_size = self.block_size;
+        encoder.multithread = false;
+
+        let coding = &mut encoder.subframe_coding;
+        match self.compression_level {
+            0 => {
+                coding.use_lpc = false;
+                coding.fixed.max_order = 2;
+                encoder.stereo_coding.use_leftside = false;
+                encoder.stereo_coding.use_rightside = false;
+                encoder.stereo_coding.use_midside = false;
+            }
+            1 => {
+                // Fixed predictors up to order 3, with mid-side the only joint stereo tried
+                coding.use_lpc = false;
+                coding.fixed.max_order = 3;
+                encoder.stereo_coding.use_leftside = false;
+                encoder.stereo_coding.use_rightside = false;
+            }
+            2 => {
+                // Every fixed predictor and stereo decorrelation, picking the
+                // order by counting bits rather than estimating them
+                coding.use_lpc = false;
+                coding.fixed.order_sel = config::OrderSel::BitCount;
+            }
+            level @ 3..=8 => {
+                coding.qlpc.lpc_order = [6, 8, 10, 12, 16, 24][level as usize - 3];
+            }
+            level => return Err(format!("Invalid FLAC compression level: {} (expected 0-8)", level)),
+        }
+
+        encoder
+            .into_verified()
+            .map_err(|(_, e)| format!("Invalid FLAC encoder settings: {}", e))
+    }
+}
+
+/// Writes interleaved 16-bit samples to a FLAC file.
+pub struct FlacWriter {
+    writer: BufWriter<File>,
+    config: Verified<config::Encoder>,
+    stream_info: StreamInfo,
+    channels: usize,
+    block_size: usize,
+    shift: u32,
+    pending: Vec<i32>,
+    frame_buf: FrameBuf,
+    sink: ByteSink,
+    md5: Md5,
+    frame_number: usize,
+    total_samples: usize,
+    min_frame_size: usize,
+    max_frame_size: usize,
+}
+
+impl FlacWriter {
+    pub fn create(path: &str, sample_rate: u32, channels: u16, options: &FlacOptions) -> Result<Self, String> {
+        if options.bits_per_sample != 16 && options.bits_per_sample != 24 {
+            return Err(format!("Unsupported FLAC bit depth: {} (expected 16 or 24)", options.bits_per_sample));
+        }
+        if !(flacenc::constant::MIN_BLOCK_SIZE..=MAX_SUBSET_BLOCK_SIZE).contains(&options.block_size) {
+            return Err(format!(
+                "Invalid FLAC block size: {} (expected {}-{})",
+                options.block_size,
+                flacenc::constant::MIN_BLOCK_SIZE,
+                MAX_SUBSET_BLOCK_SIZE
+            ));
+        }
+        let config = options.encoder_config()?;
+        let channels = channels as usize;
+        let stream_info = StreamInfo::new(sample_rate as usize, channels, options.bits_per_sample as usize)
+            .map_err(|e| format!("Invalid FLAC stream parameters: {}", e))?;
+        let frame_buf = FrameBuf::with_size(channels, options.block_size)
+            .map_err(|e| format!("Invalid FLAC stream parameters: {}", e))?;
+        let comments = vorbis_comment(&options.tags)?;
+        if comments.len() >= 1 << 24 {
+            return Err("FLAC tags are too large".to_string());
+        }
+
+        let file = File::create(path).map_err(|e| format!("Failed to create FLAC file: {}", e))?;
+        let mut writer = BufWriter::new(file);
+
+        // STREAMINFO is filled in by `finalize`
+        let mut header = b"fLaC".to_vec();
+        header.extend(block_header(BLOCK_TYPE_STREAMINFO, STREAMINFO_LEN));
+        header.extend([0u8; STREAMINFO_LEN]);
+        header.extend(block_header(BLOCK_TYPE_VORBIS_COMMENT | LAST_BLOCK, comments.len()));
+        header.extend(comments);
+        writer
+            .write_all(&header)
+            .map_err(|e| format!("Failed to write FLAC header: {}", e))?;
+
+        Ok(Self {
+            writer,
+            config,
+            stream_info,
+            channels,
+            block_size: options.block_size,
+            shift: options.bits_per_sample as u32 - 16,
+            pending: Vec::with_capacity(options.block_size * channels),
+            frame_buf,
+            sink: ByteSink::new(),
+            md5: Md5::new(),
+            frame_number: 0,
+            total_samples: 0,
+            min_frame_size: usize::MAX,
+            max_frame_size: 0,
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<(), String> {
+        let bytes_per_sample = (16 + self.shift as usize) / 8;
+        let block_len = self.block_size * self.channels;
+
+        for &sample in samples {
+            let value = (sample as i32) << self.shift;
+            self.md5.update(&value.to_le_bytes()[..bytes_per_sample]);
+            self.pending.push(value);
+            if self.pending.len() == block_len {
+                self.encode_pending()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_pending(&mut self) -> Result<(), String> {
+        let frames = self.pending.len() / self.channels;
+        if frames == 0 {
+            return Ok(());
+        }
+        // Only the final frame may be shorter than the block size
+        if frames != self.frame_buf.size() {
+            self.frame_buf.resize(frames);
+        }
+        self.frame_buf
+            .fill_interleaved(&self.pending)
+            .map_err(|e| format!("Failed to buffer FLAC frame: {}", e))?;
+
+        let frame = flacenc::encode_fixed_size_frame(&self.config, &self.frame_buf, self.frame_number, &self.stream_info)
+            .map_err(|e| format!("Failed to encode FLAC frame: {:?}", e))?;
+
+        self.sink.clear();
+        frame
+            .write(&mut self.sink)
+            .map_err(|e| format!("Failed to encode FLAC frame: {:?}", e))?;
+        self.writer
+            .write_all(self.sink.as_slice())
+            .map_err(|e| format!("Failed to write FLAC frame: {}", e))?;
+
+        let frame_size = self.sink.as_slice().len();
+        self.min_frame_size = self.min_frame_size.min(frame_size);
+        self.max_frame_size = self.max_frame_size.max(frame_size);
+        self.total_samples += frames;
+        self.frame_number += 1;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Encode any partial final frame and fill in STREAMINFO.
+    pub fn finalize(mut self) -> Result<(), String> {
+        if self.pending.len() % self.channels != 0 {
+            return Err("FLAC input ended partway through a frame".to_string());
+        }
+        self.encode_pending()?;
+
+        let info = &mut self.stream_info;
+        info.set_block_sizes(self.block_size, self.block_size)
+            .and_then(|_| info.set_frame_sizes(self.min_frame_size.min(self.max_frame_size), self.max_frame_size))
+            .map_err(|e| format!("Failed to finalize FLAC stream info: {}", e))?;
+        info.set_total_samples(self.total_samples);
+        info.set_md5_digest(&self.md5.finalize().into());
+
+        self.sink.clear();
+        info.write(&mut self.sink)
+            .map_err(|e| format!("Failed to finalize FLAC stream info: {:?}", e))?;
+
+        let mut file = self
+            .writer
+            .into_inner()
+            .map_err(|e| format!("Failed to flush FLAC file: {}", e))?;
+        file.seek(SeekFrom::Start(8))
+            .and_then(|_| file.write_all(self.sink.as_slice()))
+            .and_then(|_| file.flush())
+            .map_err(|e| format!("Failed to write FLAC stream info: {}", e))?;
+        Ok(())
+    }
+}
+
+/// Metadata block header: type byte (with the last-block flag) and 24-bit length.
+fn block_header(block_type: u8, len: usize) -> [u8; 4] {
+    let len = len as u32;
+    [block_type, (len >> 16) as u8, (len >> 8) as u8, len as u8]
+}
+
+/// Vorbis comment block, shared with the Ogg formats.
+pub(crate) fn vorbis_comment(tags: &[(String, String)]) -> Result<Vec<u8>, String> {
+    let vendor = concat!("Lyria AI Studio ", env!("CARGO_PKG_VERSION"));
+    let mut data = Vec::new();
+    data.extend((vendor.len() as u32).to_le_bytes());
+    data.extend(vendor.as_bytes());
+    data.extend((tags.len() as u32).to_le_bytes());
+
+    for (field, value) in tags {
+        if field.is_empty() || !field.bytes().all(|b| (0x20..=0x7d).contains(&b) && b != b'=') {
+            return Err(format!("Invalid tag name: {:?}", field));
+        }
+        let comment = format!("{}={}", field, value);
+        data.extend((comment.len() as u32).to_le_bytes());
+        data.extend(comment.as_bytes());
+    }
+    Ok(data)
+}
@@ -7,8 +7,26 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 
-mod audio_stream;
-mod lyria_ws;
+pub mod analysis;
+pub mod audio_stream;
+pub mod detection;
+pub mod edits;
+mod events;
+pub mod flac;
+pub mod loops;
+pub mod loudness;
+pub mod lyria_ws;
+pub mod metadata;
+pub mod mixer;
+pub mod mp3;
+pub mod opus;
+mod pcm_store;
+pub mod peaks;
+mod playback;
+pub mod resample;
+pub mod transfer;
+pub mod vorbis;
+pub mod wav;
 use audio_stream::{get_streamer, init_streamer};
 
 const ENCRYPTION_KEY: &[u8; 32] = b"LyriaStudioSecretKey2024!@#$%^&*";
@@ -20,6 +38,8 @@ pub struct Settings {
     pub vertex_region: Option<String>,
     pub vertex_access_token_encrypted: Option<String>,
     pub lyria_model: Option<String>,
+    pub lyria_base_url: Option<String>,
+    pub lyria_api_version: Option<String>,
     pub show_api_key: bool,
     pub theme: String,
     pub presets: Vec<Preset>,
@@ -40,7 +60,7 @@ pub struct Preset {
     pub temperature: f32,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PromptWeight {
     pub text: String,
     pub weight: f32,
@@ -130,6 +150,12 @@ fn save_settings(settings_json: String) -> Result<(), String> {
         if settings.vertex_access_token_encrypted.is_none() {
             settings.vertex_access_token_encrypted = existing.vertex_access_token_encrypted;
         }
+        if settings.lyria_base_url.is_none() {
+            settings.lyria_base_url = existing.lyria_base_url;
+        }
+        if settings.lyria_api_version.is_none() {
+            settings.lyria_api_version = existing.lyria_api_version;
+        }
     }
     
     save_settings_internal(&settings)
@@ -224,6 +250,22 @@ fn audio_write_chunk_base64(audio_data_base64: String) -> Result<usize, String>
     }
 }
 
+/// Like `audio_write_chunk`, for a raw body of little-endian 16-bit PCM.
+#[tauri::command]
+fn audio_write_chunk_raw(request: tauri::ipc::Request<'_>) -> Result<usize, String> {
+    let tauri::ipc::InvokeBody::Raw(bytes) = request.body() else {
+        return Err("Expected raw PCM bytes".to_string());
+    };
+    let samples = transfer::samples_from_bytes(bytes)?;
+
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.write_chunk(&samples),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
 #[tauri::command]
 fn audio_start_playback() -> Result<(), String> {
     let streamer = get_streamer();
@@ -244,16 +286,287 @@ fn audio_stop_playback() -> Result<(), String> {
     Ok(())
 }
 
+#[tauri::command]
+fn audio_end_stream() -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    if let Some(s) = guard.as_mut() {
+        s.end_of_stream();
+    }
+    Ok(())
+}
+
 #[tauri::command]
 fn audio_pause_playback() -> Result<(), String> {
-    // Pause not implemented in thread-based approach - just stop
-    audio_stop_playback()
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    if let Some(s) = guard.as_mut() {
+        s.pause_playback();
+    }
+    Ok(())
 }
 
 #[tauri::command]
 fn audio_resume_playback() -> Result<(), String> {
-    // Resume not implemented - need to restart playback
-    audio_start_playback()
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.resume_playback(),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_seek(seconds: f64) -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.seek(seconds),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_set_loop(start: f64, end: f64) -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.set_loop(start, end),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_clear_loop() -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    if let Some(s) = guard.as_mut() {
+        s.clear_loop();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn audio_set_output_latency(latency_ms: u64) -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => {
+            s.set_output_latency_ms(latency_ms);
+            Ok(())
+        }
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_set_edits(edits: edits::EditList) -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.set_edits(edits),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_get_edits() -> Result<edits::EditList, String> {
+    let streamer = get_streamer();
+    let guard = streamer.lock();
+    match guard.as_ref() {
+        Some(s) => Ok(s.get_edits().clone()),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_set_trim(start: f64, end: Option<f64>) -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.set_trim(start, end),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_set_fade_in(fade: Option<edits::Fade>) -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.set_fade_in(fade),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_set_fade_out(fade: Option<edits::Fade>) -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.set_fade_out(fade),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_add_crossfade(crossfade: edits::Crossfade) -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.add_crossfade(crossfade),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_clear_edits() -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    if let Some(s) = guard.as_mut() {
+        s.clear_edits()?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn audio_set_metadata(metadata: metadata::TrackMetadata) -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => {
+            s.set_metadata(metadata);
+            Ok(())
+        }
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_get_metadata() -> Result<metadata::TrackMetadata, String> {
+    let streamer = get_streamer();
+    let guard = streamer.lock();
+    match guard.as_ref() {
+        Some(s) => Ok(s.get_metadata().clone()),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+/// Snapshot the session under the streamer lock and release it, so generation and
+/// playback carry on while an export reads and encodes.
+fn export_snapshot() -> Result<audio_stream::ExportSnapshot, String> {
+    match get_streamer().lock().as_ref() {
+        Some(s) => Ok(s.export_snapshot()),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_measure_loudness() -> Result<loudness::LoudnessReport, String> {
+    export_snapshot()?.measure_loudness()
+}
+
+#[tauri::command]
+fn audio_set_normalization(normalization: Option<loudness::Normalization>) -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.set_normalization(normalization),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_set_analysis(options: Option<analysis::AnalysisOptions>) -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.set_analysis(options.unwrap_or_default()),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_detect_tempo_key() -> Result<detection::Detection, String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.detect_tempo_and_key(),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+/// Decode a vocal track sent as the raw bytes of an audio file, with its id and
+/// URL-encoded file name in the `x-track-id` and `x-track-name` headers.
+#[tauri::command]
+fn audio_add_vocal_track(request: tauri::ipc::Request<'_>) -> Result<mixer::VocalTrack, String> {
+    let tauri::ipc::InvokeBody::Raw(bytes) = request.body() else {
+        return Err("Expected raw audio file bytes".to_string());
+    };
+    let header = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| format!("Missing {} header", name))
+    };
+    let id = header("x-track-id")?;
+    let name = urlencoding::decode(header("x-track-name")?)
+        .map_err(|e| format!("Failed to decode track name: {}", e))?;
+
+    let streamer = get_streamer();
+    let sample_rate = match streamer.lock().as_ref() {
+        Some(s) => s.get_sample_rate(),
+        None => return Err("Audio streamer not initialized".to_string()),
+    };
+    // Decode without holding the streamer, which playback and metering share
+    let track = mixer::VocalTrack::decode(id, &name, bytes.clone(), sample_rate)?;
+
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => Ok(s.add_vocal_track(track).clone()),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_set_vocal_track(id: String, settings: Option<mixer::TrackSettings>) -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.set_vocal_track(&id, settings.unwrap_or_default()),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_remove_vocal_track(id: String) -> Result<(), String> {
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    match guard.as_mut() {
+        Some(s) => s.remove_vocal_track(&id),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_get_vocal_tracks() -> Result<Vec<mixer::VocalTrack>, String> {
+    let streamer = get_streamer();
+    let guard = streamer.lock();
+    match guard.as_ref() {
+        Some(s) => Ok(s.get_vocal_tracks().to_vec()),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+fn audio_find_loops(search: Option<loops::LoopSearch>) -> Result<Vec<loops::LoopCandidate>, String> {
+    let streamer = get_streamer();
+    let guard = streamer.lock();
+    match guard.as_ref() {
+        Some(s) => s.find_loops(&search.unwrap_or_default()),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
 }
 
 #[tauri::command]
@@ -261,21 +574,56 @@ fn audio_get_status() -> Result<serde_json::Value, String> {
     let streamer = get_streamer();
     let guard = streamer.lock();
     match guard.as_ref() {
-        Some(s) => Ok(serde_json::json!({
-            "isPlaying": s.is_playing(),
-            "position": s.get_position(),
-            "duration": s.get_duration(),
-            "chunkCount": s.get_chunk_count(),
-        })),
+        Some(s) => {
+            let loop_region = s.get_loop();
+            Ok(serde_json::json!({
+                "isPlaying": s.is_playing(),
+                "isPaused": s.is_paused(),
+                "position": s.get_position(),
+                "heardPosition": s.get_heard_position(),
+                "outputLatencyMs": s.get_output_latency_ms(),
+                "duration": s.get_edited_duration(),
+                "recordedDuration": s.get_duration(),
+                "chunkCount": s.get_chunk_count(),
+                "underruns": s.get_underruns(),
+                "loopStart": loop_region.map(|(start, _)| start),
+                "loopEnd": loop_region.map(|(_, end)| end),
+                "normalization": s.get_normalization(),
+                "edits": s.get_edits(),
+                "analysis": s.get_analysis(),
+                "vocalTracks": s.get_vocal_tracks(),
+            }))
+        }
         None => Ok(serde_json::json!({
             "isPlaying": false,
+            "isPaused": false,
             "position": 0.0,
+            "heardPosition": 0.0,
+            "outputLatencyMs": 0,
             "duration": 0.0,
+            "recordedDuration": 0.0,
             "chunkCount": 0,
+            "underruns": 0,
+            "loopStart": null,
+            "loopEnd": null,
+            "normalization": null,
+            "edits": null,
+            "analysis": null,
+            "vocalTracks": [],
         })),
     }
 }
 
+#[tauri::command]
+fn audio_get_peaks(start: f64, end: f64, pixels: usize) -> Result<peaks::Peaks, String> {
+    let streamer = get_streamer();
+    let guard = streamer.lock();
+    match guard.as_ref() {
+        Some(s) => s.get_peaks(start, end, pixels),
+        None => Err("Audio streamer not initialized".to_string()),
+    }
+}
+
 #[tauri::command]
 fn audio_clear() -> Result<(), String> {
     let streamer = get_streamer();
@@ -288,38 +636,120 @@ fn audio_clear() -> Result<(), String> {
 
 #[tauri::command]
 fn audio_export(output_path: String) -> Result<(), String> {
-    let streamer = get_streamer();
-    let guard = streamer.lock();
-    match guard.as_ref() {
-        Some(s) => s.export_to_file(&output_path),
-        None => Err("Audio streamer not initialized".to_string()),
-    }
+    export_snapshot()?.export_to_file(&output_path)
 }
 
 #[tauri::command]
 fn audio_export_format(output_path: String, format: String, bitrate: u32) -> Result<(), String> {
+    export_snapshot()?.export_to_file_with_format(&output_path, &format, bitrate)
+}
+
+#[tauri::command]
+fn audio_export_mp3(output_path: String, options: Option<mp3::Mp3Options>) -> Result<(), String> {
+    export_snapshot()?.export_to_mp3(&output_path, &options.unwrap_or_default())
+}
+
+#[tauri::command]
+fn audio_export_loop(
+    output_path: String,
+    start: f64,
+    end: f64,
+    options: Option<loops::LoopExportOptions>,
+    wav: Option<wav::WavOptions>,
+) -> Result<(), String> {
+    export_snapshot()?.export_loop(&output_path, start, end, &options.unwrap_or_default(), &wav.unwrap_or_default())
+}
+
+#[tauri::command]
+fn audio_export_wav(output_path: String, options: Option<wav::WavOptions>) -> Result<(), String> {
+    export_snapshot()?.export_to_wav(&output_path, &options.unwrap_or_default())
+}
+
+#[tauri::command]
+fn audio_export_flac(output_path: String, options: Option<flac::FlacOptions>) -> Result<(), String> {
+    export_snapshot()?.export_to_flac(&output_path, &options.unwrap_or_default())
+}
+
+#[tauri::command]
+fn audio_export_opus(output_path: String, options: Option<opus::OpusOptions>) -> Result<(), String> {
+    export_snapshot()?.export_to_opus(&output_path, &options.unwrap_or_default())
+}
+
+#[tauri::command]
+fn audio_export_vorbis(output_path: String, options: Option<vorbis::VorbisOptions>) -> Result<(), String> {
+    export_snapshot()?.export_to_vorbis(&output_path, &options.unwrap_or_default())
+}
+
+#[tauri::command]
+fn audio_get_samples() -> Result<Vec<i16>, String> {
     let streamer = get_streamer();
     let guard = streamer.lock();
     match guard.as_ref() {
-        Some(s) => s.export_to_file_with_format(&output_path, &format, bitrate),
+        Some(s) => s.get_all_samples(),
         None => Err("Audio streamer not initialized".to_string()),
     }
 }
 
+/// Like `audio_get_samples`, as raw little-endian 16-bit PCM.
 #[tauri::command]
-fn audio_get_samples() -> Result<Vec<i16>, String> {
+fn audio_read_pcm(start: Option<f64>, end: Option<f64>) -> Result<tauri::ipc::Response, String> {
     let streamer = get_streamer();
     let guard = streamer.lock();
     match guard.as_ref() {
-        Some(s) => s.get_all_samples(),
+        Some(s) => s.read_pcm(start.unwrap_or(0.0), end).map(tauri::ipc::Response::new),
         None => Err("Audio streamer not initialized".to_string()),
     }
 }
 
 // Rust-native Lyria generation (bypasses JavaScript entirely)
 #[tauri::command]
-fn lyria_start_generation(api_key: String, prompt: String, duration_seconds: u32) -> Result<(), String> {
-    lyria_ws::start_generation(&api_key, &prompt, duration_seconds)
+fn lyria_start_generation(
+    api_key: String,
+    prompts: Vec<PromptWeight>,
+    duration_seconds: u32,
+    config: Option<lyria_ws::GenerationConfig>,
+    reconnect: Option<lyria_ws::ReconnectPolicy>,
+    connection: Option<lyria_ws::ConnectionConfig>,
+) -> Result<(), String> {
+    let settings = load_settings_internal().unwrap_or_default();
+    let from_settings = lyria_ws::ConnectionConfig {
+        base_url: settings.lyria_base_url,
+        api_version: settings.lyria_api_version,
+        model: settings.lyria_model,
+    };
+    lyria_ws::start_generation(
+        &api_key,
+        connection.unwrap_or_default().or(from_settings),
+        prompts,
+        duration_seconds,
+        config.unwrap_or_default(),
+        reconnect.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+fn lyria_update_prompts(prompts: Vec<PromptWeight>) -> Result<(), String> {
+    lyria_ws::update_prompts(prompts)
+}
+
+#[tauri::command]
+fn lyria_update_config(config: lyria_ws::GenerationConfig) -> Result<(), String> {
+    lyria_ws::update_config(config)
+}
+
+#[tauri::command]
+fn lyria_pause() -> Result<(), String> {
+    lyria_ws::control_playback(lyria_ws::PlaybackControl::Pause)
+}
+
+#[tauri::command]
+fn lyria_resume() -> Result<(), String> {
+    lyria_ws::control_playback(lyria_ws::PlaybackControl::Play)
+}
+
+#[tauri::command]
+fn lyria_reset_context() -> Result<(), String> {
+    lyria_ws::control_playback(lyria_ws::PlaybackControl::ResetContext)
 }
 
 #[tauri::command]
@@ -360,6 +790,73 @@ fn js_memory_report(heap_used: f64, heap_total: f64, chunk_count: u32, queue_siz
     );
 }
 
+/// Serve `session.wav` or `session.pcm` over the `lyria-audio` scheme, honouring
+/// `Range` so players can stream the session while it records.
+fn serve_audio(request: &tauri::http::Request<Vec<u8>>) -> tauri::http::Response<Vec<u8>> {
+    audio_response(request).unwrap_or_else(|e| {
+        log::error!("Failed to build audio response: {}", e);
+        let mut response = tauri::http::Response::new(Vec::new());
+        *response.status_mut() = tauri::http::StatusCode::INTERNAL_SERVER_ERROR;
+        response
+    })
+}
+
+fn audio_response(request: &tauri::http::Request<Vec<u8>>) -> tauri::http::Result<tauri::http::Response<Vec<u8>>> {
+    use tauri::http::{header, response::Builder, Response, StatusCode};
+
+    let response = Response::builder()
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(header::CACHE_CONTROL, "no-store");
+    let fail = |response: Builder, status: StatusCode, message: String| {
+        log::warn!("Audio request for {} failed: {}", request.uri(), message);
+        response
+            .status(status)
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(message.into_bytes())
+    };
+
+    let path = request.uri().path();
+    let Some(resource) = transfer::AudioResource::from_path(path) else {
+        return fail(response, StatusCode::NOT_FOUND, format!("No audio at {}", path));
+    };
+    let streamer = get_streamer();
+    let guard = streamer.lock();
+    let Some(s) = guard.as_ref() else {
+        return fail(response, StatusCode::SERVICE_UNAVAILABLE, "Audio streamer not initialized".to_string());
+    };
+
+    let total = s.resource_len(resource);
+    let range = request.headers().get(header::RANGE).map(|value| {
+        let value = value.to_str().map_err(|e| format!("Invalid range header: {}", e))?;
+        transfer::parse_range(value, total)
+    });
+    let (start, end) = match range {
+        // The whole session could be too big for one response, so an unranged
+        // request gets the first piece as partial content and the player pages on
+        None => (0, total.min(transfer::MAX_RANGE_BYTES)),
+        Some(Ok(range)) => range,
+        Some(Err(e)) => {
+            let response = response.header(header::CONTENT_RANGE, format!("bytes */{}", total));
+            return fail(response, StatusCode::RANGE_NOT_SATISFIABLE, e);
+        }
+    };
+    let body = match s.read_resource(resource, start, end) {
+        Ok(body) => body,
+        Err(e) => return fail(response, StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+
+    let response = response
+        .header(header::CONTENT_TYPE, resource.content_type())
+        .header(header::ACCEPT_RANGES, "bytes");
+    if range.is_none() && end == total {
+        return response.status(StatusCode::OK).body(body);
+    }
+    response
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end - 1, total))
+        .body(body)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -371,8 +868,9 @@ pub fn run() {
                 .level(log::LevelFilter::Debug)
                 .build(),
         )
-        .setup(|_app| {
+        .setup(|app| {
             log::info!("Lyria AI Studio starting...");
+            events::init(app.handle().clone());
             log::info!("Dialog and FS plugins initialized");
             
             // Initialize audio streamer
@@ -384,6 +882,10 @@ pub fn run() {
             
             Ok(())
         })
+        .register_asynchronous_uri_scheme_protocol(transfer::SCHEME, |_ctx, request, responder| {
+            // Reads can run to megabytes, so keep them off the main thread
+            std::thread::spawn(move || responder.respond(serve_audio(&request)));
+        })
         .invoke_handler(tauri::generate_handler![
             save_api_key,
             get_api_key,
@@ -397,16 +899,53 @@ pub fn run() {
             audio_init,
             audio_write_chunk,
             audio_write_chunk_base64,
+            audio_write_chunk_raw,
             audio_start_playback,
             audio_stop_playback,
+            audio_end_stream,
             audio_pause_playback,
             audio_resume_playback,
+            audio_seek,
+            audio_set_loop,
+            audio_clear_loop,
+            audio_set_output_latency,
+            audio_set_edits,
+            audio_get_edits,
+            audio_set_trim,
+            audio_set_fade_in,
+            audio_set_fade_out,
+            audio_add_crossfade,
+            audio_clear_edits,
+            audio_set_metadata,
+            audio_get_metadata,
+            audio_measure_loudness,
+            audio_set_normalization,
+            audio_set_analysis,
+            audio_detect_tempo_key,
+            audio_add_vocal_track,
+            audio_set_vocal_track,
+            audio_remove_vocal_track,
+            audio_get_vocal_tracks,
+            audio_find_loops,
             audio_get_status,
+            audio_get_peaks,
             audio_clear,
             audio_export,
             audio_export_format,
+            audio_export_mp3,
+            audio_export_loop,
+            audio_export_wav,
+            audio_export_flac,
+            audio_export_opus,
+            audio_export_vorbis,
             audio_get_samples,
+            audio_read_pcm,
             lyria_start_generation,
+            lyria_update_prompts,
+            lyria_update_config,
+            lyria_pause,
+            lyria_resume,
+            lyria_reset_context,
             lyria_stop_generation,
             lyria_get_status,
             lyria_is_generating,
@@ -0,0 +1,259 @@
+//! Finding seamless bar-aligned loops in a session and rendering them for export.
+//!
+//! A loop from `start` to `end` is seamless when jumping from `end` back to
+//! `start` is inaudible, so candidates are scored by how well the audio around
+//! the two boundaries matches: the normalized cross-correlation of the windows
+//! just before and just after each. Candidates are whole numbers of bars at the
+//! session BPM; bar lines are assumed to fall on beats, so every beat is tried as
+//! a downbeat.
+
+use serde::{Deserialize, Serialize};
+
+/// Audio compared either side of a boundary.
+const WINDOW_SECONDS: f64 = 0.2;
+/// Windows quieter than this RMS (about -80 dBFS) are too quiet to compare.
+const SILENCE_RMS: f64 = 3.0;
+/// Cost of seam ends heading in opposite directions, worse than any level.
+const SLOPE_MISMATCH: f32 = 65536.0;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LoopSearch {
+    /// Defaults to the session BPM.
+    pub bpm: Option<f64>,
+    pub bars: u32,
+    pub beats_per_bar: u32,
+    pub max_results: usize,
+}
+
+impl Default for LoopSearch {
+    fn default() -> Self {
+        Self {
+            bpm: None,
+            bars: 4,
+            beats_per_bar: 4,
+            max_results: 5,
+        }
+    }
+}
+
+/// A loop region in seconds, best first in search results.
+#[derive(Clone, Debug, Serialize)]
+pub struct LoopCandidate {
+    pub start: f64,
+    pub end: f64,
+    pub bars: u32,
+    pub bpm: f64,
+    /// How alike the audio around the boundaries is, up to 1 for a perfect seam.
+    pub score: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoopSeam {
+    /// Blend the end of the loop into the audio leading up to its start.
+    Crossfade,
+    /// Shift the whole loop, keeping its length, so both ends sit on zero crossings.
+    ZeroCrossing,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LoopExportOptions {
+    pub seam: LoopSeam,
+    /// Crossfade length, or how far the loop may move to find a zero crossing.
+    pub seam_ms: f64,
+}
+
+impl Default for LoopExportOptions {
+    fn default() -> Self {
+        Self {
+            seam: LoopSeam::Crossfade,
+            seam_ms: 10.0,
+        }
+    }
+}
+
+impl LoopExportOptions {
+    /// Frames of audio the seam may reach beyond either end of the loop.
+    pub fn seam_frames(&self, sample_rate: u32) -> Result<usize, String> {
+        if !self.seam_ms.is_finite() || !(0.0..=1000.0).contains(&self.seam_ms) {
+            return Err(format!("Invalid loop seam length: {}ms (expected 0-1000)", self.seam_ms));
+        }
+        Ok((self.seam_ms / 1000.0 * sample_rate as f64).round() as usize)
+    }
+}
+
+/// Score every bar-aligned loop in `samples` (interleaved) and return the best.
+pub fn find_loops(
+    samples: &[i16],
+    channels: u16,
+    sample_rate: u32,
+    bpm: f64,
+    search: &LoopSearch,
+) -> Result<Vec<LoopCandidate>, String> {
+    if !(20.0..=400.0).contains(&bpm) {
+        return Err(format!("Invalid BPM for loop search: {}", bpm));
+    }
+    if search.bars == 0 || search.beats_per_bar == 0 {
+        return Err("A loop needs at least one bar of at least one beat".to_string());
+    }
+
+    let mono = downmix(samples, channels);
+    let rate = sample_rate as f64;
+    let beat = 60.0 / bpm * rate;
+    let len = beat * (search.bars * search.beats_per_bar) as f64;
+    let window = ((WINDOW_SECONDS * rate) as usize).min(len as usize / 2);
+
+    let mut candidates = Vec::new();
+    for beat_index in 0.. {
+        let start = (beat_index as f64 * beat).round() as usize;
+        let end = (beat_index as f64 * beat + len).round() as usize;
+        if end > mono.len() {
+            break;
+        }
+
+        let mut scores = Vec::with_capacity(2);
+        if start >= window {
+            scores.extend(correlation(&mono[start - window..start], &mono[end - window..end]));
+        }
+        if end + window <= mono.len() {
+            scores.extend(correlation(&mono[start..start + window], &mono[end..end + window]));
+        }
+        if scores.is_empty() {
+            continue;
+        }
+        candidates.push(LoopCandidate {
+            start: start as f64 / rate,
+            end: end as f64 / rate,
+            bars: search.bars,
+            bpm,
+            score: scores.iter().sum::<f64>() / scores.len() as f64,
+        });
+    }
+
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates.truncate(search.max_results);
+    Ok(candidates)
+}
+
+fn downmix<S: Copy + Into<f32>>(samples: &[S], channels: u16) -> Vec<f32> {
+    let channels = channels as usize;
+    samples
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().map(|&s| s.into()).sum::<f32>() / channels as f32)
+        .collect()
+}
+
+/// Normalized cross-correlation, or `None` if either window is silent.
+fn correlation(a: &[f32], b: &[f32]) -> Option<f64> {
+    let (mut ab, mut aa, mut bb) = (0f64, 0f64, 0f64);
+    for (&x, &y) in a.iter().zip(b) {
+        let (x, y) = (x as f64, y as f64);
+        ab += x * y;
+        aa += x * x;
+        bb += y * y;
+    }
+    let silence = SILENCE_RMS * SILENCE_RMS * a.len() as f64;
+    if aa < silence || bb < silence {
+        return None;
+    }
+    Some(ab / (aa * bb).sqrt())
+}
+
+/// The loop `start..end` (frames) of `samples` with its seam treated, ready to
+/// repeat end to start. `samples` needs to hold the seam's reach of audio either
+/// side of the loop where there is any.
+pub fn render_loop(
+    samples: &[f32],
+    channels: u16,
+    sample_rate: u32,
+    start: usize,
+    end: usize,
+    options: &LoopExportOptions,
+) -> Result<Vec<f32>, String> {
+    let channels = channels as usize;
+    let total = samples.len() / channels;
+    let seam = options.seam_frames(sample_rate)?.min((end - start) / 2);
+
+    match options.seam {
+        LoopSeam::ZeroCrossing => {
+            let mono = downmix(samples, channels as u16);
+            let shift = zero_crossing_shift(&mono, start, end, seam);
+            let (start, end) = (start.wrapping_add_signed(shift), end.wrapping_add_signed(shift));
+            Ok(samples[start * channels..end * channels].to_vec())
+        }
+        LoopSeam::Crossfade => {
+            let mut out = samples[start * channels..end * channels].to_vec();
+            let len = end - start;
+            if start >= seam {
+                // Fade the tail into the audio that led up to the start
+                for i in 0..seam {
+                    let fade_in = (i + 1) as f32 / (seam + 1) as f32;
+                    let lead_in = (start - seam + i) * channels;
+                    for c in 0..channels {
+                        let o = &mut out[(len - seam + i) * channels + c];
+                        *o = mix(*o, samples[lead_in + c], fade_in);
+                    }
+                }
+            } else if end + seam <= total {
+                // Fade the head out of the audio that followed the end
+                for i in 0..seam {
+                    let fade_in = (i + 1) as f32 / (seam + 1) as f32;
+                    let follow = (end + i) * channels;
+                    for c in 0..channels {
+                        let o = &mut out[i * channels + c];
+                        *o = mix(samples[follow + c], *o, fade_in);
+                    }
+                }
+            } else if seam > 0 {
+                return Err("No audio around the loop to crossfade with; use a zero-crossing seam".to_string());
+            }
+            Ok(out)
+        }
+    }
+}
+
+/// `from` faded out while `to` fades in, `fade_in` of the way through.
+fn mix(from: f32, to: f32, fade_in: f32) -> f32 {
+    from * (1.0 - fade_in) + to * fade_in
+}
+
+/// Offset within `-reach..=reach` frames that puts both `start` and `end` closest
+/// to zero crossings heading the same way.
+fn zero_crossing_shift(mono: &[f32], start: usize, end: usize, reach: usize) -> isize {
+    let rising = |at: usize| mono.get(at + 1).is_none_or(|&next| next >= mono[at]);
+    let lowest = -(reach.min(start) as isize);
+    let highest = (reach as isize).min(mono.len() as isize - 1 - end as isize);
+
+    (lowest..=highest)
+        .map(|shift| {
+            let (s, e) = (start.wrapping_add_signed(shift), end.wrapping_add_signed(shift));
+            let mismatch = if rising(s) == rising(e) { 0.0 } else { SLOPE_MISMATCH };
+            (mono[s].abs() + mono[e].abs() + mismatch, shift)
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.unsigned_abs().cmp(&b.1.unsigned_abs())))
+        .map_or(0, |(_, shift)| shift)
+}
+
+/// WAV `smpl` chunk body marking the whole file as one forward loop.
+pub fn smpl_chunk(frames: usize, sample_rate: u32) -> Vec<u8> {
+    let fields: [u32; 15] = [
+        0,                                   // manufacturer
+        0,                                   // product
+        1_000_000_000 / sample_rate,         // sample period in nanoseconds
+        60,                                  // MIDI unity note
+        0,                                   // MIDI pitch fraction
+        0,                                   // SMPTE format
+        0,                                   // SMPTE offset
+        1,                                   // sample loops
+        0,                                   // sampler data
+        0,                                   // cue point id
+        0,                                   // loop type: forward
+        0,                                   // loop start
+        frames.saturating_sub(1) as u32,     // loop end, inclusive
+        0,                                   // fraction
+        0,                                   // play count: infinite
+    ];
+    fields.iter().flat_map(|f| f.to_le_bytes()).collect()
+}
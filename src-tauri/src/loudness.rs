@@ -0,0 +1,167 @@
+//! ITU-R BS.1770 / EBU R128 loudness measurement and export normalization.
+
+use ebur128::{EbuR128, Mode};
+use serde::{Deserialize, Serialize};
+
+/// Frames fed to the meter between short-term readings: 100 ms at 48 kHz, the
+/// update rate EBU Tech 3341 asks of a short-term meter.
+const READING_FRAMES: usize = 4800;
+
+/// Loudness of a whole session. Values are `None` when the audio is too short
+/// or too quiet for the gating to keep any of it.
+#[derive(Clone, Debug, Serialize)]
+pub struct LoudnessReport {
+    pub integrated_lufs: Option<f64>,
+    /// Loudest 3-second window.
+    pub max_short_term_lufs: Option<f64>,
+    /// Highest true peak across channels, from 4x oversampling.
+    pub true_peak_dbtp: Option<f64>,
+}
+
+/// Measures interleaved 16-bit audio fed in blocks of any size.
+pub struct LoudnessMeter {
+    meter: EbuR128,
+    channels: usize,
+    pending: Vec<i16>,
+    max_short_term: f64,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32, channels: u16) -> Result<Self, String> {
+        let meter = EbuR128::new(channels as u32, sample_rate, Mode::I | Mode::S | Mode::TRUE_PEAK)
+            .map_err(|e| format!("Failed to create loudness meter: {}", e))?;
+        Ok(Self {
+            meter,
+            channels: channels as usize,
+            pending: Vec::with_capacity(READING_FRAMES * channels as usize),
+            max_short_term: f64::NEG_INFINITY,
+        })
+    }
+
+    pub fn add_samples(&mut self, samples: &[i16]) -> Result<(), String> {
+        let reading_len = READING_FRAMES * self.channels;
+        let mut rest = samples;
+        while !rest.is_empty() {
+            let take = (reading_len - self.pending.len()).min(rest.len());
+            self.pending.extend_from_slice(&rest[..take]);
+            rest = &rest[take..];
+            if self.pending.len() == reading_len {
+                self.flush_pending()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_pending(&mut self) -> Result<(), String> {
+        self.meter
+            .add_frames_i16(&self.pending)
+            .map_err(|e| format!("Failed to measure loudness: {}", e))?;
+        self.pending.clear();
+        let short_term = self
+            .meter
+            .loudness_shortterm()
+            .map_err(|e| format!("Failed to measure loudness: {}", e))?;
+        self.max_short_term = self.max_short_term.max(short_term);
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<LoudnessReport, String> {
+        if !self.pending.is_empty() {
+            self.flush_pending()?;
+        }
+        let integrated = self
+            .meter
+            .loudness_global()
+            .map_err(|e| format!("Failed to measure loudness: {}", e))?;
+
+        let mut true_peak = 0f64;
+        for channel in 0..self.channels as u32 {
+            let peak = self
+                .meter
+                .true_peak(channel)
+                .map_err(|e| format!("Failed to measure true peak: {}", e))?;
+            true_peak = true_peak.max(peak);
+        }
+
+        Ok(LoudnessReport {
+            integrated_lufs: finite(integrated),
+            max_short_term_lufs: finite(self.max_short_term),
+            true_peak_dbtp: finite(20.0 * true_peak.log10()),
+        })
+    }
+}
+
+fn finite(value: f64) -> Option<f64> {
+    value.is_finite().then_some(value)
+}
+
+/// Export setting: bring the integrated loudness to a target without letting the
+/// true peak rise above a ceiling. Only gain is applied, so when the ceiling is
+/// the tighter limit the result lands below the target.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Normalization {
+    pub target_lufs: f64,
+    pub true_peak_ceiling_dbtp: f64,
+}
+
+impl Default for Normalization {
+    /// The common streaming-platform target.
+    fn default() -> Self {
+        Self {
+            target_lufs: -14.0,
+            true_peak_ceiling_dbtp: -1.0,
+        }
+    }
+}
+
+/// What normalizing an export did.
+#[derive(Clone, Debug, Serialize)]
+pub struct NormalizationResult {
+    pub gain_db: f64,
+    /// Loudness and true peak of the exported audio.
+    pub integrated_lufs: Option<f64>,
+    pub true_peak_dbtp: Option<f64>,
+    /// False when the true-peak ceiling held the loudness below the target.
+    pub reached_target: bool,
+}
+
+impl Normalization {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(-60.0..=0.0).contains(&self.target_lufs) {
+            return Err(format!("Invalid loudness target: {} LUFS (expected -60 to 0)", self.target_lufs));
+        }
+        if !(-20.0..=0.0).contains(&self.true_peak_ceiling_dbtp) {
+            return Err(format!(
+                "Invalid true-peak ceiling: {} dBTP (expected -20 to 0)",
+                self.true_peak_ceiling_dbtp
+            ));
+        }
+        Ok(())
+    }
+
+    /// Gain that meets the target within the ceiling. Audio with no measurable
+    /// loudness is left alone.
+    pub fn apply_to(&self, report: &LoudnessReport) -> NormalizationResult {
+        let Some(integrated) = report.integrated_lufs else {
+            return NormalizationResult {
+                gain_db: 0.0,
+                integrated_lufs: None,
+                true_peak_dbtp: report.true_peak_dbtp,
+                reached_target: false,
+            };
+        };
+
+        let wanted = self.target_lufs - integrated;
+        let gain_db = match report.true_peak_dbtp {
+            Some(peak) => wanted.min(self.true_peak_ceiling_dbtp - peak),
+            None => wanted,
+        };
+        NormalizationResult {
+            gain_db,
+            integrated_lufs: Some(integrated + gain_db),
+            true_peak_dbtp: report.true_peak_dbtp.map(|peak| peak + gain_db),
+            reached_target: gain_db >= wanted - 0.05,
+        }
+    }
+}
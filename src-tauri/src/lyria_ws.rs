@@ -1,14 +1,27 @@
 use base64::{Engine as _, engine::general_purpose::STANDARD};
 use futures_util::{SinkExt, StreamExt};
-use log::info;
+use log::{info, warn};
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio_tungstenite::{connect_async, tungstenite::Message, tungstenite::http::Request, tungstenite::handshake::client::generate_key};
 
 use crate::audio_stream::{get_streamer, init_streamer};
+use crate::events::{self, ErrorEvent, FilteredPromptEvent, Throttle};
+use crate::metadata::Provenance;
+use crate::PromptWeight;
+
+const DEFAULT_BASE_URL: &str = "wss://generativelanguage.googleapis.com";
+const DEFAULT_API_VERSION: &str = "v1alpha";
+const DEFAULT_MODEL: &str = "models/lyria-realtime-exp";
+
+/// Longest prompt text the API accepts.
+const MAX_PROMPT_CHARS: usize = 1000;
+/// Weight given to the negative prompt so it steers away from its text.
+const NEGATIVE_PROMPT_WEIGHT: f32 = -1.0;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct GenerationStatus {
@@ -17,12 +30,14 @@ pub struct GenerationStatus {
     pub total_samples: usize,
     pub duration_seconds: f64,
     pub error: Option<String>,
+    pub reconnect_attempts: u32,
 }
 
 #[derive(Debug, Deserialize)]
 struct LyriaMessage {
+    // The server sends an empty object; older builds of this client expected `true`
     #[serde(rename = "setupComplete")]
-    setup_complete: Option<bool>,
+    setup_complete: Option<serde_json::Value>,
     #[serde(rename = "audioChunk")]
     audio_chunk: Option<AudioChunk>,
     #[serde(rename = "filteredPrompt")]
@@ -36,6 +51,7 @@ struct AudioChunk {
 
 #[derive(Debug, Deserialize)]
 struct FilteredPrompt {
+    text: Option<String>,
     #[serde(rename = "filteredReason")]
     filtered_reason: Option<String>,
 }
@@ -50,32 +66,315 @@ struct SetupConfig {
     model: String,
 }
 
-#[derive(Debug, Serialize)]
-struct PlayMessage {
-    #[serde(rename = "liveMusicInput")]
-    live_music_input: LiveMusicInput,
+#[derive(Serialize)]
+struct PlaybackControlMessage {
+    #[serde(rename = "playbackControl")]
+    playback_control: PlaybackControl,
 }
 
-#[derive(Debug, Serialize)]
-struct LiveMusicInput {
-    play: PlayConfig,
+/// Playback control messages understood by the realtime music session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PlaybackControl {
+    Play,
+    Pause,
+    Stop,
+    ResetContext,
 }
 
-#[derive(Debug, Serialize)]
-struct PlayConfig {
+/// Where the realtime session is in its play/pause lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SessionState {
+    Idle,
+    AwaitingSetup,
+    Playing,
+    Paused,
+    Stopped,
+}
+
+impl SessionState {
+    /// State after applying `control`, or an error if the control makes no sense now.
+    fn apply(self, control: PlaybackControl) -> Result<SessionState, String> {
+        match (self, control) {
+            (SessionState::Idle, _) => Err("No generation in progress".to_string()),
+            (SessionState::AwaitingSetup, _) => Err("Session is still being set up".to_string()),
+            (SessionState::Playing | SessionState::Paused | SessionState::Stopped, PlaybackControl::Play) => {
+                Ok(SessionState::Playing)
+            }
+            (SessionState::Playing, PlaybackControl::Pause) => Ok(SessionState::Paused),
+            (state, PlaybackControl::Pause) => Err(format!("Cannot pause a session that is {:?}", state)),
+            (SessionState::Playing | SessionState::Paused, PlaybackControl::Stop) => Ok(SessionState::Stopped),
+            (SessionState::Stopped, PlaybackControl::Stop) => Ok(SessionState::Stopped),
+            (state, PlaybackControl::ResetContext) => Ok(state),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ClientContentMessage {
+    #[serde(rename = "clientContent")]
+    client_content: ClientContent,
+}
+
+#[derive(Serialize)]
+struct ClientContent {
+    #[serde(rename = "weightedPrompts")]
+    weighted_prompts: Vec<PromptWeight>,
+}
+
+#[derive(Serialize)]
+struct MusicGenerationConfigMessage {
     #[serde(rename = "musicGenerationConfig")]
     music_generation_config: MusicGenerationConfig,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 struct MusicGenerationConfig {
-    #[serde(rename = "textPrompt")]
-    text_prompt: TextPrompt,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bpm: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    density: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    brightness: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    guidance: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i32>,
 }
 
-#[derive(Debug, Serialize)]
-struct TextPrompt {
-    text: String,
+/// Generation controls as the UI and `Preset` store them (key and scale kept separate).
+/// Unset fields are left out of the request so the server uses its defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenerationConfig {
+    pub bpm: Option<u32>,
+    pub key: Option<String>,
+    pub scale: Option<String>,
+    pub density: Option<f32>,
+    pub brightness: Option<f32>,
+    pub guidance: Option<f32>,
+    pub temperature: Option<f32>,
+    pub negative_prompt: Option<String>,
+    /// Fixes the server's random choices so a session can be reproduced.
+    pub seed: Option<i32>,
+}
+
+impl GenerationConfig {
+    /// Reject values that clamping can't bring into range.
+    fn validate(&self) -> Result<(), String> {
+        let fields = [
+            ("density", self.density),
+            ("brightness", self.brightness),
+            ("guidance", self.guidance),
+            ("temperature", self.temperature),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value.filter(|v| !v.is_finite()) {
+                return Err(format!("Invalid {} {}", name, value));
+            }
+        }
+        Ok(())
+    }
+
+    fn to_music_config(&self) -> MusicGenerationConfig {
+        MusicGenerationConfig {
+            bpm: self.bpm.map(|b| b.clamp(60, 200)),
+            density: self.density.map(|d| d.clamp(0.0, 1.0)),
+            brightness: self.brightness.map(|b| b.clamp(0.0, 1.0)),
+            scale: lyria_scale(self.key.as_deref(), self.scale.as_deref()),
+            guidance: self.guidance.map(|g| g.clamp(0.0, 6.0)),
+            temperature: self.temperature.map(|t| t.clamp(0.0, 3.0)),
+            seed: self.seed,
+        }
+    }
+}
+
+/// Clean up the prompt list and fold in the negative prompt, rejecting anything the
+/// server would refuse so the error surfaces before a connection is opened.
+fn build_weighted_prompts(prompts: &[PromptWeight], negative_prompt: Option<&str>) -> Result<Vec<PromptWeight>, String> {
+    let mut weighted = Vec::with_capacity(prompts.len() + 1);
+
+    for prompt in prompts {
+        let text = prompt.text.trim();
+        if text.is_empty() || prompt.weight == 0.0 {
+            continue;
+        }
+        if !prompt.weight.is_finite() || prompt.weight < 0.0 {
+            return Err(format!("Invalid weight {} for prompt \"{}\"", prompt.weight, text));
+        }
+        if text.chars().count() > MAX_PROMPT_CHARS {
+            let preview: String = text.chars().take(40).collect();
+            return Err(format!("Prompt exceeds {} characters: \"{}...\"", MAX_PROMPT_CHARS, preview));
+        }
+        weighted.push(PromptWeight {
+            text: text.to_string(),
+            weight: prompt.weight,
+        });
+    }
+
+    if weighted.is_empty() {
+        return Err("At least one prompt with a non-zero weight is required".to_string());
+    }
+
+    let total: f32 = weighted.iter().map(|p| p.weight).sum();
+    if total <= 0.0 {
+        return Err("Prompt weights must not total zero".to_string());
+    }
+
+    if let Some(negative) = negative_prompt.map(str::trim).filter(|n| !n.is_empty()) {
+        if negative.chars().count() > MAX_PROMPT_CHARS {
+            return Err(format!("Negative prompt exceeds {} characters", MAX_PROMPT_CHARS));
+        }
+        weighted.push(PromptWeight {
+            text: negative.to_string(),
+            weight: NEGATIVE_PROMPT_WEIGHT,
+        });
+    }
+
+    Ok(weighted)
+}
+
+/// Lyria scale names indexed by the pitch class of the relative major key.
+const LYRIA_SCALES: [&str; 12] = [
+    "C_MAJOR_A_MINOR",
+    "D_FLAT_MAJOR_B_FLAT_MINOR",
+    "D_MAJOR_B_MINOR",
+    "E_FLAT_MAJOR_C_MINOR",
+    "E_MAJOR_D_FLAT_MINOR",
+    "F_MAJOR_D_MINOR",
+    "G_FLAT_MAJOR_E_FLAT_MINOR",
+    "G_MAJOR_E_MINOR",
+    "A_FLAT_MAJOR_F_MINOR",
+    "A_MAJOR_G_FLAT_MINOR",
+    "B_FLAT_MAJOR_G_MINOR",
+    "B_MAJOR_A_FLAT_MINOR",
+];
+
+/// Map a UI key ("F#") and scale ("minor") to the Lyria scale enum. A scale that is
+/// already in enum form is passed through unchanged.
+pub(crate) fn lyria_scale(key: Option<&str>, scale: Option<&str>) -> Option<String> {
+    let scale = scale?.trim();
+    if scale.contains('_') {
+        return Some(scale.to_uppercase());
+    }
+
+    let key = key?.trim();
+    let mut chars = key.chars();
+    let base = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let pitch = match chars.as_str() {
+        "#" | "♯" => base + 1,
+        "b" | "♭" => base + 11,
+        _ => base,
+    } % 12;
+
+    let major_pitch = if scale.eq_ignore_ascii_case("minor") {
+        (pitch + 3) % 12
+    } else {
+        pitch
+    };
+    Some(LYRIA_SCALES[major_pitch].to_string())
+}
+
+/// Where to connect and which model to ask for. Unset fields fall back to the
+/// settings file and then to the public Lyria RealTime endpoint.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ConnectionConfig {
+    /// `ws://` or `wss://` origin, e.g. `ws://127.0.0.1:9000` for a local stand-in server.
+    pub base_url: Option<String>,
+    pub api_version: Option<String>,
+    pub model: Option<String>,
+}
+
+impl ConnectionConfig {
+    /// Fill any unset fields from `fallback`.
+    pub fn or(self, fallback: ConnectionConfig) -> ConnectionConfig {
+        ConnectionConfig {
+            base_url: self.base_url.or(fallback.base_url),
+            api_version: self.api_version.or(fallback.api_version),
+            model: self.model.or(fallback.model),
+        }
+    }
+
+    fn resolve(&self, api_key: &str) -> Result<Endpoint, String> {
+        let base_url = non_empty(&self.base_url).unwrap_or(DEFAULT_BASE_URL).trim_end_matches('/');
+        let api_version = non_empty(&self.api_version).unwrap_or(DEFAULT_API_VERSION);
+
+        let parsed = url::Url::parse(base_url)
+            .map_err(|e| format!("Invalid Lyria base URL \"{}\": {}", base_url, e))?;
+        if !matches!(parsed.scheme(), "ws" | "wss") {
+            return Err(format!("Lyria base URL must use ws:// or wss://, got \"{}\"", base_url));
+        }
+        let host = match (parsed.host_str(), parsed.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(format!("Lyria base URL has no host: \"{}\"", base_url)),
+        };
+
+        let path = format!(
+            "{}/ws/google.ai.generativelanguage.{}.GenerativeService.BidiGenerateMusic",
+            base_url, api_version
+        );
+
+        Ok(Endpoint {
+            url: format!("{}?key={}", path, urlencoding::encode(api_key)),
+            redacted_url: format!("{}?key=<redacted>", path),
+            host,
+            model: model_name(non_empty(&self.model)),
+        })
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Turn a model setting into a resource name. The UI stores "realtime", "lyria2" or
+/// "lyria3"; only the realtime model speaks this protocol, so the batch models fall
+/// back to it.
+fn model_name(model: Option<&str>) -> String {
+    match model {
+        None | Some("realtime") => DEFAULT_MODEL.to_string(),
+        Some(batch @ ("lyria2" | "lyria3")) => {
+            warn!("{} is not a realtime model, using {}", batch, DEFAULT_MODEL);
+            DEFAULT_MODEL.to_string()
+        }
+        Some(name) if name.starts_with("models/") => name.to_string(),
+        Some(name) => format!("models/{}", name),
+    }
+}
+
+/// A fully resolved connection target.
+struct Endpoint {
+    url: String,
+    redacted_url: String,
+    host: String,
+    model: String,
+}
+
+/// Changes pushed into a running session's select! loop.
+enum SessionCommand {
+    SetPrompts(Vec<PromptWeight>),
+    SetConfig(MusicGenerationConfig),
+    Playback(PlaybackControl),
+}
+
+/// Prompts and config of the current session, as last requested by the UI.
+#[derive(Default)]
+struct SessionParams {
+    prompts: Vec<PromptWeight>,
+    config: GenerationConfig,
 }
 
 struct LyriaGenerator {
@@ -83,6 +382,10 @@ struct LyriaGenerator {
     is_connected: AtomicBool,
     status: Mutex<GenerationStatus>,
     stop_signal: Mutex<Option<mpsc::Sender<()>>>,
+    commands: Mutex<Option<mpsc::Sender<SessionCommand>>>,
+    session: Mutex<SessionParams>,
+    session_state: Mutex<SessionState>,
+    status_throttle: Throttle,
 }
 
 impl LyriaGenerator {
@@ -96,18 +399,65 @@ impl LyriaGenerator {
                 total_samples: 0,
                 duration_seconds: 0.0,
                 error: None,
+                reconnect_attempts: 0,
             }),
             stop_signal: Mutex::new(None),
+            commands: Mutex::new(None),
+            session: Mutex::new(SessionParams::default()),
+            session_state: Mutex::new(SessionState::Idle),
+            status_throttle: Throttle::new(events::EVENT_INTERVAL),
         }
     }
 
+    fn send_command(&self, command: SessionCommand) -> Result<(), String> {
+        self.send_commands(vec![command])
+    }
+
+    /// Queue all of `commands` or, if the channel can't take them all, none.
+    fn send_commands(&self, commands: Vec<SessionCommand>) -> Result<(), String> {
+        let guard = self.commands.lock();
+        let tx = guard.as_ref().ok_or("No generation in progress")?;
+        let permits = tx
+            .try_reserve_many(commands.len())
+            .map_err(|e| format!("Failed to queue session update: {}", e))?;
+        for (permit, command) in permits.zip(commands) {
+            permit.send(command);
+        }
+        Ok(())
+    }
+
+    /// Update the status and notify the frontend. Progress-only updates are throttled,
+    /// state changes always go out.
     fn update_status(&self, state: &str, chunks: usize, samples: usize, duration: f64, error: Option<String>) {
-        let mut status = self.status.lock();
-        status.state = state.to_string();
-        status.chunks_received = chunks;
-        status.total_samples = samples;
-        status.duration_seconds = duration;
-        status.error = error;
+        let snapshot = {
+            let mut status = self.status.lock();
+            let changed = status.state != state || status.error != error;
+            status.state = state.to_string();
+            status.chunks_received = chunks;
+            status.total_samples = samples;
+            status.duration_seconds = duration;
+            status.error = error;
+            (changed || self.status_throttle.ready()).then(|| status.clone())
+        };
+        if let Some(status) = snapshot {
+            events::emit(events::GENERATION_STATUS, status);
+        }
+    }
+
+    /// Change the reported state while keeping the progress counters.
+    fn set_state(&self, state: &str, error: Option<String>) {
+        let snapshot = {
+            let mut status = self.status.lock();
+            status.state = state.to_string();
+            status.error = error;
+            status.clone()
+        };
+        if state == "error" {
+            if let Some(message) = &snapshot.error {
+                events::emit(events::GENERATION_ERROR, ErrorEvent { message: message.clone() });
+            }
+        }
+        events::emit(events::GENERATION_STATUS, snapshot);
     }
 }
 
@@ -120,67 +470,233 @@ lazy_static::lazy_static! {
         .expect("Failed to create tokio runtime");
 }
 
-pub fn start_generation(api_key: &str, prompt: &str, duration_seconds: u32) -> Result<(), String> {
+pub fn start_generation(
+    api_key: &str,
+    connection: ConnectionConfig,
+    prompts: Vec<PromptWeight>,
+    duration_seconds: u32,
+    config: GenerationConfig,
+    reconnect: ReconnectPolicy,
+) -> Result<(), String> {
     if GENERATOR.is_running.load(Ordering::SeqCst) {
         return Err("Generation already in progress".to_string());
     }
 
+    let raw_prompts = prompts;
+    let prompts = build_weighted_prompts(&raw_prompts, config.negative_prompt.as_deref())?;
+    config.validate()?;
+
+    let endpoint = connection.resolve(api_key)?;
+
     init_streamer()?;
+    if let Some(s) = get_streamer().lock().as_mut() {
+        s.set_provenance(Provenance::new(&endpoint.model, raw_prompts.clone(), config.clone()));
+    }
 
-    let api_key = api_key.to_string();
-    let prompt = prompt.to_string();
     let generator = Arc::clone(&GENERATOR);
 
     let (stop_tx, stop_rx) = mpsc::channel::<()>(1);
     *generator.stop_signal.lock() = Some(stop_tx);
 
+    let (command_tx, command_rx) = mpsc::channel::<SessionCommand>(16);
+    *generator.commands.lock() = Some(command_tx);
+    *generator.session.lock() = SessionParams {
+        prompts: raw_prompts,
+        config: config.clone(),
+    };
+
+    *generator.session_state.lock() = SessionState::AwaitingSetup;
     generator.is_running.store(true, Ordering::SeqCst);
+    generator.status.lock().reconnect_attempts = 0;
     generator.update_status("connecting", 0, 0, 0.0, None);
 
     TOKIO_RT.spawn(async move {
-        match run_generation(&api_key, &prompt, duration_seconds, stop_rx, &generator).await {
+        let result = run_generation(&endpoint, prompts, duration_seconds, &config, &reconnect, stop_rx, command_rx, &generator).await;
+        // `stop_generation` takes the stop signal and reports "stopped" itself
+        let stopped = generator.stop_signal.lock().is_none();
+        match result {
+            Ok(_) if stopped => info!("Generation stopped"),
+            Err(e) if stopped => info!("Generation stopped: {}", e),
             Ok(_) => {
                 info!("Generation completed successfully");
-                generator.update_status("completed", 
-                    generator.status.lock().chunks_received,
-                    generator.status.lock().total_samples,
-                    generator.status.lock().duration_seconds,
-                    None);
+                generator.set_state("completed", None);
             }
             Err(e) => {
                 info!("Generation failed: {}", e);
-                generator.update_status("error", 0, 0, 0.0, Some(e));
+                generator.set_state("error", Some(e));
             }
         }
+        if let Some(s) = get_streamer().lock().as_mut() {
+            s.end_of_stream();
+        }
         generator.is_running.store(false, Ordering::SeqCst);
         generator.is_connected.store(false, Ordering::SeqCst);
         *generator.stop_signal.lock() = None;
+        *generator.commands.lock() = None;
+        *generator.session_state.lock() = SessionState::Idle;
     });
 
     Ok(())
 }
 
+async fn send_message<W, T>(write: &mut W, label: &str, msg: &T) -> Result<(), String>
+where
+    W: futures_util::Sink<Message> + Unpin,
+    W::Error: std::fmt::Display,
+    T: Serialize,
+{
+    let json = serde_json::to_string(msg)
+        .map_err(|e| format!("Failed to serialize {}: {}", label, e))?;
+
+    info!("Sending {}: {}", label, json);
+    write.send(Message::Text(json))
+        .await
+        .map_err(|e| format!("Failed to send {}: {}", label, e))
+}
+
+/// Retry policy for dropped sessions. The delay doubles after each failed attempt.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReconnectPolicy {
+    pub max_attempts: u32,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay_ms: 500,
+            max_delay_ms: 10_000,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u64 << attempt.saturating_sub(1).min(16);
+        Duration::from_millis(self.initial_delay_ms.saturating_mul(factor).min(self.max_delay_ms))
+    }
+}
+
+/// Everything that outlives a single connection: what to replay on reconnect and
+/// how far generation has got.
+struct SessionProgress {
+    prompts: Vec<PromptWeight>,
+    config: MusicGenerationConfig,
+    chunks_received: usize,
+    total_samples: usize,
+    target_samples: usize,
+    playback_started: bool,
+    sessions_started: u32,
+}
+
+impl SessionProgress {
+    fn record(&mut self, command: &SessionCommand) {
+        match command {
+            SessionCommand::SetPrompts(prompts) => self.prompts = prompts.clone(),
+            SessionCommand::SetConfig(config) => self.config = config.clone(),
+            SessionCommand::Playback(_) => {}
+        }
+    }
+}
+
+/// Why a single connection ended early.
+enum SessionError {
+    /// The connection dropped; generation can carry on over a new one.
+    Dropped(String),
+    /// Retrying would fail the same way.
+    Fatal(String),
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn run_generation(
-    api_key: &str,
-    prompt: &str,
+    endpoint: &Endpoint,
+    prompts: Vec<PromptWeight>,
     target_duration: u32,
+    config: &GenerationConfig,
+    reconnect: &ReconnectPolicy,
     mut stop_rx: mpsc::Receiver<()>,
+    mut command_rx: mpsc::Receiver<SessionCommand>,
     generator: &Arc<LyriaGenerator>,
 ) -> Result<(), String> {
-    let encoded_key = urlencoding::encode(api_key);
-    let url = format!(
-        "wss://generativelanguage.googleapis.com/ws/google.ai.generativelanguage.v1alpha.GenerativeService.BidiGenerateMusic?key={}",
-        encoded_key
-    );
+    let mut progress = SessionProgress {
+        prompts,
+        config: config.to_music_config(),
+        chunks_received: 0,
+        total_samples: 0,
+        target_samples: (target_duration as usize) * 48000,
+        playback_started: false,
+        sessions_started: 0,
+    };
+    let mut attempt: u32 = 0;
+
+    loop {
+        let chunks_before = progress.chunks_received;
+        let reason = match run_session(endpoint, &mut progress, &mut stop_rx, &mut command_rx, generator).await {
+            Ok(()) => return Ok(()),
+            Err(SessionError::Fatal(e)) => return Err(e),
+            Err(SessionError::Dropped(e)) => e,
+        };
+        generator.is_connected.store(false, Ordering::SeqCst);
+
+        // A first connection that never got going is a configuration problem, not a blip
+        if progress.sessions_started == 0 {
+            return Err(reason);
+        }
+        if progress.chunks_received > chunks_before {
+            attempt = 0;
+        }
+        attempt += 1;
+        if attempt > reconnect.max_attempts {
+            return Err(format!("Connection lost after {} reconnect attempts: {}", reconnect.max_attempts, reason));
+        }
+
+        let delay = reconnect.delay(attempt);
+        warn!("Lyria connection dropped ({}), reconnecting in {:?} (attempt {}/{})",
+            reason, delay, attempt, reconnect.max_attempts);
+        {
+            let mut status = generator.status.lock();
+            status.reconnect_attempts += 1;
+        }
+        // The backoff and reconnect can outlast the playback starvation timeout
+        if let Some(s) = get_streamer().lock().as_mut() {
+            s.pause_input();
+        }
+        generator.set_state("reconnecting", Some(reason));
+
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => break,
+                _ = stop_rx.recv() => {
+                    info!("Stop signal received while reconnecting");
+                    return Ok(());
+                }
+                Some(command) = command_rx.recv() => progress.record(&command),
+            }
+        }
+    }
+}
 
+/// Run one WebSocket connection until the target is reached, a stop is requested
+/// or the connection ends.
+async fn run_session(
+    endpoint: &Endpoint,
+    progress: &mut SessionProgress,
+    stop_rx: &mut mpsc::Receiver<()>,
+    command_rx: &mut mpsc::Receiver<SessionCommand>,
+    generator: &Arc<LyriaGenerator>,
+) -> Result<(), SessionError> {
     info!("Connecting to Lyria API...");
-    info!("URL: wss://generativelanguage.googleapis.com/ws/...?key=<redacted>");
+    info!("URL: {}", endpoint.redacted_url);
 
     let ws_key = generate_key();
     let request = Request::builder()
         .method("GET")
-        .uri(&url)
-        .header("Host", "generativelanguage.googleapis.com")
+        .uri(&endpoint.url)
+        .header("Host", &endpoint.host)
         .header("Connection", "Upgrade")
         .header("Upgrade", "websocket")
         .header("Sec-WebSocket-Version", "13")
@@ -188,86 +704,126 @@ async fn run_generation(
         .header("User-Agent", "google-genai-sdk/1.30.0 lyria-ai-studio")
         .header("x-goog-api-client", "google-genai-sdk/1.30.0 lyria-ai-studio")
         .body(())
-        .map_err(|e| format!("Failed to build request: {}", e))?;
+        .map_err(|e| SessionError::Fatal(format!("Failed to build request: {}", e)))?;
 
     let (ws_stream, _) = connect_async(request)
         .await
-        .map_err(|e| format!("WebSocket connection failed: {}", e))?;
+        .map_err(|e| SessionError::Dropped(format!("WebSocket connection failed: {}", e)))?;
 
     info!("WebSocket connected");
     generator.is_connected.store(true, Ordering::SeqCst);
-    generator.update_status("connected", 0, 0, 0.0, None);
+    generator.set_state("connected", None);
 
     let (mut write, mut read) = ws_stream.split();
 
     let setup_msg = SetupMessage {
         setup: SetupConfig {
-            model: "models/lyria-realtime-exp".to_string(),
+            model: endpoint.model.clone(),
         },
     };
-    let setup_json = serde_json::to_string(&setup_msg)
-        .map_err(|e| format!("Failed to serialize setup: {}", e))?;
-    
-    info!("Sending setup: {}", setup_json);
-    
-    write.send(Message::Text(setup_json))
-        .await
-        .map_err(|e| format!("Failed to send setup: {}", e))?;
+    send_message(&mut write, "setup", &setup_msg).await.map_err(SessionError::Dropped)?;
 
     info!("Sent setup message, waiting for setupComplete...");
 
-    let mut _setup_complete = false;
-    let mut chunks_received: usize = 0;
-    let mut total_samples: usize = 0;
-    let target_samples = (target_duration as usize) * 48000;
-    let mut playback_started = false;
+    let mut setup_complete = false;
     const BUFFER_CHUNKS: usize = 15;
 
     loop {
         tokio::select! {
             _ = stop_rx.recv() => {
                 info!("Stop signal received");
-                break;
+                if setup_complete {
+                    let stop_msg = PlaybackControlMessage { playback_control: PlaybackControl::Stop };
+                    let _ = send_message(&mut write, "playback control", &stop_msg).await;
+                }
+                return Ok(());
+            }
+            Some(command) = command_rx.recv() => {
+                // Before setupComplete the latest prompts and config are sent as part of setup
+                progress.record(&command);
+                if !setup_complete {
+                    continue;
+                }
+                let sent = match command {
+                    SessionCommand::SetPrompts(_) => {
+                        let msg = ClientContentMessage {
+                            client_content: ClientContent {
+                                weighted_prompts: progress.prompts.clone(),
+                            },
+                        };
+                        send_message(&mut write, "live prompts update", &msg).await
+                    }
+                    SessionCommand::SetConfig(_) => {
+                        let msg = MusicGenerationConfigMessage {
+                            music_generation_config: progress.config.clone(),
+                        };
+                        send_message(&mut write, "live config update", &msg).await
+                    }
+                    SessionCommand::Playback(control) => {
+                        let msg = PlaybackControlMessage { playback_control: control };
+                        send_message(&mut write, "playback control", &msg).await
+                    }
+                };
+                sent.map_err(SessionError::Dropped)?;
             }
             msg = read.next() => {
                 match msg {
                     Some(Ok(Message::Text(text))) => {
                         info!("Received message: {}", if text.len() > 200 { &text[..200] } else { &text });
                         if let Ok(lyria_msg) = serde_json::from_str::<LyriaMessage>(&text) {
-                            if lyria_msg.setup_complete.unwrap_or(false) {
-                                info!("Setup complete, sending play command");
-                                _setup_complete = true;
-                                generator.update_status("generating", 0, 0, 0.0, None);
-
-                                let play_msg = PlayMessage {
-                                    live_music_input: LiveMusicInput {
-                                        play: PlayConfig {
-                                            music_generation_config: MusicGenerationConfig {
-                                                text_prompt: TextPrompt {
-                                                    text: prompt.to_string(),
-                                                },
-                                            },
-                                        },
+                            if lyria_msg.setup_complete.is_some_and(|v| v != serde_json::Value::Bool(false)) {
+                                info!("Setup complete, sending prompts and play command");
+                                setup_complete = true;
+                                progress.sessions_started += 1;
+                                generator.set_state("generating", None);
+
+                                let prompts_msg = ClientContentMessage {
+                                    client_content: ClientContent {
+                                        weighted_prompts: progress.prompts.clone(),
                                     },
                                 };
-                                let play_json = serde_json::to_string(&play_msg)
-                                    .map_err(|e| format!("Failed to serialize play: {}", e))?;
-                                
-                                info!("Sending play command: {}", play_json);
-                                write.send(Message::Text(play_json))
+                                send_message(&mut write, "weighted prompts", &prompts_msg)
                                     .await
-                                    .map_err(|e| format!("Failed to send play: {}", e))?;
+                                    .map_err(SessionError::Dropped)?;
+
+                                let config_msg = MusicGenerationConfigMessage {
+                                    music_generation_config: progress.config.clone(),
+                                };
+                                send_message(&mut write, "generation config", &config_msg)
+                                    .await
+                                    .map_err(SessionError::Dropped)?;
+
+                                // A session resumed while paused or stopped stays that way until told to play
+                                let should_play = {
+                                    let mut state = generator.session_state.lock();
+                                    let resume = matches!(*state, SessionState::AwaitingSetup | SessionState::Playing);
+                                    if resume {
+                                        *state = SessionState::Playing;
+                                    }
+                                    resume
+                                };
+                                if should_play {
+                                    let play_msg = PlaybackControlMessage { playback_control: PlaybackControl::Play };
+                                    send_message(&mut write, "playback control", &play_msg)
+                                        .await
+                                        .map_err(SessionError::Dropped)?;
+                                }
                             }
 
                             if let Some(filtered) = lyria_msg.filtered_prompt {
                                 let reason = filtered.filtered_reason.unwrap_or_else(|| "Unknown".to_string());
-                                return Err(format!("Prompt filtered: {}", reason));
+                                warn!("Prompt filtered: {} ({:?})", reason, filtered.text);
+                                events::emit(events::FILTERED_PROMPT, FilteredPromptEvent {
+                                    text: filtered.text,
+                                    reason,
+                                });
+                                // The server leaves the prompt out and carries on with the rest
                             }
 
                             if let Some(chunk) = lyria_msg.audio_chunk {
                                 if let Some(data) = chunk.data {
                                     let bytes = STANDARD.decode(&data)
-                                        .map_err(|e| format!("Base64 decode failed: {}", e))?;
+                                        .map_err(|e| SessionError::Fatal(format!("Base64 decode failed: {}", e)))?;
                                     
                                     let samples: Vec<i16> = bytes
                                         .chunks_exact(2)
@@ -277,68 +833,77 @@ async fn run_generation(
                                     let streamer = get_streamer();
                                     let mut guard = streamer.lock();
                                     if let Some(s) = guard.as_mut() {
-                                        s.write_chunk(&samples)?;
+                                        s.write_chunk(&samples).map_err(SessionError::Fatal)?;
                                     }
+                                    drop(guard);
 
-                                    chunks_received += 1;
-                                    total_samples += samples.len() / 2;
-                                    let duration = total_samples as f64 / 48000.0;
+                                    progress.chunks_received += 1;
+                                    progress.total_samples += samples.len() / 2;
+                                    let duration = progress.total_samples as f64 / 48000.0;
 
-                                    if chunks_received % 10 == 0 {
-                                        info!("Received chunk {}, {:.1}s generated", chunks_received, duration);
+                                    if progress.chunks_received % 10 == 0 {
+                                        info!("Received chunk {}, {:.1}s generated", progress.chunks_received, duration);
                                     }
 
+                                    let state = if *generator.session_state.lock() == SessionState::Paused {
+                                        "paused"
+                                    } else if progress.playback_started {
+                                        "playing"
+                                    } else {
+                                        "buffering"
+                                    };
                                     generator.update_status(
-                                        if playback_started { "playing" } else { "buffering" },
-                                        chunks_received,
-                                        total_samples,
+                                        state,
+                                        progress.chunks_received,
+                                        progress.total_samples,
                                         duration,
                                         None
                                     );
 
-                                    if chunks_received >= BUFFER_CHUNKS && !playback_started {
+                                    if progress.chunks_received >= BUFFER_CHUNKS && !progress.playback_started {
                                         info!("Buffer ready, starting playback");
-                                        playback_started = true;
+                                        progress.playback_started = true;
                                         let streamer = get_streamer();
                                         let mut guard = streamer.lock();
                                         if let Some(s) = guard.as_mut() {
-                                            s.start_playback()?;
+                                            s.start_playback().map_err(SessionError::Fatal)?;
                                         }
                                     }
 
-                                    if total_samples >= target_samples {
-                                        info!("Target duration reached ({} samples)", total_samples);
-                                        break;
+                                    if progress.total_samples >= progress.target_samples {
+                                        info!("Target duration reached ({} samples)", progress.total_samples);
+                                        return Ok(());
                                     }
                                 }
                             }
                         }
                     }
                     Some(Ok(Message::Close(frame))) => {
-                        if let Some(cf) = frame {
-                            info!("WebSocket closed by server: code={:?}, reason={}", cf.code, cf.reason);
-                            if !cf.reason.is_empty() {
-                                return Err(format!("Server closed connection: {}", cf.reason));
-                            }
-                        } else {
+                        let Some(cf) = frame else {
                             info!("WebSocket closed by server (no frame)");
-                        }
-                        break;
+                            return Err(SessionError::Dropped("Server closed connection".to_string()));
+                        };
+                        info!("WebSocket closed by server: code={:?}, reason={}", cf.code, cf.reason);
+                        let reason = format!("Server closed connection: {:?} {}", cf.code, cf.reason);
+                        // Going away, abnormal closure and server trouble are worth a new
+                        // connection; policy, auth, quota and bad-request closes would recur
+                        return Err(match u16::from(cf.code) {
+                            1001 | 1006 | 1011..=1014 => SessionError::Dropped(reason),
+                            _ => SessionError::Fatal(reason),
+                        });
                     }
                     Some(Err(e)) => {
-                        return Err(format!("WebSocket error: {}", e));
+                        return Err(SessionError::Dropped(format!("WebSocket error: {}", e)));
                     }
                     None => {
                         info!("WebSocket stream ended");
-                        break;
+                        return Err(SessionError::Dropped("WebSocket stream ended".to_string()));
                     }
                     _ => {}
                 }
             }
         }
     }
-
-    Ok(())
 }
 
 pub fn stop_generation() -> Result<(), String> {
@@ -353,11 +918,83 @@ pub fn stop_generation() -> Result<(), String> {
     }
 
     GENERATOR.is_running.store(false, Ordering::SeqCst);
+    GENERATOR.status.lock().reconnect_attempts = 0;
     GENERATOR.update_status("stopped", 0, 0, 0.0, None);
     
     Ok(())
 }
 
+/// Replace the weighted prompts of the running session without reconnecting.
+pub fn update_prompts(prompts: Vec<PromptWeight>) -> Result<(), String> {
+    let mut session = GENERATOR.session.lock();
+    let weighted = build_weighted_prompts(&prompts, session.config.negative_prompt.as_deref())?;
+    GENERATOR.send_command(SessionCommand::SetPrompts(weighted))?;
+    session.prompts = prompts.clone();
+    drop(session);
+    record_change(Some(prompts), None);
+    Ok(())
+}
+
+/// Replace the generation config of the running session without reconnecting.
+/// BPM and scale changes only take effect once the server resets its context, so
+/// changing either resets it too.
+pub fn update_config(config: GenerationConfig) -> Result<(), String> {
+    config.validate()?;
+    let mut session = GENERATOR.session.lock();
+    let mut commands = Vec::with_capacity(3);
+    if config.negative_prompt != session.config.negative_prompt {
+        let weighted = build_weighted_prompts(&session.prompts, config.negative_prompt.as_deref())?;
+        commands.push(SessionCommand::SetPrompts(weighted));
+    }
+    let music_config = config.to_music_config();
+    let previous = session.config.to_music_config();
+    let reset = music_config.bpm != previous.bpm || music_config.scale != previous.scale;
+    commands.push(SessionCommand::SetConfig(music_config));
+    if reset {
+        commands.push(SessionCommand::Playback(PlaybackControl::ResetContext));
+    }
+    GENERATOR.send_commands(commands)?;
+    session.config = config.clone();
+    drop(session);
+    record_change(None, Some(config));
+    Ok(())
+}
+
+/// Note steering in the provenance of the audio being recorded.
+fn record_change(prompts: Option<Vec<PromptWeight>>, config: Option<GenerationConfig>) {
+    if let Some(s) = get_streamer().lock().as_mut() {
+        s.record_provenance_change(prompts, config);
+    }
+}
+
+/// Send a playback control to the running session. Pausing and resuming also pause
+/// and resume local playback so what is heard follows the session.
+pub fn control_playback(control: PlaybackControl) -> Result<(), String> {
+    let mut state = GENERATOR.session_state.lock();
+    let next = state.apply(control)?;
+    GENERATOR.send_command(SessionCommand::Playback(control))?;
+    *state = next;
+    drop(state);
+
+    let streamer = get_streamer();
+    let mut guard = streamer.lock();
+    if let Some(s) = guard.as_mut() {
+        match control {
+            PlaybackControl::Pause => s.pause_playback(),
+            PlaybackControl::Play if s.is_paused() => s.resume_playback()?,
+            _ => {}
+        }
+    }
+
+    match next {
+        SessionState::Paused => GENERATOR.set_state("paused", None),
+        SessionState::Stopped => GENERATOR.set_state("stopped", None),
+        SessionState::Playing if control == PlaybackControl::Play => GENERATOR.set_state("generating", None),
+        _ => {}
+    }
+    Ok(())
+}
+
 pub fn get_generation_status() -> GenerationStatus {
     GENERATOR.status.lock().clone()
 }
@@ -0,0 +1,322 @@
+//! Tags and generation provenance embedded in exported files.
+//!
+//! Every format carries the same provenance JSON (a `LYRIA_PROVENANCE` Vorbis
+//! comment, an ID3v2 `TXXX` frame, or a field of the WAV iXML chunk), so any export
+//! can be traced back to the session that produced it.
+
+use chrono::{SecondsFormat, Utc};
+use id3::frame::{Comment, ExtendedText};
+use id3::{Tag, TagLike, Version};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::detection::Detection;
+use crate::lyria_ws::GenerationConfig;
+use crate::PromptWeight;
+
+const PROVENANCE_FIELD: &str = "LYRIA_PROVENANCE";
+const SOFTWARE: &str = concat!("Lyria AI Studio ", env!("CARGO_PKG_VERSION"));
+const IXML_VERSION: &str = "2.10";
+
+/// How a session's audio was generated.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Provenance {
+    pub model: String,
+    pub prompts: Vec<PromptWeight>,
+    /// Config the session started with, including the negative prompt and seed.
+    #[serde(flatten)]
+    pub config: GenerationConfig,
+    /// RFC 3339 UTC time the session started.
+    pub generated_at: String,
+    /// Steering applied while the session ran, in order.
+    pub changes: Vec<ProvenanceChange>,
+    /// Tempo and key measured from the audio, against the config in effect.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detection: Option<Detection>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProvenanceChange {
+    /// How much audio had been recorded when the change was made.
+    pub at_seconds: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompts: Option<Vec<PromptWeight>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<GenerationConfig>,
+}
+
+impl Provenance {
+    pub fn new(model: &str, prompts: Vec<PromptWeight>, config: GenerationConfig) -> Self {
+        Self {
+            model: model.to_string(),
+            prompts,
+            config,
+            generated_at: now(),
+            changes: Vec::new(),
+            detection: None,
+        }
+    }
+
+    /// Config in effect at the end of the session, after any steering.
+    pub fn current_config(&self) -> &GenerationConfig {
+        self.changes
+            .iter()
+            .rev()
+            .find_map(|change| change.config.as_ref())
+            .unwrap_or(&self.config)
+    }
+
+    /// One line naming the model and the weighted prompts, for comment fields.
+    fn summary(&self) -> String {
+        let prompts: Vec<String> = self
+            .prompts
+            .iter()
+            .map(|p| format!("{} ({})", p.text, p.weight))
+            .collect();
+        let mut summary = format!("Generated with {} from: {}", self.model, prompts.join(", "));
+        if let Some(negative) = self.config.negative_prompt.as_deref().filter(|n| !n.trim().is_empty()) {
+            summary.push_str(&format!("; avoiding: {}", negative.trim()));
+        }
+        summary
+    }
+}
+
+/// Tags written into exports. BPM and key fall back to the session's config.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub bpm: Option<u32>,
+    /// Musical key as usually written in tags, e.g. "F#m".
+    pub key: Option<String>,
+    pub provenance: Option<Provenance>,
+}
+
+impl TrackMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.artist.is_none()
+            && self.bpm.is_none()
+            && self.key.is_none()
+            && self.provenance.is_none()
+    }
+
+    pub(crate) fn bpm(&self) -> Option<u32> {
+        self.bpm.or_else(|| self.provenance.as_ref()?.current_config().bpm)
+    }
+
+    /// BPM and scale the audio was meant to have: the session config in effect
+    /// at the end, or else the tags.
+    pub(crate) fn requested_config(&self) -> GenerationConfig {
+        if let Some(provenance) = &self.provenance {
+            return provenance.current_config().clone();
+        }
+        let (key, scale) = match self.key.as_deref().map(str::trim) {
+            Some(key) if key.contains('_') => (None, Some(key)),
+            Some(key) => match key.strip_suffix('m') {
+                Some(tonic) => (Some(tonic), Some("minor")),
+                None => (Some(key), Some("major")),
+            },
+            None => (None, None),
+        };
+        GenerationConfig {
+            bpm: self.bpm,
+            key: key.map(str::to_string),
+            scale: scale.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn key(&self) -> Option<String> {
+        self.key.clone().or_else(|| {
+            let config = self.provenance.as_ref()?.current_config();
+            key_name(config.key.as_deref()?, config.scale.as_deref())
+        })
+    }
+
+    fn comment(&self) -> Option<String> {
+        self.provenance.as_ref().map(Provenance::summary)
+    }
+
+    fn date(&self) -> Option<&str> {
+        self.provenance.as_ref().map(|p| p.generated_at.as_str())
+    }
+
+    fn provenance_json(&self) -> Result<Option<String>, String> {
+        self.provenance
+            .as_ref()
+            .map(|p| serde_json::to_string(p).map_err(|e| format!("Failed to serialize provenance: {}", e)))
+            .transpose()
+    }
+
+    /// Vorbis comments for FLAC and Ogg, followed by `extra`. A field in `extra`
+    /// replaces the one generated from the metadata.
+    pub fn vorbis_comments(&self, extra: &[(String, String)]) -> Result<Vec<(String, String)>, String> {
+        let generated = [
+            ("TITLE", self.title.clone()),
+            ("ARTIST", self.artist.clone()),
+            ("BPM", self.bpm().map(|b| b.to_string())),
+            ("KEY", self.key()),
+            ("DATE", self.date().map(str::to_string)),
+            ("COMMENT", self.comment()),
+            (PROVENANCE_FIELD, self.provenance_json()?),
+        ];
+
+        let mut tags: Vec<(String, String)> = generated
+            .into_iter()
+            .filter(|(field, _)| !extra.iter().any(|(f, _)| f.eq_ignore_ascii_case(field)))
+            .filter_map(|(field, value)| Some((field.to_string(), value?)))
+            .collect();
+        tags.extend_from_slice(extra);
+        Ok(tags)
+    }
+
+    /// Write an ID3v2.4 tag, which goes at the very start of an MP3 file.
+    pub fn write_id3(&self, writer: impl Write) -> Result<(), String> {
+        let mut tag = Tag::new();
+        if let Some(title) = &self.title {
+            tag.set_title(title.as_str());
+        }
+        if let Some(artist) = &self.artist {
+            tag.set_artist(artist.as_str());
+        }
+        if let Some(bpm) = self.bpm() {
+            tag.set_text("TBPM", bpm.to_string());
+        }
+        if let Some(key) = self.key() {
+            tag.set_text("TKEY", key);
+        }
+        if let Some(date) = self.date() {
+            // ID3 timestamps carry no zone designator
+            tag.set_text("TDRC", date.trim_end_matches('Z'));
+        }
+        tag.set_text("TSSE", SOFTWARE);
+        if let Some(comment) = self.comment() {
+            tag.add_frame(Comment {
+                lang: "eng".to_string(),
+                description: String::new(),
+                text: comment,
+            });
+        }
+        if let Some(json) = self.provenance_json()? {
+            tag.add_frame(ExtendedText {
+                description: PROVENANCE_FIELD.to_string(),
+                value: json,
+            });
+        }
+
+        tag.write_to(writer, Version::Id3v24)
+            .map_err(|e| format!("Failed to write ID3 tag: {}", e))
+    }
+
+    /// Append LIST/INFO and iXML chunks to a finished WAV file and fix up its
+    /// RIFF size.
+    pub fn append_to_wav(&self, path: &str) -> Result<(), String> {
+        let mut info = b"INFO".to_vec();
+        let fields = [
+            (b"INAM", self.title.clone()),
+            (b"IART", self.artist.clone()),
+            (b"ICRD", self.date().map(|d| d.chars().take(10).collect())),
+            (b"ICMT", self.comment()),
+            (b"ISFT", Some(SOFTWARE.to_string())),
+        ];
+        for (id, value) in fields {
+            if let Some(value) = value {
+                let mut text = value.into_bytes();
+                text.push(0);
+                info.extend(riff_chunk(id, &text));
+            }
+        }
+
+        let mut chunks = riff_chunk(b"LIST", &info);
+        chunks.extend(riff_chunk(b"iXML", self.ixml()?.as_bytes()));
+        append_wav_chunks(path, &chunks)
+    }
+
+    /// iXML document: title and comment in the standard fields, everything else
+    /// in our own block, which iXML readers are required to skip.
+    fn ixml(&self) -> Result<String, String> {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<BWFXML>\n");
+        xml.push_str(&format!("<IXML_VERSION>{}</IXML_VERSION>\n", IXML_VERSION));
+        if let Some(title) = &self.title {
+            xml.push_str(&format!("<PROJECT>{}</PROJECT>\n", xml_escape(title)));
+        }
+        if let Some(comment) = self.comment() {
+            xml.push_str(&format!("<NOTE>{}</NOTE>\n", xml_escape(&comment)));
+        }
+
+        xml.push_str("<LYRIA_AI_STUDIO>\n");
+        let fields = [
+            ("TITLE", self.title.clone()),
+            ("ARTIST", self.artist.clone()),
+            ("BPM", self.bpm().map(|b| b.to_string())),
+            ("KEY", self.key()),
+            ("PROVENANCE", self.provenance_json()?),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value {
+                xml.push_str(&format!("<{0}>{1}</{0}>\n", name, xml_escape(&value)));
+            }
+        }
+        xml.push_str("</LYRIA_AI_STUDIO>\n</BWFXML>\n");
+        Ok(xml)
+    }
+}
+
+/// The current time as an RFC 3339 UTC timestamp.
+pub(crate) fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Key in tag notation: "A" and "minor" become "Am". A Lyria scale enum, which
+/// names a major/relative minor pair, is kept as it is.
+fn key_name(key: &str, scale: Option<&str>) -> Option<String> {
+    let key = key.trim();
+    match scale.map(str::trim) {
+        Some(scale) if scale.contains('_') => Some(scale.to_string()),
+        _ if key.is_empty() => None,
+        Some(scale) if scale.eq_ignore_ascii_case("minor") => Some(format!("{}m", key)),
+        _ => Some(key.to_string()),
+    }
+}
+
+/// Append encoded chunks to a finished WAV file and fix up its RIFF size.
+pub(crate) fn append_wav_chunks(path: &str, chunks: &[u8]) -> Result<(), String> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open WAV file for tagging: {}", e))?;
+    let end = file
+        .seek(SeekFrom::End(0))
+        .map_err(|e| format!("Failed to write WAV tags: {}", e))?;
+    let riff_size = u32::try_from(end + chunks.len() as u64 - 8).map_err(|_| "WAV file is too large to tag".to_string())?;
+
+    file.write_all(chunks)
+        .and_then(|_| file.seek(SeekFrom::Start(4)))
+        .and_then(|_| file.write_all(&riff_size.to_le_bytes()))
+        .and_then(|_| file.flush())
+        .map_err(|e| format!("Failed to write WAV tags: {}", e))
+}
+
+/// RIFF chunk with its id, little-endian length and pad byte.
+pub(crate) fn riff_chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(data.len() + 9);
+    chunk.extend(id);
+    chunk.extend((data.len() as u32).to_le_bytes());
+    chunk.extend(data);
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
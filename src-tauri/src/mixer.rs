@@ -0,0 +1,310 @@
+//! Mixdown of user vocal tracks into the generated bed.
+//!
+//! Tracks arrive as whole audio files (WAV, MP3 or FLAC), are decoded and
+//! resampled to the session rate once, and are then summed into the edited
+//! session block by block as it is exported. Volume and pan follow the Web
+//! Audio graph the app previews vocals through, so exports sound as heard.
+
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::sync::Arc;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::resample::Resampler;
+
+/// Loudest volume a track can be set to, a little over +12 dB.
+const MAX_VOLUME: f32 = 4.0;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TrackSettings {
+    /// Linear gain, 1 for unity.
+    pub volume: f32,
+    /// -1 for hard left to 1 for hard right.
+    pub pan: f32,
+    pub muted: bool,
+    /// Seconds into the edited session the track starts at.
+    pub start_time: f64,
+}
+
+impl Default for TrackSettings {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            pan: 0.0,
+            muted: false,
+            start_time: 0.0,
+        }
+    }
+}
+
+impl TrackSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=MAX_VOLUME).contains(&self.volume) {
+            return Err(format!("Invalid track volume: {} (expected 0-{})", self.volume, MAX_VOLUME));
+        }
+        if !(-1.0..=1.0).contains(&self.pan) {
+            return Err(format!("Invalid track pan: {} (expected -1 to 1)", self.pan));
+        }
+        if !self.start_time.is_finite() || self.start_time < 0.0 {
+            return Err(format!("Invalid track start time: {}", self.start_time));
+        }
+        Ok(())
+    }
+
+    /// Gain from each input channel to each output channel, `[out][in]`, as
+    /// Web Audio's `StereoPannerNode` pans mono and stereo input.
+    fn matrix(&self, channels: u16) -> [[f32; 2]; 2] {
+        let angle = |x: f32| (x * std::f32::consts::FRAC_PI_2).sin_cos();
+        let matrix = if channels == 1 {
+            let (right, left) = angle((self.pan + 1.0) / 2.0);
+            [[left, 0.0], [right, 0.0]]
+        } else if self.pan <= 0.0 {
+            let (right, left) = angle(self.pan + 1.0);
+            [[1.0, left], [0.0, right]]
+        } else {
+            let (right, left) = angle(self.pan);
+            [[left, 0.0], [right, 1.0]]
+        };
+        matrix.map(|row| row.map(|g| g * self.volume))
+    }
+}
+
+/// A decoded vocal track, at the session rate, in its own channel count (mono
+/// or stereo; further channels are dropped).
+#[derive(Clone, Debug, Serialize)]
+pub struct VocalTrack {
+    pub id: String,
+    pub name: String,
+    pub duration: f64,
+    pub source_sample_rate: u32,
+    pub source_channels: u16,
+    pub channels: u16,
+    #[serde(flatten)]
+    pub settings: TrackSettings,
+    #[serde(skip)]
+    samples: Arc<[i16]>,
+}
+
+impl VocalTrack {
+    /// Decode an audio file, resampled to `sample_rate`. `name` is the file name,
+    /// whose extension helps pick the format.
+    pub fn decode(id: &str, name: &str, bytes: Vec<u8>, sample_rate: u32) -> Result<Self, String> {
+        let source = MediaSourceStream::new(Box::new(Cursor::new(bytes)), Default::default());
+        let mut hint = Hint::new();
+        if let Some((_, extension)) = name.rsplit_once('.') {
+            hint.with_extension(extension);
+        }
+        let format_options = FormatOptions {
+            enable_gapless: true,
+            ..Default::default()
+        };
+        let mut format = symphonia::default::get_probe()
+            .format(&hint, source, &format_options, &MetadataOptions::default())
+            .map_err(|e| format!("Failed to read {}: {}", name, e))?
+            .format;
+        let track = format
+            .default_track()
+            .ok_or_else(|| format!("No audio track in {}", name))?;
+        let track_id = track.id;
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .map_err(|e| format!("Failed to decode {}: {}", name, e))?;
+
+        let mut resampler: Option<(Resampler, u32, u16)> = None;
+        let (mut kept, mut resampled, mut samples) = (Vec::new(), Vec::new(), Vec::new());
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(format!("Failed to read {}: {}", name, e)),
+            };
+            if packet.track_id() != track_id {
+                continue;
+            }
+            let decoded = match decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // A damaged frame; carry on with the next one
+                Err(Error::DecodeError(e)) => {
+                    log::warn!("Skipped undecodable frame in {}: {}", name, e);
+                    continue;
+                }
+                Err(e) => return Err(format!("Failed to decode {}: {}", name, e)),
+            };
+
+            let spec = *decoded.spec();
+            let source_channels = spec.channels.count();
+            let (resampler, _, _) = match &mut resampler {
+                Some(resampler) => resampler,
+                None => resampler.insert((
+                    Resampler::new(spec.rate, sample_rate, source_channels.min(2) as u16)?,
+                    spec.rate,
+                    source_channels as u16,
+                )),
+            };
+            let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+            buffer.copy_interleaved_ref(decoded);
+
+            kept.clear();
+            kept.extend(
+                buffer
+                    .samples()
+                    .chunks_exact(source_channels)
+                    .flat_map(|frame| &frame[..source_channels.min(2)]),
+            );
+            resampled.clear();
+            resampler.process(&kept, &mut resampled);
+            samples.extend(resampled.iter().map(|&s| to_i16(s)));
+        }
+
+        let Some((mut resampler, source_sample_rate, source_channels)) = resampler else {
+            return Err(format!("No audio in {}", name));
+        };
+        resampled.clear();
+        resampler.finish(&mut resampled);
+        samples.extend(resampled.iter().map(|&s| to_i16(s)));
+
+        let channels = source_channels.min(2);
+        Ok(Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            duration: samples.len() as f64 / channels as f64 / sample_rate as f64,
+            source_sample_rate,
+            source_channels,
+            channels,
+            settings: TrackSettings::default(),
+            samples: samples.into(),
+        })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels as usize
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample * 32768.0).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+/// Vocal tracks summed over a stereo bed. Clones share the track samples.
+#[derive(Clone)]
+pub struct Mixer {
+    sample_rate: u32,
+    tracks: Vec<VocalTrack>,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            tracks: Vec::new(),
+        }
+    }
+
+    pub fn tracks(&self) -> &[VocalTrack] {
+        &self.tracks
+    }
+
+    /// Add a track, replacing any with the same id but keeping its settings.
+    pub fn add(&mut self, mut track: VocalTrack) -> &VocalTrack {
+        let index = match self.tracks.iter().position(|t| t.id == track.id) {
+            Some(index) => {
+                track.settings = self.tracks[index].settings.clone();
+                self.tracks[index] = track;
+                index
+            }
+            None => {
+                self.tracks.push(track);
+                self.tracks.len() - 1
+            }
+        };
+        &self.tracks[index]
+    }
+
+    pub fn set(&mut self, id: &str, settings: TrackSettings) -> Result<(), String> {
+        settings.validate()?;
+        let track = self
+            .tracks
+            .iter_mut()
+            .find(|t| t.id == id)
+            .ok_or_else(|| format!("No vocal track: {}", id))?;
+        track.settings = settings;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: &str) -> Result<(), String> {
+        let before = self.tracks.len();
+        self.tracks.retain(|t| t.id != id);
+        if self.tracks.len() == before {
+            return Err(format!("No vocal track: {}", id));
+        }
+        Ok(())
+    }
+
+    fn start_frame(&self, track: &VocalTrack) -> usize {
+        (track.settings.start_time * self.sample_rate as f64).round() as usize
+    }
+
+    fn audible(&self) -> impl Iterator<Item = &VocalTrack> {
+        self.tracks.iter().filter(|t| !t.settings.muted && t.settings.volume > 0.0)
+    }
+
+    /// Frame the last audible track ends at; 0 if none is audible.
+    pub fn end_frame(&self) -> usize {
+        self.audible()
+            .map(|t| self.start_frame(t) + t.frames())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn has_audible_tracks(&self) -> bool {
+        self.audible().next().is_some()
+    }
+
+    /// Replace `out` with the stereo `bed` block starting at frame `first`, with
+    /// every audible track added and the sum scaled by `gain_db`. Stays on the
+    /// 16-bit scale but is neither rounded nor clamped.
+    pub fn mix(&self, first: usize, bed: &[i16], gain_db: f64, out: &mut Vec<f32>) {
+        let mut sum: Vec<f32> = bed.iter().map(|&s| s as f32).collect();
+        let frames = bed.len() / 2;
+
+        for track in self.audible() {
+            let start = self.start_frame(track);
+            let from = first.max(start);
+            let to = (first + frames).min(start + track.frames());
+            if from >= to {
+                continue;
+            }
+            let matrix = track.settings.matrix(track.channels);
+            let channels = track.channels as usize;
+            let source = &track.samples[(from - start) * channels..(to - start) * channels];
+            let target = &mut sum[(from - first) * 2..(to - first) * 2];
+            for (input, output) in source.chunks_exact(channels).zip(target.chunks_exact_mut(2)) {
+                let left = input[0] as f32;
+                let right = if channels > 1 { input[1] as f32 } else { 0.0 };
+                output[0] += matrix[0][0] * left + matrix[0][1] * right;
+                output[1] += matrix[1][0] * left + matrix[1][1] * right;
+            }
+        }
+
+        let gain = 10f64.powf(gain_db / 20.0) as f32;
+        out.clear();
+        out.extend(sum.iter().map(|&s| s * gain));
+    }
+}
+
+/// Round mixed samples and clamp them to 16 bits.
+pub fn to_samples(mixed: &[f32], out: &mut Vec<i16>) {
+    out.clear();
+    out.extend(
+        mixed
+            .iter()
+            .map(|&s| s.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16),
+    );
+}
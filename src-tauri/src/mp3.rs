@@ -0,0 +1,267 @@
+//! Streaming MP3 export through LAME.
+//!
+//! Samples are encoded a block at a time as they are read, so memory use does not
+//! grow with the session. LAME reserves the first frame for its Xing/LAME tag,
+//! which `finalize` fills in once the stream is complete: it carries the frame
+//! count and seek table for VBR, and the encoder delay and padding that let
+//! players trim the output to the exact session length.
+//!
+//! LAME is called through its C API directly, as the safe wrapper crates can't
+//! fetch the tag frame. Every unsafe block states why it is sound.
+
+#![deny(clippy::undocumented_unsafe_blocks)]
+
+use mp3lame_sys::{
+    lame_close, lame_encode_buffer, lame_encode_buffer_interleaved, lame_encode_flush, lame_get_lametag_frame,
+    lame_global_flags, lame_init, lame_init_params, lame_set_VBR, lame_set_VBR_mean_bitrate_kbps,
+    lame_set_VBR_quality, lame_set_bWriteVbrTag, lame_set_brate, lame_set_in_samplerate, lame_set_num_channels,
+    lame_set_quality, vbr_mode,
+};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+use crate::metadata::TrackMetadata;
+
+/// Frames handed to LAME at a time, a whole number of 1152-sample MP3 frames.
+const BLOCK_FRAMES: usize = 1152 * 8;
+/// Bitrates an MPEG-1 Layer III stream can use at a constant rate.
+const CBR_BITRATES: [u32; 14] = [32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+/// Largest Xing/LAME tag frame: one 320 kbps frame at 32 kHz.
+const MAX_TAG_FRAME_BYTES: usize = 1441;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mp3BitrateMode {
+    /// Constant bitrate at `bitrate_kbps`.
+    Cbr,
+    /// Variable bitrate driven by `vbr_quality`.
+    Vbr,
+    /// Variable bitrate averaging `bitrate_kbps`.
+    Abr,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Mp3Options {
+    pub mode: Mp3BitrateMode,
+    /// Bitrate for CBR, or the target average for ABR.
+    pub bitrate_kbps: u32,
+    /// VBR quality, 0 (best, like `-V0`) to 9.999.
+    pub vbr_quality: f32,
+    /// Encoder effort, 0 (best, slowest) to 9.
+    pub quality: u8,
+}
+
+impl Default for Mp3Options {
+    fn default() -> Self {
+        Self {
+            mode: Mp3BitrateMode::Cbr,
+            bitrate_kbps: 320,
+            vbr_quality: 2.0,
+            quality: 0,
+        }
+    }
+}
+
+impl Mp3Options {
+    fn validate(&self) -> Result<(), String> {
+        match self.mode {
+            Mp3BitrateMode::Cbr if !CBR_BITRATES.contains(&self.bitrate_kbps) => {
+                return Err(format!("Invalid MP3 bitrate: {} kbps (expected one of {:?})", self.bitrate_kbps, CBR_BITRATES));
+            }
+            Mp3BitrateMode::Abr if !(8..=320).contains(&self.bitrate_kbps) => {
+                return Err(format!("Invalid MP3 average bitrate: {} kbps (expected 8-320)", self.bitrate_kbps));
+            }
+            Mp3BitrateMode::Vbr if !(0.0..10.0).contains(&self.vbr_quality) => {
+                return Err(format!("Invalid MP3 VBR quality: {} (expected 0-9.999)", self.vbr_quality));
+            }
+            _ => {}
+        }
+        if self.quality > 9 {
+            return Err(format!("Invalid MP3 encoder quality: {} (expected 0-9)", self.quality));
+        }
+        Ok(())
+    }
+}
+
+/// Owns a LAME encoder.
+struct Encoder(*mut lame_global_flags);
+
+// SAFETY: LAME's encoder state isn't tied to the thread that made it, and
+// without `Sync` only one thread can reach it at a time.
+unsafe impl Send for Encoder {}
+
+impl Encoder {
+    fn new(sample_rate: u32, channels: u16, options: &Mp3Options) -> Result<Self, String> {
+        // SAFETY: takes no arguments; the result is checked for null before use.
+        let flags = unsafe { lame_init() };
+        if flags.is_null() {
+            return Err("Failed to create MP3 encoder".to_string());
+        }
+        let encoder = Self(flags);
+
+        // SAFETY: `flags` is the live encoder from `lame_init` and the setters
+        // only take plain values. `encoder` owns it, so it is closed on every
+        // early return.
+        unsafe {
+            encoder.check(lame_set_in_samplerate(flags, sample_rate as i32), "sample rate")?;
+            encoder.check(lame_set_num_channels(flags, channels as i32), "channel count")?;
+            encoder.check(lame_set_quality(flags, options.quality as i32), "quality")?;
+            encoder.check(lame_set_bWriteVbrTag(flags, 1), "LAME tag")?;
+            match options.mode {
+                Mp3BitrateMode::Cbr => {
+                    encoder.check(lame_set_VBR(flags, vbr_mode::vbr_off), "bitrate mode")?;
+                    encoder.check(lame_set_brate(flags, options.bitrate_kbps as i32), "bitrate")?;
+                }
+                Mp3BitrateMode::Vbr => {
+                    encoder.check(lame_set_VBR(flags, vbr_mode::vbr_mtrh), "bitrate mode")?;
+                    encoder.check(lame_set_VBR_quality(flags, options.vbr_quality), "VBR quality")?;
+                }
+                Mp3BitrateMode::Abr => {
+                    encoder.check(lame_set_VBR(flags, vbr_mode::vbr_abr), "bitrate mode")?;
+                    encoder.check(lame_set_VBR_mean_bitrate_kbps(flags, options.bitrate_kbps as i32), "average bitrate")?;
+                }
+            }
+            encoder.check(lame_init_params(flags), "parameters")?;
+        }
+        Ok(encoder)
+    }
+
+    fn check(&self, result: i32, what: &str) -> Result<(), String> {
+        if result < 0 {
+            return Err(format!("Failed to set MP3 {}: error {}", what, result));
+        }
+        Ok(())
+    }
+
+    /// Encode interleaved samples into `output`, returning the bytes written.
+    fn encode(&mut self, samples: &[i16], channels: usize, output: &mut [u8]) -> Result<usize, String> {
+        let frames = (samples.len() / channels) as i32;
+        // SAFETY: the encoder is live for as long as `self`. LAME reads `frames`
+        // frames from `samples`, which holds that many, and writes no more than
+        // the `output.len()` bytes it is told it has. A mono encoder never reads
+        // the right channel pointer.
+        let len = unsafe {
+            if channels == 1 {
+                lame_encode_buffer(self.0, samples.as_ptr(), samples.as_ptr(), frames, output.as_mut_ptr(), output.len() as i32)
+            } else {
+                // LAME takes a mutable pointer but only reads the samples
+                lame_encode_buffer_interleaved(self.0, samples.as_ptr() as *mut i16, frames, output.as_mut_ptr(), output.len() as i32)
+            }
+        };
+        if len < 0 {
+            return Err(format!("Failed to encode MP3: error {}", len));
+        }
+        Ok(len as usize)
+    }
+
+    fn flush(&mut self, output: &mut [u8]) -> Result<usize, String> {
+        // SAFETY: the encoder is live, and LAME writes no more than `output.len()` bytes.
+        let len = unsafe { lame_encode_flush(self.0, output.as_mut_ptr(), output.len() as i32) };
+        if len < 0 {
+            return Err(format!("Failed to flush MP3 encoder: error {}", len));
+        }
+        Ok(len as usize)
+    }
+
+    /// The Xing/LAME tag frame for the finished stream.
+    fn tag_frame(&self) -> Result<Vec<u8>, String> {
+        let mut frame = vec![0; MAX_TAG_FRAME_BYTES];
+        // SAFETY: the encoder is live, and LAME writes nothing if the frame doesn't
+        // fit in `frame.len()` bytes, returning the size it needs instead.
+        let mut len = unsafe { lame_get_lametag_frame(self.0, frame.as_mut_ptr(), frame.len()) };
+        if len > frame.len() {
+            // Nothing was written; ask again with the room LAME asked for
+            frame.resize(len, 0);
+            // SAFETY: as above.
+            len = unsafe { lame_get_lametag_frame(self.0, frame.as_mut_ptr(), frame.len()) };
+        }
+        if len > frame.len() {
+            return Err(format!("Failed to write MP3 tag frame: needs {} bytes", len));
+        }
+        frame.truncate(len);
+        Ok(frame)
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        // SAFETY: `self.0` came from `lame_init` and nothing else closes it.
+        unsafe { lame_close(self.0) };
+    }
+}
+
+/// Writes interleaved 16-bit samples to an MP3 file.
+pub struct Mp3Writer {
+    writer: BufWriter<File>,
+    encoder: Encoder,
+    channels: usize,
+    /// File offset of the first MPEG frame, after any ID3 tag.
+    audio_start: u64,
+    output: Vec<u8>,
+}
+
+impl Mp3Writer {
+    pub fn create(
+        path: &str,
+        sample_rate: u32,
+        channels: u16,
+        options: &Mp3Options,
+        metadata: &TrackMetadata,
+    ) -> Result<Self, String> {
+        options.validate()?;
+        if channels != 1 && channels != 2 {
+            return Err(format!("Unsupported channel count for MP3: {}", channels));
+        }
+        let encoder = Encoder::new(sample_rate, channels, options)?;
+
+        let file = File::create(path).map_err(|e| format!("Failed to create MP3 file: {}", e))?;
+        let mut writer = BufWriter::new(file);
+        if !metadata.is_empty() {
+            metadata.write_id3(&mut writer)?;
+        }
+        let audio_start = writer
+            .stream_position()
+            .map_err(|e| format!("Failed to write MP3 file: {}", e))?;
+
+        Ok(Self {
+            writer,
+            encoder,
+            channels: channels as usize,
+            audio_start,
+            // Worst case from the LAME documentation: 1.25 * frames + 7200
+            output: vec![0; BLOCK_FRAMES * 5 / 4 + 7200],
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<(), String> {
+        for block in samples.chunks(BLOCK_FRAMES * self.channels) {
+            let len = self.encoder.encode(block, self.channels, &mut self.output)?;
+            self.writer
+                .write_all(&self.output[..len])
+                .map_err(|e| format!("Failed to write MP3 data: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Flush the encoder and fill in the LAME tag frame.
+    pub fn finalize(mut self) -> Result<(), String> {
+        let len = self.encoder.flush(&mut self.output)?;
+        self.writer
+            .write_all(&self.output[..len])
+            .map_err(|e| format!("Failed to write final MP3 data: {}", e))?;
+
+        let tag_frame = self.encoder.tag_frame()?;
+        let mut file = self
+            .writer
+            .into_inner()
+            .map_err(|e| format!("Failed to flush MP3 file: {}", e))?;
+        if !tag_frame.is_empty() {
+            file.seek(SeekFrom::Start(self.audio_start))
+                .and_then(|_| file.write_all(&tag_frame))
+                .map_err(|e| format!("Failed to write LAME tag: {}", e))?;
+        }
+        file.flush().map_err(|e| format!("Failed to flush MP3 file: {}", e))
+    }
+}
@@ -0,0 +1,234 @@
+//! Ogg Opus export.
+//!
+//! Opus runs natively at 48 kHz, the rate Lyria generates, so samples go to the
+//! encoder untouched. The encoder is the reference libopus translated to Rust, so
+//! it needs no system library or C toolchain. Its API is still the C one, raw
+//! pointers included, so every unsafe block states why it is sound.
+
+#![deny(clippy::undocumented_unsafe_blocks)]
+
+use ogg::{PacketWriteEndInfo, PacketWriter};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use unsafe_libopus::{
+    opus_encode, opus_encoder_create, opus_encoder_ctl, opus_encoder_destroy, opus_strerror, OpusEncoder,
+    OPUS_APPLICATION_AUDIO, OPUS_GET_LOOKAHEAD_REQUEST, OPUS_OK, OPUS_SET_BITRATE_REQUEST,
+    OPUS_SET_COMPLEXITY_REQUEST, OPUS_SET_SIGNAL_REQUEST, OPUS_SET_VBR_REQUEST, OPUS_SIGNAL_MUSIC,
+};
+
+use crate::flac::vorbis_comment;
+
+/// 20 ms at 48 kHz, the usual Opus frame for music.
+const FRAME_SAMPLES: usize = 960;
+/// Recommended maximum packet size from the libopus documentation.
+const MAX_PACKET_BYTES: usize = 4000;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct OpusOptions {
+    /// Target bitrate for the whole stream, 6-510 kbps.
+    pub bitrate_kbps: u32,
+    /// Encoder effort, 0 (fastest) to 10 (best).
+    pub complexity: u8,
+    /// Variable bitrate; constant bitrate when false.
+    pub vbr: bool,
+    pub tags: Vec<(String, String)>,
+}
+
+impl Default for OpusOptions {
+    fn default() -> Self {
+        Self {
+            bitrate_kbps: 128,
+            complexity: 10,
+            vbr: true,
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// Owns a libopus encoder.
+struct Encoder(*mut OpusEncoder);
+
+// SAFETY: the encoder is a heap allocation of its own, not tied to the thread
+// that made it, and without `Sync` only one thread can reach it at a time.
+unsafe impl Send for Encoder {}
+
+impl Encoder {
+    fn new(sample_rate: u32, channels: u16, options: &OpusOptions) -> Result<Self, String> {
+        let mut error = OPUS_OK;
+        // SAFETY: `error` is a live local for the call to write to; the result is
+        // checked for null before use.
+        let encoder = unsafe { opus_encoder_create(sample_rate as i32, channels as i32, OPUS_APPLICATION_AUDIO, &mut error) };
+        if encoder.is_null() || error != OPUS_OK {
+            return Err(format!("Failed to create Opus encoder: {}", opus_strerror(error)));
+        }
+        let encoder = Self(encoder);
+
+        encoder.ctl(OPUS_SET_BITRATE_REQUEST, options.bitrate_kbps as i32 * 1000, "bitrate")?;
+        encoder.ctl(OPUS_SET_COMPLEXITY_REQUEST, options.complexity as i32, "complexity")?;
+        encoder.ctl(OPUS_SET_VBR_REQUEST, options.vbr as i32, "VBR mode")?;
+        encoder.ctl(OPUS_SET_SIGNAL_REQUEST, OPUS_SIGNAL_MUSIC, "signal type")?;
+        Ok(encoder)
+    }
+
+    fn ctl(&self, request: i32, value: i32, what: &str) -> Result<(), String> {
+        // SAFETY: `self.0` is the live encoder from `opus_encoder_create`, and every
+        // request passed here is a setter taking a single integer by value.
+        let result = unsafe { opus_encoder_ctl!(self.0, request, value) };
+        if result != OPUS_OK {
+            return Err(format!("Failed to set Opus {}: {}", what, opus_strerror(result)));
+        }
+        Ok(())
+    }
+
+    /// Samples the decoder must discard from the start of the stream.
+    fn lookahead(&self) -> Result<u16, String> {
+        let mut lookahead = 0i32;
+        // SAFETY: the encoder is live, and the getter writes one `i32` through a
+        // pointer to a local that outlives the call.
+        let result = unsafe { opus_encoder_ctl!(self.0, OPUS_GET_LOOKAHEAD_REQUEST, &mut lookahead) };
+        if result != OPUS_OK {
+            return Err(format!("Failed to read Opus lookahead: {}", opus_strerror(result)));
+        }
+        Ok(lookahead as u16)
+    }
+
+    /// Encode one frame of interleaved samples into `packet`, returning its length.
+    fn encode(&mut self, frame: &[i16], channels: usize, packet: &mut [u8]) -> Result<usize, String> {
+        let frame_samples = frame.len() / channels;
+        // SAFETY: the encoder is live and `&mut self` keeps any other call out. It
+        // reads `frame_samples * channels` samples, all inside `frame`, and writes
+        // no more than `packet.len()` bytes.
+        let len = unsafe {
+            opus_encode(self.0, frame.as_ptr(), frame_samples as i32, packet.as_mut_ptr(), packet.len() as i32)
+        };
+        if len < 0 {
+            return Err(format!("Failed to encode Opus frame: {}", opus_strerror(len)));
+        }
+        Ok(len as usize)
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        // SAFETY: `self.0` came from `opus_encoder_create` and nothing else frees it.
+        unsafe { opus_encoder_destroy(self.0) };
+    }
+}
+
+/// Writes interleaved 16-bit samples to an Ogg Opus file.
+pub struct OpusWriter {
+    packets: PacketWriter<'static, BufWriter<File>>,
+    encoder: Encoder,
+    serial: u32,
+    channels: usize,
+    pre_skip: u64,
+    pending: Vec<i16>,
+    packet: Vec<u8>,
+    /// Last encoded packet and its granule position, held back until we know
+    /// whether it ends the stream.
+    held: Option<(Vec<u8>, u64)>,
+    encoded_samples: u64,
+    input_samples: u64,
+}
+
+impl OpusWriter {
+    pub fn create(path: &str, sample_rate: u32, channels: u16, options: &OpusOptions) -> Result<Self, String> {
+        if sample_rate != 48000 {
+            return Err(format!("Opus export needs 48 kHz audio, not {} Hz", sample_rate));
+        }
+        if !(6..=510).contains(&options.bitrate_kbps) {
+            return Err(format!("Invalid Opus bitrate: {} kbps (expected 6-510)", options.bitrate_kbps));
+        }
+        if options.complexity > 10 {
+            return Err(format!("Invalid Opus complexity: {} (expected 0-10)", options.complexity));
+        }
+        let encoder = Encoder::new(sample_rate, channels, options)?;
+        let pre_skip = encoder.lookahead()?;
+        let comments = vorbis_comment(&options.tags)?;
+
+        let file = File::create(path).map_err(|e| format!("Failed to create Opus file: {}", e))?;
+        let mut packets = PacketWriter::new(BufWriter::new(file));
+        let serial = rand::random::<u32>();
+
+        // RFC 7845: identification and comment headers each on their own page
+        let mut head = b"OpusHead".to_vec();
+        head.push(1);
+        head.push(channels as u8);
+        head.extend(pre_skip.to_le_bytes());
+        head.extend(sample_rate.to_le_bytes());
+        head.extend(0i16.to_le_bytes());
+        head.push(0);
+
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend(comments);
+
+        packets
+            .write_packet(head, serial, PacketWriteEndInfo::EndPage, 0)
+            .and_then(|_| packets.write_packet(tags, serial, PacketWriteEndInfo::EndPage, 0))
+            .map_err(|e| format!("Failed to write Opus headers: {}", e))?;
+
+        Ok(Self {
+            packets,
+            encoder,
+            serial,
+            channels: channels as usize,
+            pre_skip: pre_skip as u64,
+            pending: Vec::with_capacity(FRAME_SAMPLES * channels as usize),
+            packet: vec![0; MAX_PACKET_BYTES],
+            held: None,
+            encoded_samples: 0,
+            input_samples: 0,
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<(), String> {
+        let frame_len = FRAME_SAMPLES * self.channels;
+        self.input_samples += (samples.len() / self.channels) as u64;
+
+        let mut rest = samples;
+        while !rest.is_empty() {
+            let take = (frame_len - self.pending.len()).min(rest.len());
+            self.pending.extend_from_slice(&rest[..take]);
+            rest = &rest[take..];
+            if self.pending.len() == frame_len {
+                self.encode_pending()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_pending(&mut self) -> Result<(), String> {
+        let len = self.encoder.encode(&self.pending, self.channels, &mut self.packet)?;
+        self.pending.clear();
+        self.encoded_samples += FRAME_SAMPLES as u64;
+
+        let packet = self.packet[..len].to_vec();
+        if let Some((previous, granule)) = self.held.replace((packet, self.encoded_samples)) {
+            self.packets
+                .write_packet(previous, self.serial, PacketWriteEndInfo::NormalPacket, granule)
+                .map_err(|e| format!("Failed to write Opus packet: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Flush the encoder's lookahead and end the stream, trimming the padding.
+    pub fn finalize(mut self) -> Result<(), String> {
+        let end = self.pre_skip + self.input_samples;
+        while self.encoded_samples < end || !self.pending.is_empty() {
+            self.pending.resize(FRAME_SAMPLES * self.channels, 0);
+            self.encode_pending()?;
+        }
+
+        if let Some((last, _)) = self.held.take() {
+            self.packets
+                .write_packet(last, self.serial, PacketWriteEndInfo::EndStream, end)
+                .map_err(|e| format!("Failed to write Opus packet: {}", e))?;
+        }
+        self.packets
+            .into_inner()
+            .flush()
+            .map_err(|e| format!("Failed to flush Opus file: {}", e))
+    }
+}
@@ -0,0 +1,200 @@
+//! Append-only PCM storage for a generation session.
+//!
+//! Every sample is appended to one raw little-endian spill file, so a long session
+//! is a single file on disk and reading it back is one sequential read. The most
+//! recent samples are also kept in a fixed-size ring that readers on other threads
+//! can use without touching the disk or taking the streamer lock. The ring is a
+//! seqlock: the writer bumps `reserved` before overwriting slots and `written` once
+//! they hold the new data, and readers discard anything `reserved` has overtaken.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{fence, AtomicI16, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+struct Shared {
+    path: PathBuf,
+    ring: Box<[AtomicI16]>,
+    /// Samples the writer has started to store.
+    reserved: AtomicUsize,
+    /// Samples that are fully in the spill file and the ring.
+    written: AtomicUsize,
+}
+
+/// Single-writer store; owned by the `AudioStreamer`.
+pub struct PcmStore {
+    shared: Arc<Shared>,
+    file: File,
+}
+
+impl PcmStore {
+    /// Create `session.pcm` in `dir`, keeping the last `ring_capacity` samples in memory.
+    pub fn create(dir: &Path, ring_capacity: usize) -> Result<Self, String> {
+        let path = dir.join("session.pcm");
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to create PCM spill file: {}", e))?;
+
+        let ring = (0..ring_capacity.max(1)).map(|_| AtomicI16::new(0)).collect();
+
+        Ok(Self {
+            shared: Arc::new(Shared {
+                path,
+                ring,
+                reserved: AtomicUsize::new(0),
+                written: AtomicUsize::new(0),
+            }),
+            file,
+        })
+    }
+
+    pub fn append(&mut self, samples: &[i16]) -> Result<(), String> {
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        self.file
+            .write_all(&bytes)
+            .map_err(|e| format!("Failed to append to PCM spill file: {}", e))?;
+
+        let shared = &self.shared;
+        let start = shared.reserved.fetch_add(samples.len(), Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        let capacity = shared.ring.len();
+        let skip = samples.len().saturating_sub(capacity);
+        for (i, sample) in samples.iter().enumerate().skip(skip) {
+            shared.ring[(start + i) % capacity].store(*sample, Ordering::Relaxed);
+        }
+
+        shared.written.fetch_add(samples.len(), Ordering::Release);
+        Ok(())
+    }
+
+    /// Interleaved samples stored so far.
+    pub fn len(&self) -> usize {
+        self.shared.written.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A reader that can follow the store from another thread.
+    pub fn reader(&self) -> PcmReader {
+        PcmReader {
+            shared: Arc::clone(&self.shared),
+            file: None,
+            limit: usize::MAX,
+        }
+    }
+
+    /// A reader of the samples stored so far; later appends stay out of its view.
+    pub fn snapshot(&self) -> PcmReader {
+        PcmReader {
+            shared: Arc::clone(&self.shared),
+            file: None,
+            limit: self.len(),
+        }
+    }
+
+    /// Every stored sample, read back from the spill file in one pass.
+    pub fn read_all(&self) -> Result<Vec<i16>, String> {
+        let len = self.len();
+        let mut file = File::open(&self.shared.path)
+            .map_err(|e| format!("Failed to open PCM spill file: {}", e))?;
+        let mut bytes = vec![0u8; len * 2];
+        file.read_exact(&mut bytes)
+            .map_err(|e| format!("Failed to read PCM spill file: {}", e))?;
+
+        Ok(bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect())
+    }
+
+    /// Drop all samples. Readers must be stopped first.
+    pub fn clear(&mut self) -> Result<(), String> {
+        self.file
+            .set_len(0)
+            .and_then(|_| self.file.seek(SeekFrom::Start(0)))
+            .map_err(|e| format!("Failed to truncate PCM spill file: {}", e))?;
+        self.shared.reserved.store(0, Ordering::Relaxed);
+        self.shared.written.store(0, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Random-access view of a `PcmStore`, served from the ring when the range is still
+/// in memory and from the spill file otherwise.
+pub struct PcmReader {
+    shared: Arc<Shared>,
+    file: Option<File>,
+    /// Samples past this index are never read, even once they are written.
+    limit: usize,
+}
+
+impl Clone for PcmReader {
+    /// The clone opens its own handle on the spill file when it first needs one.
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+            file: None,
+            limit: self.limit,
+        }
+    }
+}
+
+impl PcmReader {
+    /// Interleaved samples available to read.
+    pub fn available(&self) -> usize {
+        self.shared.written.load(Ordering::Acquire).min(self.limit)
+    }
+
+    /// Copy samples starting at interleaved index `start` into `out`, returning how
+    /// many were available.
+    pub fn read(&mut self, start: usize, out: &mut [i16]) -> Result<usize, String> {
+        let available = self.available();
+        if start >= available {
+            return Ok(0);
+        }
+        let count = out.len().min(available - start);
+        let written = self.shared.written.load(Ordering::Acquire);
+        let out = &mut out[..count];
+
+        let ring = &self.shared.ring;
+        let capacity = ring.len();
+        if start + capacity >= written {
+            for (i, sample) in out.iter_mut().enumerate() {
+                *sample = ring[(start + i) % capacity].load(Ordering::Relaxed);
+            }
+            fence(Ordering::Acquire);
+            if start + capacity >= self.shared.reserved.load(Ordering::Relaxed) {
+                return Ok(count);
+            }
+        }
+
+        self.read_file(start, out)?;
+        Ok(count)
+    }
+
+    fn read_file(&mut self, start: usize, out: &mut [i16]) -> Result<(), String> {
+        if self.file.is_none() {
+            let file = File::open(&self.shared.path)
+                .map_err(|e| format!("Failed to open PCM spill file: {}", e))?;
+            self.file = Some(file);
+        }
+        let file = self.file.as_mut().expect("spill file opened above");
+
+        let mut bytes = vec![0u8; out.len() * 2];
+        file.seek(SeekFrom::Start(start as u64 * 2))
+            .and_then(|_| file.read_exact(&mut bytes))
+            .map_err(|e| format!("Failed to read PCM spill file: {}", e))?;
+
+        for (sample, b) in out.iter_mut().zip(bytes.chunks_exact(2)) {
+            *sample = i16::from_le_bytes([b[0], b[1]]);
+        }
+        Ok(())
+    }
+}
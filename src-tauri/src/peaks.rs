@@ -0,0 +1,172 @@
+//! Multi-resolution min/max/RMS overview of the recording, for drawing waveforms.
+//!
+//! Each chunk is folded into buckets of `BASE_FRAMES` frames as it is written, and
+//! every `FAN_OUT` buckets of one level are merged into a bucket of the next, so a
+//! query for any range and width combines a handful of buckets per pixel instead
+//! of reading the samples.
+
+use serde::Serialize;
+
+/// Frames in a bucket of the finest level.
+pub const BASE_FRAMES: usize = 256;
+/// Buckets of one level merged into each bucket of the next.
+const FAN_OUT: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    min: i16,
+    max: i16,
+    sum_squares: f64,
+    samples: usize,
+}
+
+impl Bucket {
+    const EMPTY: Self = Self {
+        min: i16::MAX,
+        max: i16::MIN,
+        sum_squares: 0.0,
+        samples: 0,
+    };
+
+    fn add(&mut self, sample: i16) {
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+        self.sum_squares += sample as f64 * sample as f64;
+        self.samples += 1;
+    }
+
+    fn merge(&mut self, other: &Bucket) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum_squares += other.sum_squares;
+        self.samples += other.samples;
+    }
+}
+
+/// Peaks across all channels, one entry per pixel, scaled to -1..1.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Peaks {
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+    pub rms: Vec<f32>,
+}
+
+impl Peaks {
+    fn push(&mut self, bucket: &Bucket) {
+        if bucket.samples == 0 {
+            self.min.push(0.0);
+            self.max.push(0.0);
+            self.rms.push(0.0);
+            return;
+        }
+        self.min.push(bucket.min as f32 / 32768.0);
+        self.max.push(bucket.max as f32 / 32768.0);
+        self.rms.push(((bucket.sum_squares / bucket.samples as f64).sqrt() / 32768.0) as f32);
+    }
+
+    /// Peaks of interleaved `samples` split evenly into `pixels`.
+    pub fn from_samples(samples: &[i16], channels: u16, pixels: usize) -> Self {
+        let channels = channels as usize;
+        let frames = samples.len() / channels;
+        let mut peaks = Self::default();
+        for pixel in 0..pixels {
+            let (start, end) = pixel_range(0, frames, pixel, pixels);
+            let mut bucket = Bucket::EMPTY;
+            samples[start * channels..end * channels].iter().for_each(|&s| bucket.add(s));
+            peaks.push(&bucket);
+        }
+        peaks
+    }
+}
+
+/// Frames covered by `pixel` when `start..end` is spread over `pixels`.
+fn pixel_range(start: usize, end: usize, pixel: usize, pixels: usize) -> (usize, usize) {
+    let len = end - start;
+    let from = start + len * pixel / pixels;
+    let to = start + len * (pixel + 1) / pixels;
+    (from, to.max(from + 1).min(end))
+}
+
+pub struct PeakCache {
+    channels: usize,
+    /// `levels[n]` holds buckets of `BASE_FRAMES * FAN_OUT^n` frames.
+    levels: Vec<Vec<Bucket>>,
+    /// The unfinished bucket at the end of the finest level.
+    pending: Bucket,
+}
+
+impl PeakCache {
+    pub fn new(channels: u16) -> Self {
+        Self {
+            channels: channels as usize,
+            levels: vec![Vec::new()],
+            pending: Bucket::EMPTY,
+        }
+    }
+
+    pub fn add(&mut self, samples: &[i16]) {
+        let bucket_samples = BASE_FRAMES * self.channels;
+        for &sample in samples {
+            self.pending.add(sample);
+            if self.pending.samples == bucket_samples {
+                let bucket = std::mem::replace(&mut self.pending, Bucket::EMPTY);
+                self.push(0, bucket);
+            }
+        }
+    }
+
+    fn push(&mut self, level: usize, bucket: Bucket) {
+        if self.levels.len() == level {
+            self.levels.push(Vec::new());
+        }
+        let buckets = &mut self.levels[level];
+        buckets.push(bucket);
+        if buckets.len() % FAN_OUT == 0 {
+            let mut merged = Bucket::EMPTY;
+            buckets[buckets.len() - FAN_OUT..].iter().for_each(|b| merged.merge(b));
+            self.push(level + 1, merged);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.levels = vec![Vec::new()];
+        self.pending = Bucket::EMPTY;
+    }
+
+    /// Peaks of frames `start..end` spread over `pixels`. Each pixel covers the
+    /// whole finest-level buckets its range touches, so edges are accurate to
+    /// `BASE_FRAMES`.
+    pub fn peaks(&self, start: usize, end: usize, pixels: usize) -> Peaks {
+        let mut peaks = Peaks::default();
+        for pixel in 0..pixels {
+            let (from, to) = pixel_range(start, end, pixel, pixels);
+            peaks.push(&self.range(from / BASE_FRAMES, to.div_ceil(BASE_FRAMES)));
+        }
+        peaks
+    }
+
+    /// Merge finest-level buckets `first..last`, using the coarsest buckets that
+    /// fit inside the range.
+    fn range(&self, first: usize, last: usize) -> Bucket {
+        let mut merged = Bucket::EMPTY;
+        let mut index = first;
+        while index < last {
+            if index >= self.levels[0].len() {
+                merged.merge(&self.pending);
+                break;
+            }
+            let (mut level, mut span) = (0, 1);
+            while level + 1 < self.levels.len() {
+                let next = span * FAN_OUT;
+                if index % next != 0 || index + next > last || index / next >= self.levels[level + 1].len() {
+                    break;
+                }
+                level += 1;
+                span = next;
+            }
+            merged.merge(&self.levels[level][index / span]);
+            index += span;
+        }
+        merged
+    }
+}
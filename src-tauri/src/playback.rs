@@ -0,0 +1,379 @@
+//! rodio sources that play straight from the session's `PcmStore`, with its
+//! edits applied. Positions are interleaved indices on the edited timeline.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::analysis::AnalysisTap;
+use crate::edits::EditedReader;
+
+/// Interleaved samples pulled from the store per refill. Small enough that newly
+/// written audio is picked up quickly, large enough to amortise the read.
+const REFILL_SAMPLES: usize = 4096;
+/// Silence inserted per refill while the store has nothing new, in milliseconds.
+const UNDERRUN_SILENCE_MS: usize = 10;
+/// Stop waiting for more audio after this long without any, for producers that
+/// never signal the end of the stream.
+const STARVATION_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often, in delivered samples, the output clock records a timestamp.
+const CLOCK_MARK_SAMPLES: usize = 256;
+/// Frames handed to the analysis tap at once, to keep its lock off the per-sample path.
+const TAP_BATCH_FRAMES: usize = 256;
+/// `pending_seek` value when no seek has been requested.
+const NO_SEEK: usize = usize::MAX;
+
+/// State shared between the sources on the audio thread and the streamer.
+pub struct StreamState {
+    /// Ask the source to end at the next refill.
+    pub stop: AtomicBool,
+    /// No more audio will be written; the source ends once it has played everything.
+    pub input_finished: AtomicBool,
+    /// The producer has stopped writing for now but will carry on, so running out
+    /// of audio doesn't count towards the starvation timeout.
+    pub input_paused: AtomicBool,
+    /// Times playback ran out of audio and had to insert silence.
+    pub underruns: AtomicUsize,
+    /// Interleaved index on the timeline of the next sample the output will receive.
+    position: AtomicUsize,
+    /// Where to jump at the next refill, or `NO_SEEK`.
+    pending_seek: AtomicUsize,
+    /// Loop region as interleaved indices; inactive while `loop_end` is zero.
+    loop_start: AtomicUsize,
+    loop_end: AtomicUsize,
+    /// Playback has wrapped around the loop since the last seek.
+    looped: AtomicBool,
+    /// Interleaved samples handed to the output device, silence included.
+    delivered: AtomicUsize,
+    /// `delivered` and the time it was recorded, for interpolating between output callbacks.
+    mark_delivered: AtomicUsize,
+    mark_nanos: AtomicU64,
+    epoch: Instant,
+}
+
+impl StreamState {
+    pub fn new(start_offset: usize) -> Self {
+        Self {
+            stop: AtomicBool::new(false),
+            input_finished: AtomicBool::new(false),
+            input_paused: AtomicBool::new(false),
+            underruns: AtomicUsize::new(0),
+            position: AtomicUsize::new(start_offset),
+            pending_seek: AtomicUsize::new(NO_SEEK),
+            loop_start: AtomicUsize::new(0),
+            loop_end: AtomicUsize::new(0),
+            looped: AtomicBool::new(false),
+            delivered: AtomicUsize::new(0),
+            mark_delivered: AtomicUsize::new(0),
+            mark_nanos: AtomicU64::new(0),
+            epoch: Instant::now(),
+        }
+    }
+
+    fn mark(&self, delivered: usize) {
+        self.mark_delivered.store(delivered, Ordering::Relaxed);
+        self.mark_nanos.store(self.epoch.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Interleaved index on the timeline of the next sample the output will receive.
+    pub fn position(&self) -> usize {
+        self.position.load(Ordering::Relaxed)
+    }
+
+    /// Interleaved index of the sample coming out of the speakers now: the output
+    /// clock interpolated since its last callback, minus the device latency.
+    pub fn heard_position(&self, samples_per_second: f64, latency: Duration) -> usize {
+        let delivered = self.delivered.load(Ordering::Relaxed);
+        let lag = delivered - self.heard_samples(samples_per_second, latency).min(delivered);
+
+        // Walk back from the output head, wrapping into the loop if it was just crossed
+        let position = self.position();
+        match self.loop_region() {
+            Some((start, end)) if self.looped.load(Ordering::Relaxed) && position >= start && position - start < lag => {
+                let wrapped = (lag - (position - start)) % (end - start);
+                end - wrapped
+            }
+            _ => position.saturating_sub(lag),
+        }
+    }
+
+    /// Samples delivered to the output that have come out of the speakers by now,
+    /// silence included.
+    pub fn heard_samples(&self, samples_per_second: f64, latency: Duration) -> usize {
+        let delivered = self.delivered.load(Ordering::Relaxed);
+        let mark = self.mark_delivered.load(Ordering::Relaxed);
+        let mark_nanos = self.mark_nanos.load(Ordering::Relaxed);
+
+        let since_mark = self.epoch.elapsed().saturating_sub(Duration::from_nanos(mark_nanos));
+        let estimated = mark as f64 + (since_mark.as_secs_f64() - latency.as_secs_f64()) * samples_per_second;
+        (estimated.max(0.0) as usize).min(delivered)
+    }
+
+    /// Jump to interleaved index `offset` at the next sample.
+    pub fn seek(&self, offset: usize) {
+        self.pending_seek.store(offset, Ordering::Relaxed);
+        self.position.store(offset, Ordering::Relaxed);
+    }
+
+    /// Repeat `start..end` (interleaved indices) once playback reaches `end`.
+    pub fn set_loop(&self, region: Option<(usize, usize)>) {
+        let (start, end) = region.unwrap_or((0, 0));
+        self.loop_end.store(0, Ordering::Relaxed);
+        self.loop_start.store(start, Ordering::Relaxed);
+        self.loop_end.store(end, Ordering::Relaxed);
+    }
+
+    pub fn loop_region(&self) -> Option<(usize, usize)> {
+        let end = self.loop_end.load(Ordering::Relaxed);
+        let start = self.loop_start.load(Ordering::Relaxed);
+        (end > start).then_some((start, end))
+    }
+}
+
+/// Plays the session from the state's position onwards, following it as new
+/// chunks arrive and honouring seeks and the loop region.
+/// When playback catches up with the writer it inserts short runs of silence
+/// rather than ending, so live generation plays continuously.
+pub struct StreamingSource {
+    reader: EditedReader,
+    state: Arc<StreamState>,
+    channels: u16,
+    sample_rate: u32,
+    /// Interleaved index of the next sample to read from the timeline.
+    offset: usize,
+    buffer: Vec<i16>,
+    /// Interleaved index on the timeline of `buffer[0]`.
+    buffer_start: usize,
+    position: usize,
+    len: usize,
+    silent: bool,
+    starving_since: Option<Instant>,
+}
+
+impl StreamingSource {
+    pub fn new(reader: EditedReader, state: Arc<StreamState>, channels: u16, sample_rate: u32) -> Self {
+        let offset = state.position();
+        Self {
+            reader,
+            state,
+            channels,
+            sample_rate,
+            offset,
+            buffer: vec![0; REFILL_SAMPLES],
+            buffer_start: offset,
+            position: 0,
+            len: 0,
+            silent: false,
+            starving_since: None,
+        }
+    }
+
+    /// Refill the buffer with stored audio or silence. Returns false once the
+    /// stream has ended.
+    fn refill(&mut self) -> bool {
+        if self.state.stop.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let channels = self.channels as usize;
+        let seek = self.state.pending_seek.swap(NO_SEEK, Ordering::Relaxed);
+        if seek != NO_SEEK {
+            self.offset = seek / channels * channels;
+            self.state.looped.store(false, Ordering::Relaxed);
+        }
+
+        let finished = self.state.input_finished.load(Ordering::Relaxed);
+        let available = self.reader.available();
+        let mut end = available;
+        if let Some((start, mut loop_end)) = self.state.loop_region() {
+            // A loop running past the end of a finished session wraps at the last sample
+            if finished {
+                loop_end = loop_end.min(available);
+            }
+            if self.offset >= loop_end && start < loop_end {
+                self.offset = start;
+                self.state.looped.store(true, Ordering::Relaxed);
+            }
+            end = end.min(loop_end);
+        }
+        let want = REFILL_SAMPLES.min(end.saturating_sub(self.offset)) / channels * channels;
+
+        if want > 0 {
+            match self.reader.read(self.offset, &mut self.buffer[..want], finished) {
+                Ok(read) if read > 0 => {
+                    self.buffer_start = self.offset;
+                    self.offset += read;
+                    self.position = 0;
+                    self.len = read;
+                    self.silent = false;
+                    self.starving_since = None;
+                    return true;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    log::error!("Streaming playback read failed: {}", e);
+                    return false;
+                }
+            }
+        }
+
+        if self.state.input_finished.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let now = Instant::now();
+        match self.starving_since {
+            None => {
+                self.state.underruns.fetch_add(1, Ordering::Relaxed);
+                self.starving_since = Some(now);
+            }
+            // However long the producer takes to come back, the wait counts from then
+            Some(_) if self.state.input_paused.load(Ordering::Relaxed) => {
+                self.starving_since = Some(now);
+            }
+            Some(since) if now.duration_since(since) > STARVATION_TIMEOUT => {
+                log::info!("No new audio for {:?}, ending playback", STARVATION_TIMEOUT);
+                return false;
+            }
+            Some(_) => {}
+        }
+
+        let silence = (self.sample_rate as usize * UNDERRUN_SILENCE_MS / 1000 * channels).min(self.buffer.len());
+        self.buffer[..silence].fill(0);
+        self.position = 0;
+        self.len = silence;
+        self.silent = true;
+        true
+    }
+}
+
+impl Iterator for StreamingSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        // A seek drops whatever is left of the current buffer
+        let exhausted = self.position >= self.len || self.state.pending_seek.load(Ordering::Relaxed) != NO_SEEK;
+        if exhausted && !self.refill() {
+            return None;
+        }
+        let sample = self.buffer[self.position];
+        self.position += 1;
+        if !self.silent {
+            self.state.position.store(self.buffer_start + self.position, Ordering::Relaxed);
+        }
+        Some(sample)
+    }
+}
+
+impl rodio::Source for StreamingSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Copies the samples the output pulls through it into an `AnalysisTap`, for
+/// metering what is being played.
+pub struct Tapped<S> {
+    inner: S,
+    tap: Arc<AnalysisTap>,
+    batch: Vec<i16>,
+}
+
+impl<S: rodio::Source<Item = i16>> Tapped<S> {
+    pub fn new(inner: S, tap: Arc<AnalysisTap>) -> Self {
+        let batch = Vec::with_capacity(TAP_BATCH_FRAMES * inner.channels() as usize);
+        Self { inner, tap, batch }
+    }
+}
+
+impl<S: rodio::Source<Item = i16>> Iterator for Tapped<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let Some(sample) = self.inner.next() else {
+            self.tap.push(&self.batch);
+            self.batch.clear();
+            return None;
+        };
+        self.batch.push(sample);
+        if self.batch.len() == TAP_BATCH_FRAMES * self.inner.channels() as usize {
+            self.tap.push(&self.batch);
+            self.batch.clear();
+        }
+        Some(sample)
+    }
+}
+
+impl<S: rodio::Source<Item = i16>> rodio::Source for Tapped<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+/// Counts the samples the output pulls through it, which is what drives the
+/// playback position. The sink stops pulling while paused, so pauses are not counted.
+pub struct Counting<S> {
+    inner: S,
+    state: Arc<StreamState>,
+}
+
+impl<S> Counting<S> {
+    pub fn new(inner: S, state: Arc<StreamState>) -> Self {
+        Self { inner, state }
+    }
+}
+
+impl<S: rodio::Source<Item = i16>> Iterator for Counting<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.inner.next()?;
+        let delivered = self.state.delivered.fetch_add(1, Ordering::Relaxed) + 1;
+        if delivered % CLOCK_MARK_SAMPLES == 0 {
+            self.state.mark(delivered);
+        }
+        Some(sample)
+    }
+}
+
+impl<S: rodio::Source<Item = i16>> rodio::Source for Counting<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
@@ -0,0 +1,208 @@
+//! Sample-rate conversion with a Kaiser-windowed sinc interpolator.
+//!
+//! Streams: each `process` call emits every output frame whose filter is already
+//! covered by the input received, and `finish` flushes the rest against silence.
+//! Output frame `n` lands exactly on input time `n * from / to`, tracked as a
+//! ratio of integers so long sessions never drift.
+
+/// Zero crossings of the sinc on each side of its centre.
+const ZERO_CROSSINGS: f64 = 16.0;
+/// Kernel values tabulated per input frame, interpolated linearly between.
+const PHASES: usize = 512;
+/// Window shape, for roughly 80 dB of stopband rejection.
+const KAISER_BETA: f64 = 8.0;
+/// Passband edge as a fraction of the lower of the two Nyquist frequencies.
+const PASSBAND: f64 = 0.95;
+
+pub struct Resampler {
+    channels: usize,
+    /// Conversion ratio `from:to`, in lowest terms.
+    from: u64,
+    to: u64,
+    /// Frames either side of an output frame that reach into it.
+    half_width: usize,
+    table: Vec<f32>,
+    /// Interleaved input not yet behind every output frame still to come.
+    input: Vec<f32>,
+    /// Input frame number of `input[0]`; negative for the silence before the start.
+    input_start: i64,
+    received: u64,
+    produced: u64,
+    weights: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(from: u32, to: u32, channels: u16) -> Result<Self, String> {
+        if from == 0 || to == 0 || channels == 0 {
+            return Err(format!("Invalid resampling: {} Hz to {} Hz, {} channels", from, to, channels));
+        }
+        let divisor = gcd(from as u64, to as u64);
+        let cutoff = PASSBAND * (to as f64 / from as f64).min(1.0);
+        let reach = ZERO_CROSSINGS / cutoff;
+        let half_width = reach.ceil() as usize;
+
+        let table = (0..=half_width * PHASES + 1)
+            .map(|i| {
+                let t = i as f64 / PHASES as f64;
+                if t >= reach {
+                    return 0.0;
+                }
+                let x = std::f64::consts::PI * cutoff * t;
+                let sinc = if x == 0.0 { 1.0 } else { x.sin() / x };
+                let window = bessel_i0(KAISER_BETA * (1.0 - (t / reach).powi(2)).sqrt()) / bessel_i0(KAISER_BETA);
+                (cutoff * sinc * window) as f32
+            })
+            .collect();
+
+        Ok(Self {
+            channels: channels as usize,
+            from: from as u64 / divisor,
+            to: to as u64 / divisor,
+            half_width,
+            table,
+            input: vec![0.0; half_width * channels as usize],
+            input_start: -(half_width as i64),
+            received: 0,
+            produced: 0,
+            weights: vec![0.0; half_width * 2],
+        })
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.from == self.to
+    }
+
+    /// Output frames made from `frames` input frames, once finished.
+    pub fn output_frames(&self, frames: u64) -> u64 {
+        (frames * self.to).div_ceil(self.from)
+    }
+
+    /// Resample interleaved `input`, appending what can be produced so far to `out`.
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        if self.is_identity() {
+            out.extend_from_slice(input);
+            return;
+        }
+        self.input.extend_from_slice(input);
+        self.received += (input.len() / self.channels) as u64;
+        self.emit(self.output_frames(self.received), out);
+    }
+
+    /// Flush the frames still held back waiting for input that will never come.
+    pub fn finish(&mut self, out: &mut Vec<f32>) {
+        if self.is_identity() {
+            return;
+        }
+        self.input.resize(self.input.len() + (self.half_width + 1) * self.channels, 0.0);
+        let total = self.output_frames(self.received);
+        self.emit(total, out);
+    }
+
+    /// Produce output frames up to `limit` while the input covers their filter.
+    fn emit(&mut self, limit: u64, out: &mut Vec<f32>) {
+        let half_width = self.half_width as i64;
+        let held = self.input_start + (self.input.len() / self.channels) as i64;
+
+        while self.produced < limit {
+            let position = self.produced * self.from;
+            let centre = (position / self.to) as i64;
+            let fraction = (position % self.to) as f64 / self.to as f64;
+            if centre + half_width >= held {
+                break;
+            }
+
+            // Input frames centre-half_width+1 ..= centre+half_width, nearest last
+            for (i, weight) in self.weights.iter_mut().enumerate() {
+                let distance = (half_width - 1 - i as i64) as f64 + fraction;
+                *weight = kernel(&self.table, distance.abs());
+            }
+            let first = (centre - half_width + 1 - self.input_start) as usize * self.channels;
+            for c in 0..self.channels {
+                let sum: f32 = self
+                    .weights
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &w)| self.input[first + i * self.channels + c] * w)
+                    .sum();
+                out.push(sum);
+            }
+            self.produced += 1;
+        }
+
+        // Drop input no later output frame reaches back to
+        let next = (self.produced * self.from / self.to) as i64 - half_width + 1;
+        let drop = (next - self.input_start).clamp(0, (self.input.len() / self.channels) as i64);
+        self.input.drain(..drop as usize * self.channels);
+        self.input_start += drop;
+    }
+}
+
+/// Tabulated kernel at `distance` input frames from its centre.
+fn kernel(table: &[f32], distance: f64) -> f32 {
+    let at = distance * PHASES as f64;
+    let i = at as usize;
+    if i + 1 >= table.len() {
+        return 0.0;
+    }
+    let t = (at - i as f64) as f32;
+    table[i] + (table[i + 1] - table[i]) * t
+}
+
+/// Resample a whole interleaved buffer at once.
+pub fn resample(samples: &[f32], from: u32, to: u32, channels: u16) -> Result<Vec<f32>, String> {
+    let mut resampler = Resampler::new(from, to, channels)?;
+    let frames = (samples.len() / channels as usize) as u64;
+    let mut out = Vec::with_capacity(resampler.output_frames(frames) as usize * channels as usize);
+    resampler.process(samples, &mut out);
+    resampler.finish(&mut out);
+    Ok(out)
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Zeroth-order modified Bessel function of the first kind.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..50 {
+        term *= (x / 2.0 / k as f64).powi(2);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Resample an interleaved loop as if it repeated forever, so its end still runs
+/// straight into its start.
+pub fn resample_loop(samples: &[f32], from: u32, to: u32, channels: u16) -> Result<Vec<f32>, String> {
+    let mut resampler = Resampler::new(from, to, channels)?;
+    let channels = channels as usize;
+    let frames = samples.len() / channels;
+    if resampler.is_identity() || frames == 0 {
+        return Ok(samples.to_vec());
+    }
+
+    // Wrap enough of the loop round each end to fill the filter, in whole steps of
+    // the ratio so the loop's first frame lands exactly on an output frame
+    let pad = (resampler.half_width + 1).div_ceil(resampler.from as usize) * resampler.from as usize;
+    let mut wrapped = Vec::with_capacity((frames + pad * 2) * channels);
+    for i in 0..frames + pad * 2 {
+        let frame = (i + frames - pad % frames) % frames;
+        wrapped.extend_from_slice(&samples[frame * channels..(frame + 1) * channels]);
+    }
+    let mut out = Vec::with_capacity(resampler.output_frames(wrapped.len() as u64 / channels as u64) as usize * channels);
+    resampler.process(&wrapped, &mut out);
+    resampler.finish(&mut out);
+
+    let skip = (pad as u64 / resampler.from * resampler.to) as usize;
+    let keep = resampler.output_frames(frames as u64) as usize;
+    Ok(out[skip * channels..(skip + keep) * channels].to_vec())
+}
@@ -0,0 +1,110 @@
+//! Moving session audio between Rust and the webview as raw bytes instead of JSON.
+//!
+//! Commands exchange little-endian 16-bit PCM as binary IPC payloads, and the
+//! `lyria-audio` URI scheme serves the edited session as PCM or WAV with HTTP
+//! range support, so `fetch` or an `<audio>` element can stream it a piece at a
+//! time while it is still being recorded.
+
+/// URI scheme registered for session audio.
+pub const SCHEME: &str = "lyria-audio";
+/// Most bytes served for one request, ranged or not; players ask again for the rest.
+pub const MAX_RANGE_BYTES: u64 = 4 * 1024 * 1024;
+/// Size of the canonical header in front of a served WAV's samples.
+pub const WAV_HEADER_BYTES: u64 = 44;
+
+/// Session audio served over the URI scheme.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AudioResource {
+    /// Bare interleaved little-endian 16-bit samples.
+    Pcm,
+    /// The same samples behind a WAV header.
+    Wav,
+}
+
+impl AudioResource {
+    /// The resource at a URI path such as `/session.wav`.
+    pub fn from_path(path: &str) -> Option<Self> {
+        match path.trim_start_matches('/') {
+            "session.pcm" => Some(Self::Pcm),
+            "session.wav" => Some(Self::Wav),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Pcm => "application/octet-stream",
+            Self::Wav => "audio/wav",
+        }
+    }
+
+    /// Bytes in front of the samples.
+    pub fn header_len(self) -> u64 {
+        match self {
+            Self::Pcm => 0,
+            Self::Wav => WAV_HEADER_BYTES,
+        }
+    }
+}
+
+/// Byte range `start..end` of a `total`-byte body requested by a `Range` header,
+/// cut to `MAX_RANGE_BYTES`. Only single `bytes=` ranges are supported.
+pub fn parse_range(header: &str, total: u64) -> Result<(u64, u64), String> {
+    let invalid = || format!("Unsupported range: {}", header);
+    let spec = header.trim().strip_prefix("bytes=").ok_or_else(invalid)?;
+    let (from, to) = spec.split_once('-').ok_or_else(invalid)?;
+    let (from, to) = (from.trim(), to.trim());
+    let parse = |n: &str| n.parse::<u64>().map_err(|_| invalid());
+
+    let (start, end) = if from.is_empty() {
+        // The last `to` bytes
+        let suffix = parse(to)?;
+        if suffix == 0 {
+            return Err(invalid());
+        }
+        (total.saturating_sub(suffix), total)
+    } else {
+        let start = parse(from)?;
+        let end = if to.is_empty() { total } else { parse(to)?.saturating_add(1).min(total) };
+        if end <= start {
+            return Err(format!("Range {} is outside the {} bytes available", header, total));
+        }
+        (start, end)
+    };
+    if start >= total {
+        return Err(format!("Range {} is outside the {} bytes available", header, total));
+    }
+    Ok((start, end.min(start + MAX_RANGE_BYTES)))
+}
+
+/// Canonical 16-bit PCM WAV header for `data_len` bytes of samples.
+pub fn wav_header(data_len: u64, sample_rate: u32, channels: u16) -> Vec<u8> {
+    let data_len = data_len.min(u32::MAX as u64 - WAV_HEADER_BYTES) as u32;
+    let block_align = channels * 2;
+    let mut header = Vec::with_capacity(WAV_HEADER_BYTES as usize);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(data_len + WAV_HEADER_BYTES as u32 - 8).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    header
+}
+
+/// Interleaved samples from little-endian 16-bit PCM bytes.
+pub fn samples_from_bytes(bytes: &[u8]) -> Result<Vec<i16>, String> {
+    if bytes.len() % 2 != 0 {
+        return Err(format!("PCM payload of {} bytes is not whole 16-bit samples", bytes.len()));
+    }
+    Ok(bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect())
+}
+
+pub fn samples_to_bytes(samples: &[i16]) -> Vec<u8> {
+    samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+}
@@ -0,0 +1,101 @@
+//! Ogg Vorbis export through libvorbis (aoTuV) bundled by `vorbis_rs`.
+
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::num::{NonZeroU32, NonZeroU8};
+use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoder, VorbisEncoderBuilder};
+
+/// Frames handed to libvorbis at a time; its documentation suggests 1024.
+const BLOCK_FRAMES: usize = 1024;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct VorbisOptions {
+    /// Quality-based VBR, -0.2 to 1.0. Takes precedence over `bitrate_kbps`.
+    pub quality: Option<f32>,
+    /// Average bitrate in kbps, used when no quality is given.
+    pub bitrate_kbps: Option<u32>,
+    pub tags: Vec<(String, String)>,
+}
+
+impl Default for VorbisOptions {
+    fn default() -> Self {
+        Self {
+            quality: Some(0.5),
+            bitrate_kbps: None,
+            tags: Vec::new(),
+        }
+    }
+}
+
+impl VorbisOptions {
+    fn strategy(&self) -> Result<VorbisBitrateManagementStrategy, String> {
+        match (self.quality, self.bitrate_kbps) {
+            (Some(quality), _) if (-0.2..=1.0).contains(&quality) => Ok(VorbisBitrateManagementStrategy::QualityVbr {
+                target_quality: quality,
+            }),
+            (Some(quality), _) => Err(format!("Invalid Vorbis quality: {} (expected -0.2 to 1.0)", quality)),
+            (None, Some(kbps)) => NonZeroU32::new(kbps * 1000)
+                .map(|average_bitrate| VorbisBitrateManagementStrategy::Abr { average_bitrate })
+                .ok_or_else(|| "Vorbis bitrate must be above zero".to_string()),
+            (None, None) => Ok(VorbisBitrateManagementStrategy::default()),
+        }
+    }
+}
+
+/// Writes interleaved 16-bit samples to an Ogg Vorbis file.
+pub struct VorbisWriter {
+    encoder: VorbisEncoder<BufWriter<File>>,
+    channels: usize,
+    planar: Vec<Vec<f32>>,
+}
+
+impl VorbisWriter {
+    pub fn create(path: &str, sample_rate: u32, channels: u16, options: &VorbisOptions) -> Result<Self, String> {
+        let strategy = options.strategy()?;
+        let rate = NonZeroU32::new(sample_rate).ok_or("Sample rate must be above zero")?;
+        let channel_count = u8::try_from(channels)
+            .ok()
+            .and_then(NonZeroU8::new)
+            .ok_or_else(|| format!("Unsupported channel count for Vorbis: {}", channels))?;
+
+        let file = File::create(path).map_err(|e| format!("Failed to create Vorbis file: {}", e))?;
+        let mut builder = VorbisEncoderBuilder::new(rate, channel_count, BufWriter::new(file))
+            .map_err(|e| format!("Failed to create Vorbis encoder: {}", e))?;
+        builder.bitrate_management_strategy(strategy);
+        builder
+            .comment_tags(options.tags.iter().map(|(field, value)| (field.as_str(), value.as_str())))
+            .map_err(|e| format!("Invalid Vorbis tag: {}", e))?;
+        let encoder = builder
+            .build()
+            .map_err(|e| format!("Failed to create Vorbis encoder: {}", e))?;
+
+        Ok(Self {
+            encoder,
+            channels: channels as usize,
+            planar: vec![Vec::with_capacity(BLOCK_FRAMES); channels as usize],
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<(), String> {
+        for block in samples.chunks(BLOCK_FRAMES * self.channels) {
+            for (channel, planar) in self.planar.iter_mut().enumerate() {
+                planar.clear();
+                planar.extend(block.iter().skip(channel).step_by(self.channels).map(|&s| s as f32 / 32768.0));
+            }
+            self.encoder
+                .encode_audio_block(&self.planar)
+                .map_err(|e| format!("Failed to encode Vorbis audio: {}", e))?;
+        }
+        Ok(())
+    }
+
+    pub fn finalize(self) -> Result<(), String> {
+        self.encoder
+            .finish()
+            .map_err(|e| format!("Failed to finish Vorbis stream: {}", e))?
+            .flush()
+            .map_err(|e| format!("Failed to flush Vorbis file: {}", e))
+    }
+}
@@ -0,0 +1,112 @@
+//! Checks the playback meters against signals with known levels and spectra, and
+//! that the tap hands the meter exactly the audio played since its last reading.
+
+mod common;
+
+use lyria_studio_lib::analysis::{AnalysisOptions, AnalysisTap, Levels, Meter, Spectrum, MIN_DB};
+use lyria_studio_lib::audio_stream::{get_streamer, init_streamer};
+
+const RATE: u32 = 48_000;
+
+/// Stereo sine at `hz` and `amplitude` (0-1), with the right channel scaled by `right`.
+fn sine(hz: f64, amplitude: f64, right: f64, frames: usize) -> Vec<i16> {
+    (0..frames)
+        .flat_map(|i| {
+            let s = (i as f64 * hz * std::f64::consts::TAU / RATE as f64).sin() * amplitude * 32767.0;
+            [s.round() as i16, (s * right).round() as i16]
+        })
+        .collect()
+}
+
+/// Band that `hz` falls in when 20 Hz to Nyquist is split into `bands` log-spaced bands.
+fn band_of(hz: f64, bands: usize) -> usize {
+    let nyquist = RATE as f64 / 2.0;
+    ((hz / 20.0).ln() / (nyquist / 20.0).ln() * bands as f64) as usize
+}
+
+#[test]
+fn levels_of_known_signals() {
+    let full = Levels::measure(&sine(1000.0, 1.0, 1.0, 4800), 2);
+    assert!(full.peak_db.iter().all(|&p| p > -0.01), "{:?}", full.peak_db);
+    assert!(full.rms_db.iter().all(|&r| (r + 3.01).abs() < 0.05), "{:?}", full.rms_db);
+    assert!((full.correlation - 1.0).abs() < 1e-4);
+
+    let quiet = Levels::measure(&sine(1000.0, 0.1, -0.5, 4800), 2);
+    assert!((quiet.peak_db[0] + 20.0).abs() < 0.05);
+    assert!((quiet.peak_db[1] + 26.02).abs() < 0.05);
+    assert!((quiet.correlation + 1.0).abs() < 1e-4, "out of phase: {}", quiet.correlation);
+
+    let silence = Levels::measure(&[0; 960], 2);
+    assert_eq!(silence.peak_db, vec![MIN_DB; 2]);
+    assert_eq!(silence.rms_db, vec![MIN_DB; 2]);
+    assert_eq!(silence.correlation, 0.0);
+    assert_eq!(Levels::measure(&[100; 480], 1).correlation, 1.0);
+}
+
+#[test]
+fn spectrum_peaks_at_the_tone() {
+    let options = AnalysisOptions::default();
+    let mut spectrum = Spectrum::new(RATE, &options).unwrap();
+
+    for hz in [100.0, 1000.0, 8000.0] {
+        let bands = spectrum.compute(&sine(hz, 0.5, 1.0, 4096), 2);
+        assert_eq!(bands.len(), options.bands);
+        let loudest = (0..bands.len()).max_by(|&a, &b| bands[a].total_cmp(&bands[b])).unwrap();
+        assert!(loudest.abs_diff(band_of(hz, options.bands)) <= 1, "{} Hz peaked in band {}", hz, loudest);
+        // A half-scale sine is 6 dB down, less at most 1.5 dB of Hann scalloping
+        assert!((-7.6..=-5.9).contains(&bands[loudest]), "{} Hz at {} dB", hz, bands[loudest]);
+        let far = band_of(if hz < 1000.0 { 10_000.0 } else { 40.0 }, options.bands);
+        assert!(bands[far] < -60.0, "{} Hz leaked {} dB into band {}", hz, bands[far], far);
+    }
+
+    assert!(spectrum.compute(&[], 2).iter().all(|&b| b == MIN_DB));
+    assert!(Spectrum::new(RATE, &AnalysisOptions { fft_size: 1000, ..Default::default() }).is_err());
+    assert!(Spectrum::new(RATE, &AnalysisOptions { bands: 0, ..Default::default() }).is_err());
+}
+
+#[test]
+fn meter_reads_what_was_heard_since_last_time() {
+    let tap = AnalysisTap::new(RATE as usize, 2);
+    let mut meter = Meter::new(RATE, 2, &AnalysisOptions::default()).unwrap();
+    assert!(meter.read(&tap, 0).is_none());
+
+    // Loud, then quiet: a reading after the quiet part only covers the quiet part
+    tap.push(&sine(1000.0, 1.0, 1.0, 4800));
+    let (loud, _) = meter.read(&tap, 4800 * 2).unwrap();
+    assert!(loud.peak_db[0] > -0.1);
+    tap.push(&sine(1000.0, 0.01, 1.0, 4800));
+    // Nothing heard yet beyond the last reading
+    assert!(meter.read(&tap, 4800 * 2).is_none());
+    let (quiet, spectrum) = meter.read(&tap, 9600 * 2).unwrap();
+    assert!((quiet.peak_db[0] + 40.0).abs() < 0.1, "{:?}", quiet.peak_db);
+    assert!(spectrum.iter().all(|&b| b < -30.0));
+
+    // Only the last second is kept, in whole frames
+    tap.push(&sine(1000.0, 0.5, 1.0, RATE as usize * 2 + 1)[..RATE as usize * 4 + 1]);
+    let mut copy = Vec::new();
+    tap.copy(0, tap.written(), &mut copy);
+    assert_eq!(copy.len(), RATE as usize * 2 - 2);
+}
+
+#[test]
+fn rejects_bad_options() {
+    let _serial = common::serial();
+    init_streamer().unwrap();
+    let mut guard = get_streamer().lock();
+    let streamer = guard.as_mut().unwrap();
+    assert!(streamer.set_analysis(AnalysisOptions { fft_size: 128, ..Default::default() }).is_err());
+    assert!(streamer.set_analysis(AnalysisOptions { bands: 10_000, ..Default::default() }).is_err());
+    assert_eq!(streamer.get_analysis(), AnalysisOptions::default());
+}
+
+#[test]
+fn options_carry_over_to_the_next_session() {
+    let _serial = common::serial();
+    init_streamer().unwrap();
+    let options = AnalysisOptions { enabled: false, fft_size: 4096, bands: 32 };
+    get_streamer().lock().as_mut().unwrap().set_analysis(options.clone()).unwrap();
+
+    init_streamer().unwrap();
+    assert_eq!(get_streamer().lock().as_ref().unwrap().get_analysis(), options);
+    get_streamer().lock().as_mut().unwrap().set_analysis(AnalysisOptions::default()).unwrap();
+}
@@ -0,0 +1,34 @@
+//! Fixtures shared by the integration tests that drive the audio streamer.
+//!
+//! The streamer is a process global, so every test that touches it holds
+//! `serial()` for its whole run and the tests in a binary go one at a time.
+
+// Each test binary uses its own subset of these
+#![allow(dead_code)]
+
+use lyria_studio_lib::audio_stream::{get_streamer, init_streamer};
+use std::sync::{Mutex, MutexGuard};
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// Take the streamer for the rest of the test. A test that panicked while
+/// holding it doesn't poison the rest.
+pub fn serial() -> MutexGuard<'static, ()> {
+    SERIAL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Start a new session and write `samples` into it a chunk at a time, as the
+/// generator would.
+pub fn load_session(samples: &[i16]) {
+    load_session_in_chunks(samples, 24_000);
+}
+
+/// Like `load_session`, with chunks of `chunk_samples` interleaved samples.
+pub fn load_session_in_chunks(samples: &[i16], chunk_samples: usize) {
+    init_streamer().unwrap();
+    let mut guard = get_streamer().lock();
+    let streamer = guard.as_mut().unwrap();
+    for chunk in samples.chunks(chunk_samples) {
+        streamer.write_chunk(chunk).unwrap();
+    }
+}
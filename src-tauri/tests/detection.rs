@@ -0,0 +1,122 @@
+//! Detects the tempo and key of synthetic chord progressions and compares them with
+//! the session's requested BPM and scale.
+
+mod common;
+
+use lyria_studio_lib::audio_stream::get_streamer;
+use lyria_studio_lib::detection::{Detection, KeyMode};
+use lyria_studio_lib::lyria_ws::GenerationConfig;
+use lyria_studio_lib::metadata::Provenance;
+
+const RATE: usize = 48_000;
+
+const C_MAJOR: [[f64; 3]; 4] = [
+    [261.63, 329.63, 392.00], // C
+    [349.23, 440.00, 523.25], // F
+    [392.00, 493.88, 587.33], // G
+    [261.63, 329.63, 392.00], // C
+];
+const A_MINOR: [[f64; 3]; 4] = [
+    [220.00, 261.63, 329.63], // Am
+    [293.66, 349.23, 440.00], // Dm
+    [329.63, 415.30, 493.88], // E
+    [220.00, 261.63, 329.63], // Am
+];
+
+/// A chord struck on every beat, a bar per chord, with the root an octave down
+/// and a click on each beat.
+fn progression(bpm: f64, chords: &[[f64; 3]], seconds: usize) -> Vec<i16> {
+    let beat = 60.0 / bpm;
+    let mut seed = 3u32;
+    (0..RATE * seconds)
+        .flat_map(|i| {
+            let t = i as f64 / RATE as f64;
+            let since_beat = t % beat;
+            let chord = &chords[(t / (beat * 4.0)) as usize % chords.len()];
+            let tone: f64 = chord
+                .iter()
+                .chain([chord[0] / 2.0].iter())
+                .map(|hz| (t * hz * std::f64::consts::TAU).sin())
+                .sum();
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let click = if since_beat < 0.005 { ((seed >> 16) as f64 / 65536.0 - 0.5) * 2.0 } else { 0.0 };
+            let sample = (tone * 0.2 * (-since_beat / 0.15).exp() + click * 0.5) * 12_000.0;
+            [sample as i16, sample as i16]
+        })
+        .collect()
+}
+
+fn detect() -> Detection {
+    get_streamer().lock().as_mut().unwrap().detect_tempo_and_key().unwrap()
+}
+
+fn config(bpm: u32, key: &str, scale: &str) -> GenerationConfig {
+    GenerationConfig {
+        bpm: Some(bpm),
+        key: Some(key.to_string()),
+        scale: Some(scale.to_string()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn detects_tempo_and_key() {
+    let _serial = common::serial();
+
+    for (bpm, chords, key, mode) in [(100.0, &C_MAJOR, "C", KeyMode::Major), (137.0, &A_MINOR, "A", KeyMode::Minor)] {
+        common::load_session(&progression(bpm, chords, 20));
+        let detection = detect();
+        let tempo = detection.tempo.unwrap();
+        assert!((tempo.bpm - bpm).abs() < 1.0, "{} BPM detected as {}", bpm, tempo.bpm);
+        assert!(tempo.confidence > 0.3, "tempo confidence {}", tempo.confidence);
+        let detected = detection.key.unwrap();
+        assert_eq!((detected.key.as_str(), detected.mode), (key, mode));
+        assert!(detected.confidence > 0.6, "key confidence {}", detected.confidence);
+        assert_eq!(detection.seconds, 20.0);
+        // Nothing was requested, so there is nothing to match
+        assert_eq!(detection.tempo_matches, None);
+    }
+}
+
+#[test]
+fn flags_drift_from_the_request() {
+    let _serial = common::serial();
+    common::load_session(&progression(100.0, &C_MAJOR, 12));
+    get_streamer()
+        .lock()
+        .as_mut()
+        .unwrap()
+        .set_provenance(Provenance::new("lyria-realtime-exp", Vec::new(), config(100, "C", "major")));
+
+    let detection = detect();
+    assert_eq!(detection.requested_bpm, Some(100));
+    assert_eq!(detection.tempo_matches, Some(true));
+    assert_eq!(detection.key_matches, Some(true));
+
+    // Steered to a different tempo and key; half time and the relative minor
+    // still count as matches
+    let steer = |config| get_streamer().lock().as_mut().unwrap().record_provenance_change(None, Some(config));
+    steer(config(120, "D", "major"));
+    let drifted = detect();
+    assert_eq!((drifted.tempo_matches, drifted.key_matches), (Some(false), Some(false)));
+    steer(config(200, "A", "minor"));
+    let relative = detect();
+    assert_eq!((relative.tempo_matches, relative.key_matches), (Some(true), Some(true)));
+
+    // Recorded where exports will pick it up
+    let guard = get_streamer().lock();
+    let provenance = guard.as_ref().unwrap().get_metadata().provenance.clone().unwrap();
+    assert_eq!(provenance.detection.unwrap().requested_bpm, Some(200));
+}
+
+#[test]
+fn needs_enough_audio() {
+    let _serial = common::serial();
+    common::load_session(&progression(100.0, &C_MAJOR, 3));
+    assert!(get_streamer().lock().as_mut().unwrap().detect_tempo_and_key().is_err());
+
+    common::load_session(&vec![0; RATE * 2 * 10]);
+    let silence = detect();
+    assert!(silence.tempo.is_none());
+    assert!(silence.key.is_none());
+}
@@ -0,0 +1,150 @@
+//! Applies trims, fades and crossfades to a session and checks the edited audio
+//! that exports and JS playback receive, sample by sample.
+
+mod common;
+
+use lyria_studio_lib::audio_stream::get_streamer;
+use lyria_studio_lib::edits::{Crossfade, EditList, Fade, FadeCurve};
+
+const RATE: usize = 48_000;
+
+/// `seconds` of stereo audio where every frame holds its own index, modulo 20000,
+/// so the source of any edited frame can be read off its value.
+fn counting(seconds: usize) -> Vec<i16> {
+    (0..RATE * seconds)
+        .flat_map(|i| {
+            let value = (i % 20_000) as i16;
+            [value, -value]
+        })
+        .collect()
+}
+
+/// Left channel of the edited session.
+fn edited() -> Vec<i16> {
+    let samples = get_streamer().lock().as_ref().unwrap().get_all_samples().unwrap();
+    samples.iter().step_by(2).copied().collect()
+}
+
+fn set_edits(edits: EditList) {
+    get_streamer().lock().as_mut().unwrap().set_edits(edits).unwrap();
+}
+
+#[test]
+fn trim_and_fades_shape_the_exported_audio() {
+    let _serial = common::serial();
+    common::load_session(&vec![10_000; RATE * 2 * 4]);
+    set_edits(EditList {
+        trim_start: 0.5,
+        trim_end: Some(3.0),
+        fade_in: Some(Fade { seconds: 0.5, curve: FadeCurve::Linear }),
+        fade_out: Some(Fade { seconds: 1.0, curve: FadeCurve::EqualPower }),
+        ..Default::default()
+    });
+
+    let left = edited();
+    assert_eq!(left.len(), RATE * 5 / 2);
+    assert_eq!(get_streamer().lock().as_ref().unwrap().get_edited_duration(), 2.5);
+    assert_eq!(left[0], 0);
+    assert_eq!(left[RATE / 4], 5_000);
+    assert_eq!(left[RATE], 10_000);
+    let half_way_out = RATE * 2;
+    assert_eq!(left[half_way_out], (10_000.0 * std::f64::consts::FRAC_PI_4.sin()).round() as i16);
+    assert!(left[left.len() - 1] < 100);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("edited.wav").to_string_lossy().into_owned();
+    get_streamer().lock().as_ref().unwrap().export_to_file(&path).unwrap();
+    let exported: Vec<i16> = hound::WavReader::open(&path)
+        .unwrap()
+        .into_samples()
+        .map(Result::unwrap)
+        .step_by(2)
+        .collect();
+    assert_eq!(exported, left);
+}
+
+#[test]
+fn crossfade_splices_two_regions() {
+    let _serial = common::serial();
+    common::load_session(&counting(4));
+    set_edits(EditList {
+        crossfades: vec![Crossfade {
+            from: 1.0,
+            to: 2.5,
+            seconds: 0.25,
+            curve: FadeCurve::Linear,
+        }],
+        ..Default::default()
+    });
+
+    // 1.0s before the cut plus 1.5s after it, sharing a quarter second
+    let left = edited();
+    assert_eq!(left.len(), RATE * 9 / 4);
+    let overlap_start = RATE * 3 / 4;
+    let overlap_end = RATE;
+    assert_eq!(left[overlap_start - 1], ((overlap_start - 1) % 20_000) as i16);
+    assert_eq!(left[overlap_end], ((RATE * 5 / 2 + RATE / 4) % 20_000) as i16);
+    assert_eq!(*left.last().unwrap(), ((RATE * 4 - 1) % 20_000) as i16);
+
+    // Halfway through the overlap both sides contribute equally
+    let middle = overlap_start + RATE / 8;
+    let outgoing = (middle % 20_000) as f64 * 0.5;
+    let incoming = ((RATE * 5 / 2 + RATE / 8) % 20_000) as f64 * 0.5;
+    assert!((left[middle] as f64 - (outgoing + incoming)).abs() <= 1.0);
+
+    let mut guard = get_streamer().lock();
+    let streamer = guard.as_mut().unwrap();
+    assert!(streamer.seek(2.2).is_ok());
+    assert!(streamer.seek(2.3).is_err(), "seek beyond the edited audio");
+}
+
+#[test]
+fn open_ended_edits_follow_a_growing_recording() {
+    let _serial = common::serial();
+    let samples = counting(3);
+    common::load_session(&samples[..RATE * 2]);
+    set_edits(EditList {
+        trim_start: 0.5,
+        fade_out: Some(Fade { seconds: 0.5, curve: FadeCurve::Log }),
+        ..Default::default()
+    });
+    assert_eq!(edited().len(), RATE / 2);
+
+    {
+        let mut guard = get_streamer().lock();
+        let streamer = guard.as_mut().unwrap();
+        for chunk in samples[RATE * 2..].chunks(24_000) {
+            streamer.write_chunk(chunk).unwrap();
+        }
+    }
+    let left = edited();
+    assert_eq!(left.len(), RATE * 5 / 2);
+    assert_eq!(left[0], (RATE / 2 % 20_000) as i16);
+    // Log fades are 6 dB down a tenth of the way in
+    let at = left.len() - RATE * 9 / 20;
+    let source = ((RATE / 2 + at) % 20_000) as f64;
+    assert!((left[at] as f64 - source * 10f64.powf(-0.3)).abs() <= 1.0);
+}
+
+#[test]
+fn rejects_impossible_edits() {
+    let _serial = common::serial();
+    common::load_session(&counting(2));
+    let mut guard = get_streamer().lock();
+    let streamer = guard.as_mut().unwrap();
+
+    assert!(streamer.set_trim(-1.0, None).is_err());
+    assert!(streamer.set_trim(1.5, Some(1.0)).is_err());
+    assert!(streamer
+        .set_fade_in(Some(Fade { seconds: f64::NAN, curve: FadeCurve::Linear }))
+        .is_err());
+    streamer.set_trim(0.5, None).unwrap();
+    let overlapping = Crossfade { from: 0.6, to: 1.5, seconds: 0.5, curve: FadeCurve::EqualPower };
+    assert!(streamer.add_crossfade(overlapping).is_err());
+    assert_eq!(streamer.get_edits().trim_start, 0.5);
+    assert!(streamer.get_edits().crossfades.is_empty());
+
+    streamer.clear_edits().unwrap();
+    assert!(streamer.get_edits().is_empty());
+    assert_eq!(streamer.get_edited_duration(), 2.0);
+}
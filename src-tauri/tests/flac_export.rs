@@ -0,0 +1,148 @@
+//! Exports session audio to FLAC and decodes it again with claxon to check the
+//! encoder is lossless and its STREAMINFO and tags are right.
+
+mod common;
+
+use lyria_studio_lib::audio_stream::get_streamer;
+use lyria_studio_lib::flac::FlacOptions;
+use md5::{Digest, Md5};
+
+/// A few seconds of stereo audio that is not a whole number of blocks long, with
+/// silence, full-scale extremes and noise so every subframe type gets exercised.
+fn session_samples() -> Vec<i16> {
+    let frames = 48_000 * 3 + 1234;
+    let mut seed = 0x2545_f491_u32;
+    let mut samples = Vec::with_capacity(frames * 2);
+    for i in 0..frames {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        let noise = (seed >> 20) as i16 - 2048;
+        let tone = ((i as f32 * 440.0 * std::f32::consts::TAU / 48_000.0).sin() * 12_000.0) as i16;
+        let (left, right) = match i / 24_000 {
+            0 => (0, 0),
+            1 => (i16::MAX, i16::MIN),
+            _ => (tone.saturating_add(noise), tone / 2 - noise),
+        };
+        samples.push(left);
+        samples.push(right);
+    }
+    samples
+}
+
+fn export(options: &FlacOptions) -> (tempfile::TempDir, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("session.flac").to_string_lossy().into_owned();
+    get_streamer().lock().as_ref().unwrap().export_to_flac(&path, options).unwrap();
+    (dir, path)
+}
+
+fn md5_of(samples: &[i16], bits_per_sample: u16) -> [u8; 16] {
+    let shift = bits_per_sample - 16;
+    let bytes = bits_per_sample as usize / 8;
+    let mut md5 = Md5::new();
+    for &sample in samples {
+        md5.update(&((sample as i32) << shift).to_le_bytes()[..bytes]);
+    }
+    md5.finalize().into()
+}
+
+#[test]
+fn round_trips_bit_exactly_at_every_setting() {
+    let _serial = common::serial();
+    let samples = session_samples();
+    common::load_session(&samples);
+
+    for bits_per_sample in [16, 24] {
+        for (compression_level, block_size) in [(0, 4096), (1, 4096), (2, 1152), (5, 4096), (8, 16384)] {
+            let options = FlacOptions {
+                compression_level,
+                block_size,
+                bits_per_sample,
+                ..Default::default()
+            };
+            let (_dir, path) = export(&options);
+
+            let mut reader = claxon::FlacReader::open(&path).unwrap();
+            let info = reader.streaminfo();
+            assert_eq!(info.sample_rate, 48_000);
+            assert_eq!(info.channels, 2);
+            assert_eq!(info.bits_per_sample, bits_per_sample as u32);
+            assert_eq!(info.samples, Some(samples.len() as u64 / 2));
+            assert_eq!(info.max_block_size as usize, block_size);
+            assert_eq!(info.md5sum, md5_of(&samples, bits_per_sample), "{:?}", options);
+
+            let shift = bits_per_sample - 16;
+            let decoded: Vec<i16> = reader
+                .samples()
+                .map(|s| (s.unwrap() >> shift) as i16)
+                .collect();
+            assert!(decoded == samples, "decoded audio differs for {:?}", options);
+        }
+    }
+}
+
+#[test]
+fn fixed_predictor_levels_search_further_each_step() {
+    let _serial = common::serial();
+    // A clean tone on the left and the same tone with noise on the right, which
+    // left-side stereo codes better than mid-side
+    let mut seed = 0x2545_f491_u32;
+    let samples: Vec<i16> = (0..48_000 * 3)
+        .flat_map(|i| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let tone = ((i as f32 * 220.0 * std::f32::consts::TAU / 48_000.0).sin() * 12_000.0) as i16;
+            [tone, tone + (seed >> 22) as i16 - 512]
+        })
+        .collect();
+    common::load_session(&samples);
+
+    let sizes: Vec<u64> = (0..=2)
+        .map(|compression_level| {
+            let (_dir, path) = export(&FlacOptions { compression_level, ..Default::default() });
+            std::fs::metadata(&path).unwrap().len()
+        })
+        .collect();
+    assert!(sizes[0] > sizes[1] && sizes[1] > sizes[2], "levels 0-2 gave {:?}", sizes);
+}
+
+#[test]
+fn writes_vorbis_comments() {
+    let _serial = common::serial();
+    common::load_session(&session_samples()[..48_000]);
+
+    let options = FlacOptions {
+        tags: vec![
+            ("TITLE".to_string(), "Night drive".to_string()),
+            ("COMMENT".to_string(), "ambient pads, 90 bpm".to_string()),
+        ],
+        ..Default::default()
+    };
+    let (_dir, path) = export(&options);
+
+    let reader = claxon::FlacReader::open(&path).unwrap();
+    assert!(reader.vendor().unwrap().starts_with("Lyria AI Studio"));
+    let tags: Vec<(&str, &str)> = reader.tags().collect();
+    assert_eq!(tags, [("TITLE", "Night drive"), ("COMMENT", "ambient pads, 90 bpm")]);
+}
+
+#[test]
+fn rejects_unsupported_settings() {
+    let _serial = common::serial();
+    common::load_session(&session_samples()[..48_000]);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bad.flac").to_string_lossy().into_owned();
+    let guard = get_streamer().lock();
+    let streamer = guard.as_ref().unwrap();
+
+    for options in [
+        FlacOptions { bits_per_sample: 20, ..Default::default() },
+        FlacOptions { compression_level: 9, ..Default::default() },
+        FlacOptions { block_size: 32, ..Default::default() },
+        FlacOptions { tags: vec![("BAD=NAME".to_string(), String::new())], ..Default::default() },
+    ] {
+        assert!(streamer.export_to_flac(&path, &options).is_err(), "{:?} accepted", options);
+    }
+}
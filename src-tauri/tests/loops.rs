@@ -0,0 +1,188 @@
+//! Finds loops in a session made of a repeating four-bar chord pattern after an
+//! unrelated intro, and exports them with each kind of seam.
+
+mod common;
+
+use lyria_studio_lib::audio_stream::get_streamer;
+use lyria_studio_lib::loops::{LoopExportOptions, LoopSearch, LoopSeam};
+use lyria_studio_lib::metadata::TrackMetadata;
+use lyria_studio_lib::wav::{Dither, WavOptions};
+
+const RATE: usize = 48_000;
+/// 120 BPM in 4/4, so a bar is two seconds.
+const BAR: usize = RATE * 2;
+const INTRO: usize = RATE * 3;
+
+/// Three seconds of noise, then a different tone each bar repeating every four bars.
+fn session_samples() -> Vec<i16> {
+    let tones = [220.0, 277.0, 330.0, 392.0];
+    let mut seed = 1u32;
+    (0..INTRO + BAR * 10)
+        .flat_map(|i| {
+            let sample = if i < INTRO {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                ((seed >> 16) as i16) / 4
+            } else {
+                let tone = tones[(i - INTRO) / BAR % tones.len()];
+                ((i as f64 * tone * std::f64::consts::TAU / RATE as f64).sin() * 8000.0) as i16
+            };
+            [sample, sample]
+        })
+        .collect()
+}
+
+fn load_session(samples: &[i16], bpm: Option<u32>) {
+    common::load_session(samples);
+    let mut guard = get_streamer().lock();
+    let streamer = guard.as_mut().unwrap();
+    streamer.set_metadata(TrackMetadata { bpm, ..Default::default() });
+}
+
+/// Left channel and the `smpl` chunk of an exported loop.
+fn read_loop(path: &str) -> (Vec<i16>, Vec<u8>) {
+    let samples = hound::WavReader::open(path)
+        .unwrap()
+        .into_samples()
+        .map(Result::unwrap)
+        .step_by(2)
+        .collect();
+    (samples, read_smpl(path))
+}
+
+fn read_smpl(path: &str) -> Vec<u8> {
+    let bytes = std::fs::read(path).unwrap();
+    let at = bytes.windows(4).position(|w| w == b"smpl").expect("no smpl chunk");
+    let len = u32::from_le_bytes(bytes[at + 4..at + 8].try_into().unwrap()) as usize;
+    bytes[at + 8..at + 8 + len].to_vec()
+}
+
+fn field(smpl: &[u8], index: usize) -> u32 {
+    u32::from_le_bytes(smpl[index * 4..index * 4 + 4].try_into().unwrap())
+}
+
+#[test]
+fn finds_the_repeating_pattern_at_the_session_bpm() {
+    let _serial = common::serial();
+    load_session(&session_samples(), Some(120));
+    let guard = get_streamer().lock();
+    let streamer = guard.as_ref().unwrap();
+
+    let loops = streamer.find_loops(&LoopSearch::default()).unwrap();
+    let best = &loops[0];
+    assert_eq!(best.bpm, 120.0);
+    assert_eq!(best.end - best.start, 8.0);
+    assert!(best.start >= 3.0, "loop starts in the intro at {}", best.start);
+    assert!(best.score > 0.99, "score {}", best.score);
+
+    // Two bars never repeat in this pattern
+    let two_bars = streamer.find_loops(&LoopSearch { bars: 2, ..Default::default() }).unwrap();
+    assert!(two_bars[0].score < 0.5, "score {}", two_bars[0].score);
+
+    let at_90 = streamer.find_loops(&LoopSearch { bpm: Some(90.0), ..Default::default() }).unwrap();
+    assert!((at_90[0].end - at_90[0].start - 32.0 / 3.0).abs() < 1e-3);
+}
+
+#[test]
+fn needs_a_bpm() {
+    let _serial = common::serial();
+    load_session(&session_samples(), None);
+    let guard = get_streamer().lock();
+    let streamer = guard.as_ref().unwrap();
+
+    assert!(streamer.find_loops(&LoopSearch::default()).is_err());
+    assert!(streamer.find_loops(&LoopSearch { bpm: Some(1000.0), ..Default::default() }).is_err());
+}
+
+#[test]
+fn exports_a_crossfaded_loop_with_loop_points() {
+    let _serial = common::serial();
+    let samples = session_samples();
+    load_session(&samples, Some(120));
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("loop.wav").to_string_lossy().into_owned();
+    let options = LoopExportOptions { seam: LoopSeam::Crossfade, seam_ms: 20.0 };
+    get_streamer().lock().as_ref().unwrap().export_loop(&path, 5.0, 13.0, &options, &WavOptions::default()).unwrap();
+
+    let (left, smpl) = read_loop(&path);
+    assert_eq!(left.len(), 4 * BAR);
+    assert_eq!(field(&smpl, 2), 1_000_000_000 / RATE as u32);
+    assert_eq!(field(&smpl, 7), 1);
+    assert_eq!(field(&smpl, 11), 0);
+    assert_eq!(field(&smpl, 12), 4 * BAR as u32 - 1);
+
+    // The head is untouched and the tail blends into the audio before the start,
+    // which in a repeating pattern is the same audio
+    let start = RATE * 5;
+    assert_eq!(left[..RATE], samples[start * 2..(start + RATE) * 2].iter().step_by(2).copied().collect::<Vec<_>>()[..]);
+    let wrap_step = (left[left.len() - 1] as i32 - left[0] as i32).abs();
+    assert!(wrap_step < 2000, "jump of {} at the seam", wrap_step);
+
+    // Over three bars the tail ends on a different tone, so it has to be blended
+    // all the way into the sample before the start
+    get_streamer().lock().as_ref().unwrap().export_loop(&path, 5.25, 11.25, &options, &WavOptions::default()).unwrap();
+    let (left, _) = read_loop(&path);
+    let start = RATE * 21 / 4;
+    let lead_in = samples[(start - 1) * 2] as i32;
+    let unblended = samples[(start + 3 * BAR - 1) * 2] as i32;
+    assert!((left[left.len() - 1] as i32 - lead_in).abs() < 20);
+    assert!((unblended - lead_in).abs() > 200);
+}
+
+#[test]
+fn zero_crossing_seam_keeps_the_loop_length() {
+    let _serial = common::serial();
+    load_session(&session_samples(), Some(120));
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("loop.wav").to_string_lossy().into_owned();
+    let guard = get_streamer().lock();
+    let streamer = guard.as_ref().unwrap();
+
+    let options = LoopExportOptions { seam: LoopSeam::ZeroCrossing, seam_ms: 10.0 };
+    streamer.export_loop(&path, 5.0001, 13.0001, &options, &WavOptions::default()).unwrap();
+    let (left, smpl) = read_loop(&path);
+    assert_eq!(left.len(), 4 * BAR);
+    assert_eq!(field(&smpl, 12), 4 * BAR as u32 - 1);
+    assert!(left[0].abs() < 300, "loop starts at {}", left[0]);
+    assert!(left[1] >= left[0], "loop does not start on a rising edge");
+    let wrap_step = left[0] as i32 - left[left.len() - 1] as i32;
+    assert!((0..400).contains(&wrap_step), "step of {} at the seam", wrap_step);
+
+    // A loop of the whole session has nothing to crossfade with
+    assert!(streamer.export_loop(&path, 0.0, 23.0, &LoopExportOptions::default(), &WavOptions::default()).is_err());
+    assert!(streamer.export_loop(&path, 0.0, 23.0, &options, &WavOptions::default()).is_ok());
+    assert!(streamer.export_loop(&path, 5.0, 4.0, &options, &WavOptions::default()).is_err());
+    assert!(streamer.export_loop(&path, 20.0, 30.0, &options, &WavOptions::default()).is_err());
+}
+
+#[test]
+fn exports_loops_in_the_wav_format_asked_for() {
+    let _serial = common::serial();
+    load_session(&session_samples(), Some(120));
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("loop.wav").to_string_lossy().into_owned();
+    let wav = WavOptions { sample_rate: 44_100, bits_per_sample: 24, ..Default::default() };
+    get_streamer().lock().as_ref().unwrap().export_loop(&path, 5.0, 13.0, &LoopExportOptions::default(), &wav).unwrap();
+
+    let reader = hound::WavReader::open(&path).unwrap();
+    let spec = reader.spec();
+    assert_eq!((spec.sample_rate, spec.bits_per_sample, spec.sample_format), (44_100, 24, hound::SampleFormat::Int));
+    assert_eq!(reader.duration(), 44_100 * 8);
+
+    // Loop points are in frames at the exported rate
+    let smpl = read_smpl(&path);
+    assert_eq!(field(&smpl, 2), 1_000_000_000 / 44_100);
+    assert_eq!(field(&smpl, 12), 44_100 * 8 - 1);
+
+    // The crossfade is only quantized once, at the requested depth
+    let wav = WavOptions { bits_per_sample: 24, dither: Dither::None, ..Default::default() };
+    get_streamer().lock().as_ref().unwrap().export_loop(&path, 5.0, 13.0, &LoopExportOptions::default(), &wav).unwrap();
+    let samples: Vec<i32> = hound::WavReader::open(&path).unwrap().into_samples().map(Result::unwrap).collect();
+    assert!(samples.iter().any(|s| s % 256 != 0), "the seam was rounded to 16 bits first");
+
+    assert!(get_streamer()
+        .lock()
+        .as_ref()
+        .unwrap()
+        .export_loop(&path, 5.0, 13.0, &LoopExportOptions::default(), &WavOptions { bits_per_sample: 8, ..Default::default() })
+        .is_err());
+}
@@ -0,0 +1,109 @@
+//! Measures sessions of known loudness (EBU Tech 3341 style test tones) and checks
+//! normalized exports land on the target within the true-peak ceiling.
+
+mod common;
+
+use lyria_studio_lib::audio_stream::get_streamer;
+use lyria_studio_lib::loudness::{LoudnessReport, Normalization};
+
+/// Ten seconds of a stereo 1 kHz sine with the given peak level.
+fn sine_at(dbfs: f64) -> Vec<i16> {
+    let amplitude = 10f64.powf(dbfs / 20.0) * 32767.0;
+    (0..48_000 * 10)
+        .flat_map(|i| {
+            let sample = ((i as f64 * 1000.0 * std::f64::consts::TAU / 48_000.0).sin() * amplitude).round() as i16;
+            [sample, sample]
+        })
+        .collect()
+}
+
+fn measure() -> LoudnessReport {
+    get_streamer().lock().as_ref().unwrap().measure_loudness().unwrap()
+}
+
+/// Export a normalized WAV, then load it back as the session.
+fn export_normalized(normalization: Normalization) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("normalized.wav").to_string_lossy().into_owned();
+    {
+        let mut guard = get_streamer().lock();
+        let streamer = guard.as_mut().unwrap();
+        streamer.set_normalization(Some(normalization)).unwrap();
+        streamer.export_to_file(&path).unwrap();
+    }
+    let exported: Vec<i16> = hound::WavReader::open(&path)
+        .unwrap()
+        .into_samples()
+        .map(Result::unwrap)
+        .collect();
+    common::load_session(&exported);
+}
+
+fn assert_close(actual: Option<f64>, expected: f64, what: &str) {
+    let actual = actual.unwrap_or_else(|| panic!("{} not measured", what));
+    assert!((actual - expected).abs() < 0.2, "{} was {:.2}, expected {:.2}", what, actual, expected);
+}
+
+#[test]
+fn measures_a_reference_tone() {
+    let _serial = common::serial();
+    common::load_session(&sine_at(-23.0));
+
+    let report = measure();
+    assert_close(report.integrated_lufs, -23.0, "integrated loudness");
+    assert_close(report.max_short_term_lufs, -23.0, "short-term loudness");
+    assert_close(report.true_peak_dbtp, -23.0, "true peak");
+}
+
+#[test]
+fn normalizes_to_the_target() {
+    let _serial = common::serial();
+    common::load_session(&sine_at(-23.0));
+
+    let normalization = Normalization::default();
+    let result = normalization.apply_to(&measure());
+    assert_close(Some(result.gain_db), 9.0, "gain");
+    assert!(result.reached_target);
+
+    export_normalized(normalization);
+    let report = measure();
+    assert_close(report.integrated_lufs, -14.0, "normalized loudness");
+    assert_close(report.true_peak_dbtp, -14.0, "normalized true peak");
+}
+
+#[test]
+fn true_peak_ceiling_limits_the_gain() {
+    let _serial = common::serial();
+    common::load_session(&sine_at(-23.0));
+
+    let normalization = Normalization {
+        target_lufs: -6.0,
+        true_peak_ceiling_dbtp: -10.0,
+    };
+    let result = normalization.apply_to(&measure());
+    assert!(!result.reached_target);
+    assert_close(result.true_peak_dbtp, -10.0, "predicted true peak");
+
+    export_normalized(normalization);
+    let report = measure();
+    assert!(report.true_peak_dbtp.unwrap() <= -9.9, "true peak {:?}", report.true_peak_dbtp);
+    assert_close(report.integrated_lufs, -10.0, "limited loudness");
+}
+
+#[test]
+fn silence_has_no_loudness_and_is_left_alone() {
+    let _serial = common::serial();
+    common::load_session(&vec![0; 48_000 * 2 * 5]);
+
+    let report = measure();
+    assert!(report.integrated_lufs.is_none());
+    assert!(report.true_peak_dbtp.is_none());
+    assert_eq!(Normalization::default().apply_to(&report).gain_db, 0.0);
+
+    let mut guard = get_streamer().lock();
+    let streamer = guard.as_mut().unwrap();
+    let bad_target = Normalization { target_lufs: 3.0, ..Default::default() };
+    assert!(streamer.set_normalization(Some(bad_target)).is_err());
+    let bad_ceiling = Normalization { true_peak_ceiling_dbtp: 1.0, ..Default::default() };
+    assert!(streamer.set_normalization(Some(bad_ceiling)).is_err());
+}
@@ -0,0 +1,435 @@
+//! Drives `lyria_ws` against the in-process mock server so protocol changes are
+//! caught without network access. The generator is a process global like the
+//! streamer, so tests hold `common::serial()` too.
+
+mod common;
+mod mock_lyria;
+
+use lyria_studio_lib::audio_stream::get_streamer;
+use lyria_studio_lib::lyria_ws::{self, ConnectionConfig, GenerationConfig, PlaybackControl, ReconnectPolicy};
+use lyria_studio_lib::PromptWeight;
+use mock_lyria::{chunk_samples, Fault, MockLyriaServer, Script, CHUNK_FRAMES};
+use serde_json::Value;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn prompt(text: &str, weight: f32) -> PromptWeight {
+    PromptWeight {
+        text: text.to_string(),
+        weight,
+    }
+}
+
+fn connection(server: &MockLyriaServer) -> ConnectionConfig {
+    ConnectionConfig {
+        base_url: Some(server.base_url.clone()),
+        ..Default::default()
+    }
+}
+
+fn fast_reconnect() -> ReconnectPolicy {
+    ReconnectPolicy {
+        max_attempts: 2,
+        initial_delay_ms: 10,
+        max_delay_ms: 50,
+    }
+}
+
+fn wait_until(what: &str, condition: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !condition() {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn wait_for_finish() -> lyria_ws::GenerationStatus {
+    wait_until("generation to finish", || !lyria_ws::is_generating());
+    lyria_ws::get_generation_status()
+}
+
+fn messages_with<'a>(messages: &'a [Value], key: &str) -> Vec<&'a Value> {
+    messages.iter().filter(|m| m.get(key).is_some()).collect()
+}
+
+#[test]
+fn streams_audio_after_full_session_setup() {
+    let _serial = common::serial();
+    let server = MockLyriaServer::start(vec![Script::default()]);
+
+    let config = GenerationConfig {
+        bpm: Some(100),
+        key: Some("A".to_string()),
+        scale: Some("minor".to_string()),
+        density: Some(0.25),
+        negative_prompt: Some("drums".to_string()),
+        seed: Some(7),
+        ..Default::default()
+    };
+    let connection = ConnectionConfig {
+        model: Some("lyria-realtime-next".to_string()),
+        ..connection(&server)
+    };
+    lyria_ws::start_generation(
+        "test-key",
+        connection,
+        vec![prompt("ambient pads", 1.0), prompt("piano", 0.5)],
+        1,
+        config,
+        fast_reconnect(),
+    )
+    .unwrap();
+
+    let status = wait_for_finish();
+    assert_eq!(status.state, "completed", "error: {:?}", status.error);
+    assert_eq!(status.total_samples, 48_000);
+    assert_eq!(status.chunks_received, 48_000 / CHUNK_FRAMES);
+
+    let expected: Vec<i16> = (0..4).flat_map(chunk_samples).collect();
+    let samples = get_streamer().lock().as_ref().unwrap().get_all_samples().unwrap();
+    assert_eq!(samples, expected);
+
+    let received = server.received();
+    assert_eq!(received.len(), 1);
+    let messages = &received[0];
+    assert_eq!(messages[0]["setup"]["model"], "models/lyria-realtime-next");
+
+    let prompts = &messages_with(messages, "clientContent")[0]["clientContent"]["weightedPrompts"];
+    assert_eq!(prompts.as_array().unwrap().len(), 3);
+    assert_eq!(prompts[0]["text"], "ambient pads");
+    assert_eq!(prompts[2]["text"], "drums");
+    assert!(prompts[2]["weight"].as_f64().unwrap() < 0.0);
+
+    let music_config = &messages_with(messages, "musicGenerationConfig")[0]["musicGenerationConfig"];
+    assert_eq!(music_config["bpm"], 100);
+    assert_eq!(music_config["scale"], "C_MAJOR_A_MINOR");
+    assert_eq!(music_config["density"], 0.25);
+    assert_eq!(music_config["seed"], 7);
+    assert!(music_config.get("brightness").is_none());
+
+    let guard = get_streamer().lock();
+    let provenance = guard.as_ref().unwrap().get_metadata().provenance.clone().unwrap();
+    assert_eq!(provenance.model, "models/lyria-realtime-next");
+    assert_eq!(provenance.prompts.len(), 2);
+    assert_eq!(provenance.config.seed, Some(7));
+    assert_eq!(provenance.config.negative_prompt.as_deref(), Some("drums"));
+
+    assert_eq!(messages_with(messages, "playbackControl")[0]["playbackControl"], "PLAY");
+}
+
+#[test]
+fn filtered_prompt_leaves_the_session_running() {
+    let _serial = common::serial();
+    let server = MockLyriaServer::start(vec![Script {
+        fault: Fault::FilterPrompt("SAFETY".to_string()),
+        ..Default::default()
+    }]);
+
+    lyria_ws::start_generation(
+        "test-key",
+        connection(&server),
+        vec![prompt("something", 1.0)],
+        1,
+        GenerationConfig::default(),
+        fast_reconnect(),
+    )
+    .unwrap();
+
+    let status = wait_for_finish();
+    assert_eq!(status.state, "completed", "error: {:?}", status.error);
+    assert_eq!(status.chunks_received, 48_000 / CHUNK_FRAMES);
+    assert_eq!(server.connection_count(), 1);
+}
+
+#[test]
+fn malformed_audio_keeps_progress_counters() {
+    let _serial = common::serial();
+    let server = MockLyriaServer::start(vec![Script {
+        fault: Fault::MalformedBase64 { at_chunk: 1 },
+        ..Default::default()
+    }]);
+
+    lyria_ws::start_generation(
+        "test-key",
+        connection(&server),
+        vec![prompt("ambient", 1.0)],
+        1,
+        GenerationConfig::default(),
+        fast_reconnect(),
+    )
+    .unwrap();
+
+    let status = wait_for_finish();
+    assert_eq!(status.state, "error");
+    assert!(status.error.unwrap().starts_with("Base64 decode failed"));
+    assert_eq!(status.chunks_received, 1);
+    assert_eq!(status.total_samples, CHUNK_FRAMES);
+}
+
+#[test]
+fn reconnects_and_replays_session_after_mid_stream_close() {
+    let _serial = common::serial();
+    let server = MockLyriaServer::start(vec![
+        Script {
+            fault: Fault::CloseAfter { chunks: 2, code: 1013 },
+            ..Default::default()
+        },
+        Script::default(),
+    ]);
+
+    lyria_ws::start_generation(
+        "test-key",
+        connection(&server),
+        vec![prompt("ambient", 1.0)],
+        1,
+        GenerationConfig {
+            bpm: Some(90),
+            ..Default::default()
+        },
+        fast_reconnect(),
+    )
+    .unwrap();
+
+    let status = wait_for_finish();
+    assert_eq!(status.state, "completed", "error: {:?}", status.error);
+    assert_eq!(status.reconnect_attempts, 1);
+    assert_eq!(status.total_samples, 48_000);
+
+    let expected: Vec<i16> = [0, 1, 0, 1].into_iter().flat_map(chunk_samples).collect();
+    let samples = get_streamer().lock().as_ref().unwrap().get_all_samples().unwrap();
+    assert_eq!(samples, expected);
+
+    let received = server.received();
+    assert_eq!(received.len(), 2);
+    let replayed = &received[1];
+    assert!(replayed[0].get("setup").is_some());
+    assert_eq!(messages_with(replayed, "clientContent")[0]["clientContent"]["weightedPrompts"][0]["text"], "ambient");
+    assert_eq!(messages_with(replayed, "musicGenerationConfig")[0]["musicGenerationConfig"]["bpm"], 90);
+}
+
+#[test]
+fn policy_close_ends_the_generation() {
+    let _serial = common::serial();
+    let server = MockLyriaServer::start(vec![
+        Script {
+            fault: Fault::CloseAfter { chunks: 2, code: 1008 },
+            ..Default::default()
+        },
+        Script::default(),
+    ]);
+
+    lyria_ws::start_generation(
+        "test-key",
+        connection(&server),
+        vec![prompt("ambient", 1.0)],
+        1,
+        GenerationConfig::default(),
+        fast_reconnect(),
+    )
+    .unwrap();
+
+    let status = wait_for_finish();
+    assert_eq!(status.state, "error");
+    assert_eq!(status.reconnect_attempts, 0);
+    assert_eq!(server.connection_count(), 1);
+}
+
+#[test]
+fn stopping_after_a_reconnect_reports_stopped() {
+    let _serial = common::serial();
+    let server = MockLyriaServer::start(vec![
+        Script {
+            fault: Fault::CloseAfter { chunks: 1, code: 1013 },
+            ..Default::default()
+        },
+        Script {
+            chunks: 40,
+            chunk_delay: Duration::from_millis(50),
+            fault: Fault::None,
+        },
+    ]);
+
+    lyria_ws::start_generation(
+        "test-key",
+        connection(&server),
+        vec![prompt("ambient", 1.0)],
+        10,
+        GenerationConfig::default(),
+        fast_reconnect(),
+    )
+    .unwrap();
+
+    wait_until("audio after the reconnect", || lyria_ws::get_generation_status().chunks_received >= 2);
+    assert_eq!(lyria_ws::get_generation_status().reconnect_attempts, 1);
+    lyria_ws::stop_generation().unwrap();
+    // The session task winds down after the stop returns and must not report it finished
+    thread::sleep(Duration::from_millis(300));
+
+    let status = lyria_ws::get_generation_status();
+    assert_eq!(status.state, "stopped");
+    assert_eq!(status.reconnect_attempts, 0);
+    assert!(!lyria_ws::is_generating());
+}
+
+#[test]
+fn playback_waits_for_the_stream_through_a_reconnect() {
+    let _serial = common::serial();
+    let server = MockLyriaServer::start(vec![
+        Script {
+            fault: Fault::CloseAfter { chunks: 2, code: 1013 },
+            ..Default::default()
+        },
+        Script::default(),
+    ]);
+
+    let slow_reconnect = ReconnectPolicy {
+        initial_delay_ms: 500,
+        ..fast_reconnect()
+    };
+    lyria_ws::start_generation(
+        "test-key",
+        connection(&server),
+        vec![prompt("ambient", 1.0)],
+        1,
+        GenerationConfig::default(),
+        slow_reconnect,
+    )
+    .unwrap();
+
+    // Silence during the backoff doesn't count towards playback's starvation timeout
+    wait_until("the connection to drop", || lyria_ws::get_generation_status().state == "reconnecting");
+    assert!(get_streamer().lock().as_ref().unwrap().is_input_paused());
+
+    // and the first chunk over the new connection ends the pause
+    let status = wait_for_finish();
+    assert_eq!(status.state, "completed", "error: {:?}", status.error);
+    assert_eq!(status.reconnect_attempts, 1);
+    assert!(!get_streamer().lock().as_ref().unwrap().is_input_paused());
+}
+
+#[test]
+fn gives_up_when_the_first_connection_fails() {
+    let _serial = common::serial();
+
+    lyria_ws::start_generation(
+        "test-key",
+        ConnectionConfig {
+            base_url: Some("ws://127.0.0.1:1".to_string()),
+            ..Default::default()
+        },
+        vec![prompt("ambient", 1.0)],
+        1,
+        GenerationConfig::default(),
+        fast_reconnect(),
+    )
+    .unwrap();
+
+    let status = wait_for_finish();
+    assert_eq!(status.state, "error");
+    assert!(status.error.unwrap().starts_with("WebSocket connection failed"));
+    assert_eq!(status.reconnect_attempts, 0);
+}
+
+#[test]
+fn rejects_invalid_prompts_before_connecting() {
+    let _serial = common::serial();
+    let server = MockLyriaServer::start(vec![Script::default()]);
+
+    let result = lyria_ws::start_generation(
+        "test-key",
+        connection(&server),
+        vec![prompt("ambient", 0.0), prompt("   ", 1.0)],
+        1,
+        GenerationConfig::default(),
+        fast_reconnect(),
+    );
+    assert!(result.is_err());
+
+    let result = lyria_ws::start_generation(
+        "test-key",
+        connection(&server),
+        vec![prompt(&"x".repeat(5000), 1.0)],
+        1,
+        GenerationConfig::default(),
+        fast_reconnect(),
+    );
+    assert!(result.is_err());
+    assert!(!lyria_ws::is_generating());
+    assert_eq!(server.connection_count(), 0);
+}
+
+#[test]
+fn steers_and_pauses_a_running_session() {
+    let _serial = common::serial();
+    let server = MockLyriaServer::start(vec![Script {
+        chunks: 8,
+        chunk_delay: Duration::from_millis(150),
+        fault: Fault::None,
+    }]);
+
+    lyria_ws::start_generation(
+        "test-key",
+        connection(&server),
+        vec![prompt("ambient", 1.0)],
+        2,
+        GenerationConfig::default(),
+        fast_reconnect(),
+    )
+    .unwrap();
+
+    wait_until("first chunk", || lyria_ws::get_generation_status().chunks_received >= 1);
+
+    // A bad config is rejected whole: the new negative prompt doesn't go out either
+    assert!(lyria_ws::update_config(GenerationConfig {
+        negative_prompt: Some("vocals".to_string()),
+        density: Some(f32::NAN),
+        ..Default::default()
+    })
+    .is_err());
+
+    lyria_ws::update_prompts(vec![prompt("techno", 1.0)]).unwrap();
+    // A new BPM resets the context so it takes effect; a new density alone doesn't
+    lyria_ws::update_config(GenerationConfig {
+        bpm: Some(140),
+        ..Default::default()
+    })
+    .unwrap();
+    lyria_ws::update_config(GenerationConfig {
+        bpm: Some(140),
+        density: Some(0.5),
+        ..Default::default()
+    })
+    .unwrap();
+    lyria_ws::control_playback(PlaybackControl::Pause).unwrap();
+    assert_eq!(lyria_ws::get_generation_status().state, "paused");
+    assert!(lyria_ws::control_playback(PlaybackControl::Pause).is_err());
+    lyria_ws::control_playback(PlaybackControl::Play).unwrap();
+    lyria_ws::control_playback(PlaybackControl::ResetContext).unwrap();
+
+    let status = wait_for_finish();
+    assert_eq!(status.state, "completed", "error: {:?}", status.error);
+
+    let messages = &server.received()[0];
+    let prompts = messages_with(messages, "clientContent");
+    assert_eq!(prompts.len(), 2);
+    assert_eq!(prompts[1]["clientContent"]["weightedPrompts"][0]["text"], "techno");
+
+    let configs = messages_with(messages, "musicGenerationConfig");
+    assert_eq!(configs.len(), 3);
+    assert_eq!(configs[1]["musicGenerationConfig"]["bpm"], 140);
+    let bpm_change = messages.iter().position(|m| std::ptr::eq(m, configs[1])).unwrap();
+    assert_eq!(messages[bpm_change + 1]["playbackControl"], "RESET_CONTEXT");
+
+    let controls: Vec<&Value> = messages_with(messages, "playbackControl")
+        .into_iter()
+        .map(|m| &m["playbackControl"])
+        .collect();
+    assert_eq!(controls, ["PLAY", "RESET_CONTEXT", "PAUSE", "PLAY", "RESET_CONTEXT"]);
+
+    let guard = get_streamer().lock();
+    let changes = &guard.as_ref().unwrap().get_metadata().provenance.as_ref().unwrap().changes;
+    assert_eq!(changes.len(), 3);
+    assert_eq!(changes[0].prompts.as_ref().unwrap()[0].text, "techno");
+    assert_eq!(changes[1].config.as_ref().unwrap().bpm, Some(140));
+    assert!(changes[0].at_seconds > 0.0);
+}
@@ -0,0 +1,179 @@
+//! Exports tagged session audio in each format and reads the tags back to check
+//! title, BPM, key and the provenance JSON all survive.
+
+mod common;
+
+use id3::TagLike;
+use lyria_studio_lib::audio_stream::get_streamer;
+use lyria_studio_lib::flac::FlacOptions;
+use lyria_studio_lib::lyria_ws::GenerationConfig;
+use lyria_studio_lib::metadata::{Provenance, TrackMetadata};
+use lyria_studio_lib::vorbis::VorbisOptions;
+use lyria_studio_lib::PromptWeight;
+use serde_json::Value;
+use std::fs::File;
+
+fn load_tagged_session() {
+    let samples: Vec<i16> = (0..48_000 * 2).map(|i| ((i % 200) as i16 - 100) * 50).collect();
+    common::load_session(&samples);
+    let mut guard = get_streamer().lock();
+    let streamer = guard.as_mut().unwrap();
+
+    let config = GenerationConfig {
+        bpm: Some(96),
+        key: Some("F#".to_string()),
+        scale: Some("minor".to_string()),
+        negative_prompt: Some("vocals".to_string()),
+        seed: Some(1234),
+        ..Default::default()
+    };
+    let prompts = vec![
+        PromptWeight { text: "dub techno".to_string(), weight: 1.0 },
+        PromptWeight { text: "rain & <static>".to_string(), weight: 0.5 },
+    ];
+    streamer.set_provenance(Provenance::new("models/lyria-realtime-exp", prompts, config));
+    streamer.set_metadata(TrackMetadata {
+        title: Some("Night drive".to_string()),
+        artist: Some("Lyria".to_string()),
+        ..Default::default()
+    });
+}
+
+fn temp_path(dir: &tempfile::TempDir, name: &str) -> String {
+    dir.path().join(name).to_string_lossy().into_owned()
+}
+
+fn check_provenance(json: &str) {
+    let provenance: Value = serde_json::from_str(json).unwrap();
+    assert_eq!(provenance["model"], "models/lyria-realtime-exp");
+    assert_eq!(provenance["prompts"][1]["text"], "rain & <static>");
+    assert_eq!(provenance["prompts"][1]["weight"], 0.5);
+    assert_eq!(provenance["negative_prompt"], "vocals");
+    assert_eq!(provenance["seed"], 1234);
+    assert_eq!(provenance["bpm"], 96);
+    assert!(provenance["generated_at"].as_str().unwrap().ends_with('Z'));
+}
+
+/// RIFF chunks starting at `at`, as (id, data) pairs.
+fn riff_chunks(bytes: &[u8], mut at: usize) -> Vec<([u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    while at + 8 <= bytes.len() {
+        let id = bytes[at..at + 4].try_into().unwrap();
+        let len = u32::from_le_bytes(bytes[at + 4..at + 8].try_into().unwrap()) as usize;
+        chunks.push((id, &bytes[at + 8..at + 8 + len]));
+        at += 8 + len + len % 2;
+    }
+    chunks
+}
+
+#[test]
+fn vorbis_comments_carry_tags_and_provenance() {
+    let _serial = common::serial();
+    load_tagged_session();
+    let dir = tempfile::tempdir().unwrap();
+    let guard = get_streamer().lock();
+    let streamer = guard.as_ref().unwrap();
+
+    let flac = temp_path(&dir, "tagged.flac");
+    let options = FlacOptions {
+        tags: vec![("title".to_string(), "Override".to_string())],
+        ..Default::default()
+    };
+    streamer.export_to_flac(&flac, &options).unwrap();
+    let reader = claxon::FlacReader::open(&flac).unwrap();
+    assert_eq!(reader.get_tag("TITLE").collect::<Vec<_>>(), ["Override"]);
+    assert_eq!(reader.get_tag("ARTIST").next(), Some("Lyria"));
+    assert_eq!(reader.get_tag("BPM").next(), Some("96"));
+    assert_eq!(reader.get_tag("KEY").next(), Some("F#m"));
+    assert!(reader.get_tag("COMMENT").next().unwrap().contains("dub techno (1)"));
+    check_provenance(reader.get_tag("LYRIA_PROVENANCE").next().unwrap());
+
+    let ogg = temp_path(&dir, "tagged.ogg");
+    streamer.export_to_vorbis(&ogg, &VorbisOptions::default()).unwrap();
+    let reader = lewton::inside_ogg::OggStreamReader::new(File::open(&ogg).unwrap()).unwrap();
+    let comments = &reader.comment_hdr.comment_list;
+    assert!(comments.contains(&("TITLE".to_string(), "Night drive".to_string())));
+    let json = &comments.iter().find(|(field, _)| field == "LYRIA_PROVENANCE").unwrap().1;
+    check_provenance(json);
+}
+
+#[test]
+fn mp3_starts_with_an_id3_tag() {
+    let _serial = common::serial();
+    load_tagged_session();
+    let dir = tempfile::tempdir().unwrap();
+    let path = temp_path(&dir, "tagged.mp3");
+    get_streamer().lock().as_ref().unwrap().export_to_file_with_format(&path, "mp3", 192).unwrap();
+
+    let tag = id3::Tag::read_from_path(&path).unwrap();
+    assert_eq!(tag.title(), Some("Night drive"));
+    assert_eq!(tag.artist(), Some("Lyria"));
+    assert_eq!(tag.get("TBPM").and_then(|f| f.content().text()), Some("96"));
+    assert_eq!(tag.get("TKEY").and_then(|f| f.content().text()), Some("F#m"));
+    let provenance = tag
+        .extended_texts()
+        .find(|t| t.description == "LYRIA_PROVENANCE")
+        .unwrap();
+    check_provenance(&provenance.value);
+
+    // The audio follows the tag directly
+    let bytes = std::fs::read(&path).unwrap();
+    let tag_len = bytes[6..10].iter().fold(0usize, |len, &b| (len << 7) | b as usize) + 10;
+    assert_eq!(bytes[tag_len], 0xff);
+    assert_eq!(bytes[tag_len + 1] & 0xe0, 0xe0);
+
+    // Steered mid-session, the tags follow the settings the track ended on
+    get_streamer().lock().as_mut().unwrap().record_provenance_change(None, Some(GenerationConfig {
+        bpm: Some(120),
+        key: Some("A".to_string()),
+        scale: Some("major".to_string()),
+        ..Default::default()
+    }));
+    get_streamer().lock().as_ref().unwrap().export_to_file_with_format(&path, "mp3", 192).unwrap();
+    let tag = id3::Tag::read_from_path(&path).unwrap();
+    assert_eq!(tag.get("TBPM").and_then(|f| f.content().text()), Some("120"));
+    assert_eq!(tag.get("TKEY").and_then(|f| f.content().text()), Some("A"));
+}
+
+#[test]
+fn wav_gets_info_and_ixml_chunks() {
+    let _serial = common::serial();
+    load_tagged_session();
+    let dir = tempfile::tempdir().unwrap();
+    let path = temp_path(&dir, "tagged.wav");
+    get_streamer().lock().as_ref().unwrap().export_to_file(&path).unwrap();
+
+    let bytes = std::fs::read(&path).unwrap();
+    let riff_size = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+    assert_eq!(riff_size, bytes.len() - 8);
+
+    let chunks = riff_chunks(&bytes, 12);
+    let ids: Vec<&[u8]> = chunks.iter().map(|(id, _)| &id[..]).collect();
+    assert_eq!(ids, [&b"fmt "[..], b"data", b"LIST", b"iXML"]);
+
+    let info = chunks[2].1;
+    assert_eq!(&info[..4], b"INFO");
+    let info_fields = riff_chunks(info, 4);
+    let title = info_fields.iter().find(|(id, _)| id == b"INAM").unwrap().1;
+    assert_eq!(title, b"Night drive\0");
+    assert!(info_fields.iter().any(|(id, _)| id == b"ICMT"));
+
+    let ixml = std::str::from_utf8(chunks[3].1).unwrap();
+    assert!(ixml.contains("<PROJECT>Night drive</PROJECT>"));
+    assert!(ixml.contains("<BPM>96</BPM>"));
+    assert!(ixml.contains("<KEY>F#m</KEY>"));
+    let json = ixml
+        .split("<PROVENANCE>")
+        .nth(1)
+        .and_then(|rest| rest.split("</PROVENANCE>").next())
+        .unwrap()
+        .replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+    check_provenance(&json);
+
+    // Still a valid WAV for readers that ignore the extra chunks
+    let reader = hound::WavReader::open(&path).unwrap();
+    assert_eq!(reader.len(), 48_000 * 2);
+}
@@ -0,0 +1,229 @@
+//! Mixes vocal tracks written as WAV, FLAC and MP3 into a silent session and reads
+//! the export back to check each track's offset, volume, pan and sample rate.
+
+mod common;
+
+use lyria_studio_lib::audio_stream::get_streamer;
+use lyria_studio_lib::flac::{FlacOptions, FlacWriter};
+use lyria_studio_lib::metadata::TrackMetadata;
+use lyria_studio_lib::mixer::{TrackSettings, VocalTrack};
+use lyria_studio_lib::mp3::{Mp3Options, Mp3Writer};
+use lyria_studio_lib::resample::{resample, resample_loop, Resampler};
+use lyria_studio_lib::wav::{Dither, WavOptions};
+use std::io::Cursor;
+
+const RATE: u32 = 48_000;
+
+/// A sine at `hz` and `amplitude` (0-1) on every channel.
+fn tone(hz: f64, amplitude: f64, rate: u32, channels: usize, frames: usize) -> Vec<f32> {
+    (0..frames)
+        .flat_map(|i| {
+            let s = (i as f64 * hz * std::f64::consts::TAU / rate as f64).sin() * amplitude;
+            vec![s as f32; channels]
+        })
+        .collect()
+}
+
+fn to_i16(samples: &[f32]) -> Vec<i16> {
+    samples.iter().map(|&s| (s * 32767.0).round() as i16).collect()
+}
+
+fn wav_bytes(samples: &[i16], rate: u32, channels: u16) -> Vec<u8> {
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate: rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut bytes = Cursor::new(Vec::new());
+    let mut writer = hound::WavWriter::new(&mut bytes, spec).unwrap();
+    for &sample in samples {
+        writer.write_sample(sample).unwrap();
+    }
+    writer.finalize().unwrap();
+    bytes.into_inner()
+}
+
+fn load_session(samples: &[i16]) {
+    common::load_session(samples);
+    let mut guard = get_streamer().lock();
+    let streamer = guard.as_mut().unwrap();
+    for id in streamer.get_vocal_tracks().iter().map(|t| t.id.clone()).collect::<Vec<_>>() {
+        streamer.remove_vocal_track(&id).unwrap();
+    }
+}
+
+fn add_track(id: &str, name: &str, bytes: Vec<u8>, settings: TrackSettings) -> VocalTrack {
+    let track = VocalTrack::decode(id, name, bytes, RATE).unwrap();
+    let mut guard = get_streamer().lock();
+    let streamer = guard.as_mut().unwrap();
+    streamer.add_vocal_track(track.clone());
+    streamer.set_vocal_track(id, settings).unwrap();
+    track
+}
+
+/// Export the session as undithered WAV and return its left and right channels.
+fn export(dir: &tempfile::TempDir) -> (Vec<f64>, Vec<f64>) {
+    let path = dir.path().join("mix.wav").to_string_lossy().into_owned();
+    let options = WavOptions { dither: Dither::None, ..Default::default() };
+    get_streamer().lock().as_ref().unwrap().export_to_wav(&path, &options).unwrap();
+    let samples: Vec<f64> = hound::WavReader::open(&path)
+        .unwrap()
+        .samples::<i16>()
+        .map(|s| s.unwrap() as f64 / 32768.0)
+        .collect();
+    (samples.iter().step_by(2).copied().collect(), samples.iter().skip(1).step_by(2).copied().collect())
+}
+
+fn rms(samples: &[f64]) -> f64 {
+    (samples.iter().map(|s| s * s).sum::<f64>() / samples.len() as f64).sqrt()
+}
+
+#[test]
+fn resamples_without_drift_or_aliasing() {
+    // 44.1 kHz to 48 kHz lands every sample where an ideal 48 kHz sine would be
+    let input = tone(1000.0, 0.5, 44_100, 2, 44_100);
+    let output = resample(&input, 44_100, RATE, 2).unwrap();
+    assert_eq!(output.len(), RATE as usize * 2);
+    let ideal = tone(1000.0, 0.5, RATE, 2, RATE as usize);
+    let error = output[2000..output.len() - 2000]
+        .iter()
+        .zip(&ideal[2000..])
+        .map(|(a, b)| (a - b).abs())
+        .fold(0.0f32, f32::max);
+    assert!(error < 1e-3, "largest error {}", error);
+
+    // Fed in odd-sized blocks, the stream comes out the same
+    let mut resampler = Resampler::new(44_100, RATE, 2).unwrap();
+    let mut streamed = Vec::new();
+    for block in input.chunks(2 * 777) {
+        resampler.process(block, &mut streamed);
+    }
+    resampler.finish(&mut streamed);
+    assert_eq!(streamed, output);
+
+    // Content above the new Nyquist frequency is filtered out, not folded down
+    let ultrasonic = resample(&tone(30_000.0, 0.5, 96_000, 1, 96_000), 96_000, RATE, 1).unwrap();
+    assert_eq!(ultrasonic.len(), RATE as usize);
+    let level = ultrasonic[1000..47_000].iter().map(|&s| s.abs()).fold(0.0f32, f32::max);
+    assert!(level < 1e-3, "30 kHz leaked through at {}", level);
+
+    // Resampled as a loop, a whole number of cycles wraps round with no edges
+    let looped = resample_loop(&input, 44_100, RATE, 2).unwrap();
+    assert_eq!(looped.len(), RATE as usize * 2);
+    let error = looped.iter().zip(&ideal).map(|(a, b)| (a - b).abs()).fold(0.0f32, f32::max);
+    assert!(error < 1e-3, "largest error {} in the loop", error);
+
+    assert_eq!(resample(&input, RATE, RATE, 2).unwrap(), input);
+    assert!(Resampler::new(0, RATE, 2).is_err());
+}
+
+#[test]
+fn mixes_tracks_at_their_offset_with_volume_and_pan() {
+    let _serial = common::serial();
+    load_session(&vec![0; RATE as usize * 2 * 2]);
+    let dir = tempfile::tempdir().unwrap();
+
+    // A mono 44.1 kHz WAV half a second in, at half volume, hard left
+    let vocal = to_i16(&tone(1000.0, 0.5, 44_100, 1, 44_100));
+    let track = add_track("lead", "lead vocal.wav", wav_bytes(&vocal, 44_100, 1), TrackSettings {
+        volume: 0.5,
+        pan: -1.0,
+        start_time: 0.5,
+        ..Default::default()
+    });
+    assert_eq!((track.source_sample_rate, track.source_channels, track.channels), (44_100, 1, 1));
+    assert!((track.duration - 1.0).abs() < 1e-6);
+
+    let (left, right) = export(&dir);
+    assert_eq!(left.len(), RATE as usize * 2);
+    assert!(left[..23_900].iter().all(|&s| s == 0.0), "vocal started early");
+    let expected = 0.5 * 0.5 / 2f64.sqrt();
+    assert!((rms(&left[26_400..69_600]) - expected).abs() < 0.002, "left at {}", rms(&left[26_400..69_600]));
+    assert!(left[72_500..].iter().all(|&s| s == 0.0), "vocal ran late");
+    assert!(right.iter().all(|&s| s == 0.0));
+
+    // Centred, mono is spread equally at -3 dB
+    get_streamer().lock().as_mut().unwrap().set_vocal_track("lead", TrackSettings {
+        start_time: 0.5,
+        ..Default::default()
+    }).unwrap();
+    let (left, right) = export(&dir);
+    let (l, r) = (rms(&left[26_400..69_600]), rms(&right[26_400..69_600]));
+    assert!((l - 0.5 / 2.0).abs() < 0.002 && (r - l).abs() < 1e-4, "{} / {}", l, r);
+
+    // A stereo FLAC running past the end of the session extends the export
+    let harmony = to_i16(&tone(500.0, 0.25, RATE, 2, RATE as usize));
+    let path = dir.path().join("harmony.flac").to_string_lossy().into_owned();
+    let mut writer = FlacWriter::create(&path, RATE, 2, &FlacOptions::default()).unwrap();
+    writer.write_samples(&harmony).unwrap();
+    writer.finalize().unwrap();
+    add_track("harmony", "harmony.flac", std::fs::read(&path).unwrap(), TrackSettings {
+        start_time: 1.5,
+        ..Default::default()
+    });
+    let (left, right) = export(&dir);
+    assert_eq!(left.len(), RATE as usize * 5 / 2);
+    let tail = rms(&right[RATE as usize * 2..]);
+    assert!((tail - 0.25 / 2f64.sqrt()).abs() < 0.002, "harmony at {}", tail);
+    assert!((rms(&left[RATE as usize * 2..]) - tail).abs() < 1e-4);
+
+    // Muted tracks drop out of the mix and its length
+    let muted = TrackSettings { start_time: 1.5, muted: true, ..Default::default() };
+    get_streamer().lock().as_mut().unwrap().set_vocal_track("harmony", muted).unwrap();
+    let (left, _) = export(&dir);
+    assert_eq!(left.len(), RATE as usize * 2);
+}
+
+#[test]
+fn tracks_carry_over_to_the_next_session() {
+    let _serial = common::serial();
+    load_session(&vec![0; RATE as usize * 2]);
+    let dir = tempfile::tempdir().unwrap();
+    let vocal = to_i16(&tone(1000.0, 0.5, RATE, 2, RATE as usize));
+    add_track("lead", "lead.wav", wav_bytes(&vocal, RATE, 2), TrackSettings { volume: 0.5, ..Default::default() });
+
+    // A new generation starts a new session but keeps the vocals and their settings
+    common::load_session(&vec![0; RATE as usize * 2]);
+    let tracks = get_streamer().lock().as_ref().unwrap().get_vocal_tracks().to_vec();
+    assert_eq!(tracks.len(), 1);
+    assert_eq!(tracks[0].settings.volume, 0.5);
+
+    let (left, right) = export(&dir);
+    let expected = 0.5 * 0.5 / 2f64.sqrt();
+    assert!((rms(&left[2400..45_600]) - expected).abs() < 0.002, "left at {}", rms(&left[2400..45_600]));
+    assert!((rms(&right[2400..45_600]) - expected).abs() < 0.002);
+}
+
+#[test]
+fn decodes_mp3_and_rejects_bad_input() {
+    let _serial = common::serial();
+    load_session(&vec![0; RATE as usize * 2]);
+    let dir = tempfile::tempdir().unwrap();
+
+    let path = dir.path().join("take.mp3").to_string_lossy().into_owned();
+    let options = Mp3Options { bitrate_kbps: 128, quality: 5, ..Default::default() };
+    let mut writer = Mp3Writer::create(&path, RATE, 2, &options, &TrackMetadata::default()).unwrap();
+    writer.write_samples(&to_i16(&tone(440.0, 0.5, RATE, 2, RATE as usize))).unwrap();
+    writer.finalize().unwrap();
+    let track = VocalTrack::decode("take", "take.mp3", std::fs::read(&path).unwrap(), RATE).unwrap();
+    // Gapless decoding gives back exactly the second that was encoded
+    assert_eq!((track.frames(), track.channels), (RATE as usize, 2));
+
+    assert!(VocalTrack::decode("junk", "junk.wav", vec![7; 4096], RATE).is_err());
+
+    let mut guard = get_streamer().lock();
+    let streamer = guard.as_mut().unwrap();
+    streamer.add_vocal_track(track);
+    for settings in [
+        TrackSettings { volume: -1.0, ..Default::default() },
+        TrackSettings { pan: 1.5, ..Default::default() },
+        TrackSettings { start_time: f64::NAN, ..Default::default() },
+    ] {
+        assert!(streamer.set_vocal_track("take", settings).is_err());
+    }
+    assert!(streamer.set_vocal_track("missing", TrackSettings::default()).is_err());
+    streamer.remove_vocal_track("take").unwrap();
+    assert!(streamer.remove_vocal_track("take").is_err());
+    assert!(streamer.get_vocal_tracks().is_empty());
+}
@@ -0,0 +1,218 @@
+//! In-process stand-in for the BidiGenerateMusic WebSocket endpoint.
+//!
+//! Each accepted connection plays one `Script`: it waits for `setup`, answers with
+//! `setupComplete`, waits for the client's PLAY and then streams audio chunks,
+//! injecting whatever fault the script asks for. Every client message is recorded
+//! so tests can assert on the protocol exchange.
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures_util::{SinkExt, StreamExt};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Stereo frames per chunk; 0.25 s at 48 kHz.
+pub const CHUNK_FRAMES: usize = 12_000;
+
+#[derive(Clone, Debug)]
+pub enum Fault {
+    /// Send chunks normally.
+    None,
+    /// Send a `filteredPrompt` message before the audio.
+    FilterPrompt(String),
+    /// Replace the chunk at this index with data that is not valid base64.
+    MalformedBase64 { at_chunk: usize },
+    /// Send a Close frame with this code after this many chunks.
+    CloseAfter { chunks: usize, code: u16 },
+}
+
+#[derive(Clone, Debug)]
+pub struct Script {
+    /// Chunks to send after PLAY; the connection then stays open until the client leaves.
+    pub chunks: usize,
+    /// Pause between chunks.
+    pub chunk_delay: Duration,
+    pub fault: Fault,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Self {
+            chunks: 8,
+            chunk_delay: Duration::from_millis(5),
+            fault: Fault::None,
+        }
+    }
+}
+
+pub struct MockLyriaServer {
+    pub base_url: String,
+    received: Arc<Mutex<Vec<Vec<Value>>>>,
+    runtime: Option<tokio::runtime::Runtime>,
+}
+
+impl MockLyriaServer {
+    /// Start a server that plays `scripts` in order, one per connection. Extra
+    /// connections reuse the last script.
+    pub fn start(scripts: Vec<Script>) -> Self {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .expect("Failed to create mock server runtime");
+
+        let listener = runtime
+            .block_on(TcpListener::bind("127.0.0.1:0"))
+            .expect("Failed to bind mock server");
+        let addr = listener.local_addr().expect("Mock server has no address");
+
+        let received: Arc<Mutex<Vec<Vec<Value>>>> = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&received);
+
+        runtime.spawn(async move {
+            let mut index = 0;
+            while let Ok((stream, _)) = listener.accept().await {
+                let script = scripts
+                    .get(index)
+                    .or(scripts.last())
+                    .cloned()
+                    .unwrap_or_default();
+                let connection = {
+                    let mut log = log.lock();
+                    log.push(Vec::new());
+                    log.len() - 1
+                };
+                let log = Arc::clone(&log);
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, script, connection, log).await {
+                        eprintln!("mock connection {} ended: {}", connection, e);
+                    }
+                });
+                index += 1;
+            }
+        });
+
+        Self {
+            base_url: format!("ws://{}", addr),
+            received,
+            runtime: Some(runtime),
+        }
+    }
+
+    /// Client messages received so far, grouped by connection.
+    pub fn received(&self) -> Vec<Vec<Value>> {
+        self.received.lock().clone()
+    }
+
+    pub fn connection_count(&self) -> usize {
+        self.received.lock().len()
+    }
+}
+
+impl Drop for MockLyriaServer {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// A recognisable ramp so tests can check samples arrive intact.
+pub fn chunk_samples(chunk: usize) -> Vec<i16> {
+    (0..CHUNK_FRAMES * 2)
+        .map(|i| ((chunk * 31 + i) % 2000) as i16 - 1000)
+        .collect()
+}
+
+fn encode_chunk(samples: &[i16]) -> String {
+    let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    STANDARD.encode(bytes)
+}
+
+async fn serve(
+    stream: tokio::net::TcpStream,
+    script: Script,
+    connection: usize,
+    log: Arc<Mutex<Vec<Vec<Value>>>>,
+) -> Result<(), String> {
+    let ws = tokio_tungstenite::accept_async(stream)
+        .await
+        .map_err(|e| format!("handshake failed: {}", e))?;
+    let (mut write, mut read) = ws.split();
+
+    let setup_seen = Arc::new(Notify::new());
+    let play_seen = Arc::new(Notify::new());
+
+    let reader = {
+        let setup_seen = Arc::clone(&setup_seen);
+        let play_seen = Arc::clone(&play_seen);
+        tokio::spawn(async move {
+            while let Some(Ok(msg)) = read.next().await {
+                let Message::Text(text) = msg else { continue };
+                let Ok(value) = serde_json::from_str::<Value>(&text) else { continue };
+                if value.get("setup").is_some() {
+                    setup_seen.notify_one();
+                }
+                if value["playbackControl"] == "PLAY" {
+                    play_seen.notify_one();
+                }
+                log.lock()[connection].push(value);
+            }
+        })
+    };
+
+    setup_seen.notified().await;
+    send_json(&mut write, json!({ "setupComplete": {} })).await?;
+
+    if let Fault::FilterPrompt(reason) = &script.fault {
+        send_json(&mut write, json!({
+            "filteredPrompt": { "text": "filtered", "filteredReason": reason }
+        }))
+        .await?;
+    }
+
+    play_seen.notified().await;
+
+    for chunk in 0..script.chunks {
+        if let Fault::CloseAfter { chunks, code } = script.fault {
+            if chunk == chunks {
+                let frame = CloseFrame {
+                    code: CloseCode::from(code),
+                    reason: "scripted close".into(),
+                };
+                write
+                    .send(Message::Close(Some(frame)))
+                    .await
+                    .map_err(|e| e.to_string())?;
+                return Ok(());
+            }
+        }
+
+        let data = match script.fault {
+            Fault::MalformedBase64 { at_chunk } if at_chunk == chunk => "not*base64!".to_string(),
+            _ => encode_chunk(&chunk_samples(chunk)),
+        };
+        send_json(&mut write, json!({ "audioChunk": { "data": data } })).await?;
+        tokio::time::sleep(script.chunk_delay).await;
+    }
+
+    reader.await.ok();
+    Ok(())
+}
+
+async fn send_json<W>(write: &mut W, value: Value) -> Result<(), String>
+where
+    W: futures_util::Sink<Message> + Unpin,
+    W::Error: std::fmt::Display,
+{
+    write
+        .send(Message::Text(value.to_string()))
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,157 @@
+//! Exports session audio to MP3 in each bitrate mode and decodes it with symphonia,
+//! honouring the LAME tag's delay and padding, to check the output is sample-
+//! aligned with the session and exactly as long.
+
+mod common;
+
+use lyria_studio_lib::audio_stream::get_streamer;
+use lyria_studio_lib::metadata::TrackMetadata;
+use lyria_studio_lib::mp3::{Mp3BitrateMode, Mp3Options, Mp3Writer};
+use std::fs::File;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// Three seconds and a bit of a stereo chord, not a whole number of MP3 frames.
+fn session_samples() -> Vec<i16> {
+    let frames = 48_000 * 3 + 701;
+    (0..frames)
+        .flat_map(|i| {
+            let t = i as f32 / 48_000.0;
+            let left = (t * 220.0 * std::f32::consts::TAU).sin() * 8000.0;
+            let right = (t * 330.0 * std::f32::consts::TAU).sin() * 8000.0;
+            [left as i16, right as i16]
+        })
+        .collect()
+}
+
+/// Decode with gapless trimming, returning interleaved samples.
+fn decode(path: &str) -> Vec<f32> {
+    let source = MediaSourceStream::new(Box::new(File::open(path).unwrap()), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension("mp3");
+    let format_options = FormatOptions {
+        enable_gapless: true,
+        ..Default::default()
+    };
+    let mut format = symphonia::default::get_probe()
+        .format(&hint, source, &format_options, &MetadataOptions::default())
+        .unwrap()
+        .format;
+    let track = format.default_track().unwrap();
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .unwrap();
+
+    let mut samples = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => panic!("failed to read MP3 packet: {}", e),
+        };
+        let decoded = decoder.decode(&packet).unwrap();
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        buffer.copy_interleaved_ref(decoded);
+        samples.extend_from_slice(buffer.samples());
+    }
+    samples
+}
+
+fn snr_db(original: &[i16], decoded: &[f32]) -> f64 {
+    let (mut signal, mut noise) = (0f64, 0f64);
+    for (&o, &d) in original.iter().zip(decoded) {
+        let o = o as f64 / 32768.0;
+        signal += o * o;
+        noise += (o - d as f64).powi(2);
+    }
+    10.0 * (signal / noise).log10()
+}
+
+#[test]
+fn every_mode_decodes_gaplessly_to_the_session_length() {
+    let _serial = common::serial();
+    let samples = session_samples();
+    common::load_session(&samples);
+    let dir = tempfile::tempdir().unwrap();
+
+    for options in [
+        Mp3Options { bitrate_kbps: 192, quality: 5, ..Default::default() },
+        Mp3Options { mode: Mp3BitrateMode::Vbr, vbr_quality: 0.0, quality: 5, ..Default::default() },
+        Mp3Options { mode: Mp3BitrateMode::Abr, bitrate_kbps: 160, quality: 5, ..Default::default() },
+    ] {
+        let path = dir.path().join("session.mp3").to_string_lossy().into_owned();
+        get_streamer().lock().as_ref().unwrap().export_to_mp3(&path, &options).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let tag = if options.mode == Mp3BitrateMode::Cbr { b"Info" } else { b"Xing" };
+        assert!(bytes[..1000].windows(4).any(|w| w == tag), "no {:?} frame for {:?}", tag, options);
+        assert!(bytes[..1000].windows(4).any(|w| w == b"LAME"), "no LAME tag for {:?}", options);
+
+        let decoded = decode(&path);
+        assert_eq!(decoded.len(), samples.len(), "{:?}", options);
+        let snr = snr_db(&samples, &decoded);
+        assert!(snr > 15.0, "MP3 SNR too low for {:?}: {:.1} dB", options, snr);
+    }
+}
+
+#[test]
+fn mono_vbr_round_trips() {
+    let left: Vec<i16> = session_samples().into_iter().step_by(2).collect();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("mono.mp3").to_string_lossy().into_owned();
+    let options = Mp3Options { mode: Mp3BitrateMode::Vbr, vbr_quality: 2.0, quality: 5, ..Default::default() };
+    let mut writer = Mp3Writer::create(&path, 48_000, 1, &options, &TrackMetadata::default()).unwrap();
+    writer.write_samples(&left).unwrap();
+    writer.finalize().unwrap();
+
+    let decoded = decode(&path);
+    assert_eq!(decoded.len(), left.len());
+    let snr = snr_db(&left, &decoded);
+    assert!(snr > 15.0, "mono MP3 SNR too low: {:.1} dB", snr);
+}
+
+#[test]
+fn lame_tag_follows_the_id3_tag() {
+    let _serial = common::serial();
+    let samples = session_samples();
+    common::load_session(&samples);
+    get_streamer().lock().as_mut().unwrap().set_metadata(TrackMetadata {
+        title: Some("Night drive".to_string()),
+        ..Default::default()
+    });
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tagged.mp3").to_string_lossy().into_owned();
+    let options = Mp3Options { quality: 5, ..Default::default() };
+    get_streamer().lock().as_ref().unwrap().export_to_mp3(&path, &options).unwrap();
+
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(&bytes[..3], b"ID3");
+    let tag_len = bytes[6..10].iter().fold(0usize, |len, &b| (len << 7) | b as usize) + 10;
+    assert_eq!(bytes[tag_len], 0xff);
+    assert!(bytes[tag_len..tag_len + 200].windows(4).any(|w| w == b"Info"));
+    assert_eq!(decode(&path).len(), samples.len());
+}
+
+#[test]
+fn rejects_out_of_range_settings() {
+    let _serial = common::serial();
+    common::load_session(&session_samples()[..48_000]);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bad.mp3").to_string_lossy().into_owned();
+    let guard = get_streamer().lock();
+    let streamer = guard.as_ref().unwrap();
+
+    for options in [
+        Mp3Options { bitrate_kbps: 300, ..Default::default() },
+        Mp3Options { mode: Mp3BitrateMode::Abr, bitrate_kbps: 400, ..Default::default() },
+        Mp3Options { mode: Mp3BitrateMode::Vbr, vbr_quality: 10.0, ..Default::default() },
+        Mp3Options { quality: 10, ..Default::default() },
+    ] {
+        assert!(streamer.export_to_mp3(&path, &options).is_err(), "{:?} accepted", options);
+    }
+}
@@ -0,0 +1,150 @@
+//! Exports session audio to Ogg Opus and Ogg Vorbis, then decodes the files to
+//! check the stream headers, the exact length and that the audio survived.
+
+mod common;
+
+use lyria_studio_lib::audio_stream::get_streamer;
+use lyria_studio_lib::opus::OpusOptions;
+use lyria_studio_lib::vorbis::VorbisOptions;
+use std::fs::File;
+
+/// Two seconds and a bit of a stereo chord, deliberately not a whole number of
+/// Opus frames long.
+fn session_samples() -> Vec<i16> {
+    let frames = 48_000 * 2 + 517;
+    (0..frames)
+        .flat_map(|i| {
+            let t = i as f32 / 48_000.0;
+            let left = (t * 220.0 * std::f32::consts::TAU).sin() * 8000.0;
+            let right = (t * 330.0 * std::f32::consts::TAU).sin() * 8000.0;
+            [left as i16, right as i16]
+        })
+        .collect()
+}
+
+fn temp_path(dir: &tempfile::TempDir, name: &str) -> String {
+    dir.path().join(name).to_string_lossy().into_owned()
+}
+
+/// Signal-to-noise ratio of `decoded` against `original`, in dB.
+fn snr_db(original: &[i16], decoded: &[f32]) -> f64 {
+    let (mut signal, mut noise) = (0f64, 0f64);
+    for (&o, &d) in original.iter().zip(decoded) {
+        let o = o as f64 / 32768.0;
+        signal += o * o;
+        noise += (o - d as f64).powi(2);
+    }
+    10.0 * (signal / noise).log10()
+}
+
+#[test]
+fn opus_export_is_trimmed_to_the_session_length() {
+    let _serial = common::serial();
+    let samples = session_samples();
+    common::load_session(&samples);
+    let dir = tempfile::tempdir().unwrap();
+    let path = temp_path(&dir, "session.opus");
+
+    let options = OpusOptions {
+        bitrate_kbps: 160,
+        tags: vec![("TITLE".to_string(), "Chord".to_string())],
+        ..Default::default()
+    };
+    get_streamer().lock().as_ref().unwrap().export_to_opus(&path, &options).unwrap();
+
+    let mut reader = ogg::PacketReader::new(File::open(&path).unwrap());
+    let head = reader.read_packet_expected().unwrap();
+    assert_eq!(&head.data[..8], b"OpusHead");
+    assert_eq!(head.data[9], 2);
+    let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as usize;
+    assert_eq!(u32::from_le_bytes(head.data[12..16].try_into().unwrap()), 48_000);
+
+    let tags = reader.read_packet_expected().unwrap();
+    assert_eq!(&tags.data[..8], b"OpusTags");
+    assert!(tags.data.windows(11).any(|w| w == b"TITLE=Chord"));
+
+    let mut error = 0;
+    let decoder = unsafe { unsafe_libopus::opus_decoder_create(48_000, 2, &mut error) };
+    assert_eq!(error, unsafe_libopus::OPUS_OK);
+
+    let mut decoded = Vec::new();
+    let mut frame = vec![0f32; 5760 * 2];
+    let mut last_granule = 0;
+    while let Some(packet) = reader.read_packet().unwrap() {
+        let len = unsafe {
+            unsafe_libopus::opus_decode_float(
+                decoder,
+                packet.data.as_ptr(),
+                packet.data.len() as i32,
+                frame.as_mut_ptr(),
+                5760,
+                0,
+            )
+        };
+        assert!(len > 0);
+        decoded.extend_from_slice(&frame[..len as usize * 2]);
+        last_granule = packet.absgp_page();
+        if packet.last_in_stream() {
+            break;
+        }
+    }
+    unsafe { unsafe_libopus::opus_decoder_destroy(decoder) };
+
+    let frames = samples.len() / 2;
+    assert_eq!(last_granule as usize, pre_skip + frames);
+    let decoded = &decoded[pre_skip * 2..(pre_skip + frames) * 2];
+    let snr = snr_db(&samples, decoded);
+    assert!(snr > 20.0, "Opus SNR too low: {:.1} dB", snr);
+}
+
+#[test]
+fn vorbis_export_decodes_to_the_session_length() {
+    let _serial = common::serial();
+    let samples = session_samples();
+    common::load_session(&samples);
+    let dir = tempfile::tempdir().unwrap();
+
+    for options in [
+        VorbisOptions::default(),
+        VorbisOptions {
+            quality: None,
+            bitrate_kbps: Some(192),
+            tags: vec![("ARTIST".to_string(), "Lyria".to_string())],
+        },
+    ] {
+        let path = temp_path(&dir, "session.ogg");
+        get_streamer().lock().as_ref().unwrap().export_to_vorbis(&path, &options).unwrap();
+
+        let mut reader = lewton::inside_ogg::OggStreamReader::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.ident_hdr.audio_sample_rate, 48_000);
+        assert_eq!(reader.ident_hdr.audio_channels, 2);
+        for (field, value) in &options.tags {
+            assert!(reader.comment_hdr.comment_list.contains(&(field.clone(), value.clone())));
+        }
+
+        let mut decoded = Vec::new();
+        while let Some(packet) = reader.read_dec_packet_itl().unwrap() {
+            decoded.extend(packet.into_iter().map(|s| s as f32 / 32768.0));
+        }
+        assert_eq!(decoded.len(), samples.len(), "{:?}", options);
+        let snr = snr_db(&samples, &decoded);
+        assert!(snr > 20.0, "Vorbis SNR too low for {:?}: {:.1} dB", options, snr);
+    }
+}
+
+#[test]
+fn rejects_out_of_range_settings() {
+    let _serial = common::serial();
+    common::load_session(&session_samples());
+    let dir = tempfile::tempdir().unwrap();
+    let guard = get_streamer().lock();
+    let streamer = guard.as_ref().unwrap();
+
+    let opus = temp_path(&dir, "bad.opus");
+    assert!(streamer.export_to_opus(&opus, &OpusOptions { bitrate_kbps: 2, ..Default::default() }).is_err());
+    assert!(streamer.export_to_opus(&opus, &OpusOptions { complexity: 11, ..Default::default() }).is_err());
+
+    let ogg = temp_path(&dir, "bad.ogg");
+    let bad_quality = VorbisOptions { quality: Some(1.5), ..Default::default() };
+    assert!(streamer.export_to_vorbis(&ogg, &bad_quality).is_err());
+}
@@ -0,0 +1,111 @@
+//! Writes sessions in awkwardly sized chunks and checks the waveform overview
+//! against peaks computed straight from the samples.
+
+mod common;
+
+use lyria_studio_lib::audio_stream::get_streamer;
+use lyria_studio_lib::peaks::Peaks;
+
+const RATE: usize = 48_000;
+
+/// A 440 Hz sine swelling from silence to full scale over `seconds`, with the
+/// right channel at half the level of the left.
+fn swell(seconds: usize) -> Vec<i16> {
+    let frames = RATE * seconds;
+    (0..frames)
+        .flat_map(|i| {
+            let level = i as f64 / frames as f64 * 32000.0;
+            let sample = (i as f64 * 440.0 * std::f64::consts::TAU / RATE as f64).sin() * level;
+            [sample as i16, (sample / 2.0) as i16]
+        })
+        .collect()
+}
+
+/// Written in chunks that split frames, as a misbehaving producer might.
+fn load_session(samples: &[i16]) {
+    common::load_session_in_chunks(samples, 7_777);
+}
+
+fn peaks(start: f64, end: f64, pixels: usize) -> Peaks {
+    get_streamer().lock().as_ref().unwrap().get_peaks(start, end, pixels).unwrap()
+}
+
+fn assert_close(actual: &Peaks, expected: &Peaks, tolerance: f32) {
+    assert_eq!(actual.max.len(), expected.max.len());
+    for (what, a, e) in [
+        ("min", &actual.min, &expected.min),
+        ("max", &actual.max, &expected.max),
+        ("rms", &actual.rms, &expected.rms),
+    ] {
+        for (pixel, (a, e)) in a.iter().zip(e).enumerate() {
+            assert!((a - e).abs() <= tolerance, "{} of pixel {} was {}, expected {}", what, pixel, a, e);
+        }
+    }
+}
+
+#[test]
+fn overview_matches_the_samples() {
+    let _serial = common::serial();
+    let samples = swell(20);
+    load_session(&samples);
+
+    // The whole session, and a range that starts and ends mid-bucket
+    assert_close(&peaks(0.0, 20.0, 800), &Peaks::from_samples(&samples, 2, 800), 0.01);
+    let (start, end) = (3.2109, 17.0071);
+    let range = &samples[(start * RATE as f64).round() as usize * 2..(end * RATE as f64).round() as usize * 2];
+    assert_close(&peaks(start, end, 333), &Peaks::from_samples(range, 2, 333), 0.01);
+
+    // The right channel is quieter, so the peaks are the left channel's
+    let whole = peaks(0.0, 20.0, 1);
+    assert!(whole.max[0] > 0.97 && whole.min[0] < -0.97);
+}
+
+#[test]
+fn zooming_past_the_cache_reads_samples() {
+    let _serial = common::serial();
+    let samples = swell(2);
+    load_session(&samples);
+
+    // One frame per pixel
+    let zoomed = peaks(1.0, 1.001, 48);
+    for (pixel, max) in zoomed.max.iter().enumerate() {
+        let left = samples[(RATE + pixel) * 2];
+        assert_eq!(*max, left as f32 / 32768.0);
+    }
+}
+
+#[test]
+fn follows_the_recording_as_it_grows() {
+    let _serial = common::serial();
+    let samples = swell(10);
+    load_session(&samples[..RATE * 2 * 5 + 1001]);
+    assert!(get_streamer().lock().as_ref().unwrap().get_peaks(6.0, 7.0, 10).is_err());
+
+    {
+        let mut guard = get_streamer().lock();
+        let streamer = guard.as_mut().unwrap();
+        for chunk in samples[RATE * 2 * 5 + 1001..].chunks(7_777) {
+            streamer.write_chunk(chunk).unwrap();
+        }
+    }
+    assert_close(&peaks(0.0, 10.0, 640), &Peaks::from_samples(&samples, 2, 640), 0.01);
+
+    // A full-width overview costs tens of kilobytes however long the session is
+    let json = serde_json::to_string(&peaks(0.0, 10.0, 2000)).unwrap();
+    assert!(json.len() < 80_000, "{} bytes", json.len());
+}
+
+#[test]
+fn rejects_bad_requests() {
+    let _serial = common::serial();
+    load_session(&swell(1));
+    let guard = get_streamer().lock();
+    let streamer = guard.as_ref().unwrap();
+
+    assert!(streamer.get_peaks(0.0, 1.0, 0).is_err());
+    assert!(streamer.get_peaks(0.0, 1.0, 1_000_000).is_err());
+    assert!(streamer.get_peaks(0.5, 0.5, 10).is_err());
+    assert!(streamer.get_peaks(2.0, 3.0, 10).is_err());
+    assert!(streamer.get_peaks(f64::NAN, 1.0, 10).is_err());
+    assert_eq!(streamer.get_peaks(0.5, 5.0, 10).unwrap().max.len(), 10);
+}
@@ -0,0 +1,103 @@
+//! Reads the session back as raw bytes, the way binary IPC and the `lyria-audio`
+//! URI scheme serve it, and checks ranged reads stitch together into the same audio
+//! the JSON path returns.
+
+mod common;
+
+use lyria_studio_lib::audio_stream::{get_streamer, AudioStreamer};
+use lyria_studio_lib::edits::EditList;
+use lyria_studio_lib::transfer::{parse_range, samples_from_bytes, AudioResource, MAX_RANGE_BYTES};
+
+const RATE: usize = 48_000;
+
+/// Stereo noise, so misplaced bytes can't line up by accident.
+fn noise(seconds: usize) -> Vec<i16> {
+    let mut seed = 7u32;
+    (0..RATE * 2 * seconds)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (seed >> 16) as i16
+        })
+        .collect()
+}
+
+/// The whole resource, fetched the way a media element does: open-ended ranges
+/// from wherever the previous response stopped.
+fn fetch_in_ranges(streamer: &AudioStreamer, resource: AudioResource) -> Vec<u8> {
+    let total = streamer.resource_len(resource);
+    let mut bytes = Vec::new();
+    while (bytes.len() as u64) < total {
+        let (start, end) = parse_range(&format!("bytes={}-", bytes.len()), total).unwrap();
+        assert!(end - start <= MAX_RANGE_BYTES);
+        bytes.extend(streamer.read_resource(resource, start, end).unwrap());
+    }
+    bytes
+}
+
+#[test]
+fn ranged_wav_reads_match_the_session() {
+    let _serial = common::serial();
+    let samples = noise(30);
+    common::load_session(&samples);
+    let guard = get_streamer().lock();
+    let streamer = guard.as_ref().unwrap();
+
+    let wav = fetch_in_ranges(streamer, AudioResource::Wav);
+    assert_eq!(wav.len(), 44 + samples.len() * 2);
+    let reader = hound::WavReader::new(std::io::Cursor::new(&wav)).unwrap();
+    assert_eq!(reader.spec().sample_rate, RATE as u32);
+    assert_eq!(reader.spec().channels, 2);
+    let decoded: Vec<i16> = reader.into_samples().map(Result::unwrap).collect();
+    assert_eq!(decoded, samples);
+
+    // Ranges that split the header, a sample and a frame
+    for (start, end) in [(10, 50), (43, 48), (1001, 1002), (44 + 4 * 999 + 3, 44 + 4 * 1005 + 1)] {
+        let bytes = streamer.read_resource(AudioResource::Wav, start, end).unwrap();
+        assert_eq!(bytes, wav[start as usize..end as usize], "bytes {}..{}", start, end);
+    }
+}
+
+#[test]
+fn raw_pcm_follows_the_edits() {
+    let _serial = common::serial();
+    common::load_session(&noise(4));
+    get_streamer()
+        .lock()
+        .as_mut()
+        .unwrap()
+        .set_edits(EditList { trim_start: 0.5, trim_end: Some(3.5), ..Default::default() })
+        .unwrap();
+
+    let guard = get_streamer().lock();
+    let streamer = guard.as_ref().unwrap();
+    let json_path = streamer.get_all_samples().unwrap();
+    assert_eq!(samples_from_bytes(&fetch_in_ranges(streamer, AudioResource::Pcm)).unwrap(), json_path);
+
+    let second = samples_from_bytes(&streamer.read_pcm(1.0, Some(2.0)).unwrap()).unwrap();
+    assert_eq!(second, json_path[RATE * 2..RATE * 4]);
+    let tail = samples_from_bytes(&streamer.read_pcm(2.5, None).unwrap()).unwrap();
+    assert_eq!(tail, json_path[RATE * 5..]);
+    assert!(streamer.read_pcm(-1.0, None).is_err());
+    assert!(streamer.read_pcm(2.0, Some(1.0)).is_err());
+    assert!(streamer.read_pcm(5.0, None).unwrap().is_empty());
+}
+
+#[test]
+fn parses_range_headers() {
+    assert_eq!(parse_range("bytes=0-99", 1000), Ok((0, 100)));
+    assert_eq!(parse_range("bytes=900-", 1000), Ok((900, 1000)));
+    assert_eq!(parse_range("bytes=-100", 1000), Ok((900, 1000)));
+    assert_eq!(parse_range("bytes=-5000", 1000), Ok((0, 1000)));
+    assert_eq!(parse_range("bytes=990-5000", 1000), Ok((990, 1000)));
+    assert_eq!(parse_range("bytes=0-", 1 << 30), Ok((0, MAX_RANGE_BYTES)));
+
+    assert!(parse_range("bytes=1000-", 1000).is_err());
+    assert!(parse_range("bytes=5-4", 1000).is_err());
+    assert!(parse_range("bytes=-0", 1000).is_err());
+    assert!(parse_range("bytes=0-1,5-9", 1000).is_err());
+    assert!(parse_range("items=0-1", 1000).is_err());
+    assert!(parse_range("bytes=0-", 0).is_err());
+
+    assert_eq!(samples_from_bytes(&[1, 0, 0xff, 0xff]), Ok(vec![1, -1]));
+    assert!(samples_from_bytes(&[1, 0, 0]).is_err());
+}
@@ -0,0 +1,158 @@
+//! Exports session audio to WAV at each rate and bit depth and reads it back, and
+//! measures the noise the quantizer's dither adds.
+
+mod common;
+
+use lyria_studio_lib::audio_stream::get_streamer;
+use lyria_studio_lib::wav::{Dither, Quantizer, WavOptions};
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+const RATE: usize = 48_000;
+
+/// Two seconds of a stereo 1 kHz sine with a little noise, so no two samples
+/// repeat a pattern rounding could hide.
+fn session_samples() -> Vec<i16> {
+    let mut seed = 0x2545_f491_u32;
+    (0..RATE * 2)
+        .flat_map(|i| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let noise = (seed >> 24) as f64 - 128.0;
+            let tone = (i as f64 * 1000.0 * std::f64::consts::TAU / RATE as f64).sin() * 16_000.0;
+            [(tone + noise) as i16, (tone / 2.0 - noise) as i16]
+        })
+        .collect()
+}
+
+/// Export with `options` and return the file's spec and its samples scaled to -1..1.
+fn export(dir: &tempfile::TempDir, options: WavOptions) -> (hound::WavSpec, Vec<f64>) {
+    let path = dir.path().join("session.wav").to_string_lossy().into_owned();
+    get_streamer().lock().as_ref().unwrap().export_to_wav(&path, &options).unwrap();
+    let mut reader = hound::WavReader::open(&path).unwrap();
+    let spec = reader.spec();
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().map(|s| s.unwrap() as f64).collect(),
+        hound::SampleFormat::Int => {
+            let scale = (1u32 << (spec.bits_per_sample - 1)) as f64;
+            reader.samples::<i32>().map(|s| s.unwrap() as f64 / scale).collect()
+        }
+    };
+    (spec, samples)
+}
+
+#[test]
+fn unprocessed_audio_exports_exactly_at_every_depth() {
+    let _serial = common::serial();
+    let session = session_samples();
+    common::load_session(&session);
+    let dir = tempfile::tempdir().unwrap();
+    let expected: Vec<f64> = session.iter().map(|&s| s as f64 / 32768.0).collect();
+
+    for (bits, format) in [(16, hound::SampleFormat::Int), (24, hound::SampleFormat::Int), (32, hound::SampleFormat::Float)] {
+        let (spec, samples) = export(&dir, WavOptions { bits_per_sample: bits, ..Default::default() });
+        assert_eq!((spec.sample_rate, spec.bits_per_sample, spec.sample_format), (48_000, bits, format));
+        // Nothing to dither: the 16-bit session samples survive untouched
+        assert_eq!(samples, expected, "{}-bit export changed the audio", bits);
+    }
+
+    let streamer = get_streamer().lock();
+    let streamer = streamer.as_ref().unwrap();
+    let path = dir.path().join("bad.wav").to_string_lossy().into_owned();
+    assert!(streamer.export_to_wav(&path, &WavOptions { sample_rate: 22_050, ..Default::default() }).is_err());
+    assert!(streamer.export_to_wav(&path, &WavOptions { bits_per_sample: 8, ..Default::default() }).is_err());
+}
+
+#[test]
+fn export_reads_a_snapshot_while_the_session_grows() {
+    let _serial = common::serial();
+    let session = session_samples();
+    let (first, rest) = session.split_at(RATE);
+    common::load_session(first);
+    let snapshot = get_streamer().lock().as_ref().unwrap().export_snapshot();
+
+    // The lock is free while the snapshot exports, so the generator can keep writing
+    get_streamer().lock().as_mut().unwrap().write_chunk(rest).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("snapshot.wav").to_string_lossy().into_owned();
+    snapshot.export_to_wav(&path, &WavOptions::default()).unwrap();
+
+    let exported: Vec<i16> = hound::WavReader::open(&path).unwrap().samples::<i16>().map(|s| s.unwrap()).collect();
+    assert_eq!(exported, first);
+}
+
+#[test]
+fn resamples_to_cd_and_high_rates() {
+    let _serial = common::serial();
+    let session = session_samples();
+    common::load_session(&session);
+    let dir = tempfile::tempdir().unwrap();
+
+    for (rate, bits) in [(44_100, 16), (88_200, 24), (96_000, 32)] {
+        let (spec, samples) = export(&dir, WavOptions { sample_rate: rate, bits_per_sample: bits, dither: Dither::Tpdf });
+        assert_eq!(spec.sample_rate, rate);
+        assert_eq!(samples.len(), rate as usize * 2 * 2, "{} Hz export is the wrong length", rate);
+
+        // What is left after taking away the tone is the source's noise, about
+        // 74 LSB RMS; drift or a gain error would leave much more
+        let range = rate as usize / 10..rate as usize * 19 / 10;
+        let residual = range
+            .clone()
+            .map(|i| {
+                let t = i as f64 / rate as f64;
+                let tone = (t * 1000.0 * std::f64::consts::TAU).sin() * 16_000.0 / 32768.0;
+                (samples[i * 2] - tone).powi(2)
+            })
+            .sum::<f64>();
+        let rms = (residual / range.len() as f64).sqrt() * 32768.0;
+        assert!(rms < 80.0, "{} Hz export strays {} LSB RMS from the tone", rate, rms);
+    }
+}
+
+/// Power of `error` in the bins below and above `split_hz`, at `rate`.
+fn band_powers(error: &[f64], rate: f64, split_hz: f64) -> (f64, f64) {
+    let mut buffer: Vec<Complex<f64>> = error.iter().map(|&e| Complex::new(e, 0.0)).collect();
+    FftPlanner::new().plan_fft_forward(buffer.len()).process(&mut buffer);
+    let split = (split_hz / rate * buffer.len() as f64) as usize;
+    let half = &buffer[1..buffer.len() / 2];
+    let power = |bins: &[Complex<f64>]| bins.iter().map(|b| b.norm_sqr()).sum::<f64>();
+    (power(&half[..split]), power(&half[split..]))
+}
+
+#[test]
+fn dither_decorrelates_and_shapes_the_noise() {
+    let frames = 1 << 16;
+    let quiet: Vec<f32> = (0..frames)
+        .map(|i| ((i as f64 * 1000.0 * std::f64::consts::TAU / 44_100.0).sin() * 0.4 / 32768.0) as f32)
+        .collect();
+    let quantize = |dither| {
+        let mut out = Vec::new();
+        Quantizer::new(16, dither, 1).quantize(&quiet, &mut out);
+        out
+    };
+
+    // A sine under half an LSB rounds away to nothing without dither, but its
+    // shape survives in the dithered output
+    assert!(quantize(Dither::None).iter().all(|&s| s == 0));
+    let dithered = quantize(Dither::Tpdf);
+    let correlation: f64 = dithered.iter().zip(&quiet).map(|(&d, &q)| d as f64 * q as f64 * 32768.0).sum::<f64>()
+        / quiet.iter().map(|&q| (q as f64 * 32768.0).powi(2)).sum::<f64>();
+    assert!((correlation - 1.0).abs() < 0.1, "dithered output tracks the sine by {}", correlation);
+
+    // TPDF noise adds a sixth of an LSB squared to the twelfth from rounding
+    let error = |out: &[i32]| -> Vec<f64> {
+        out.iter().zip(&quiet).map(|(&o, &q)| o as f64 - q as f64 * 32768.0).collect()
+    };
+    let flat = error(&dithered);
+    let power = flat.iter().map(|e| e * e).sum::<f64>() / frames as f64;
+    assert!((power - 0.25).abs() < 0.02, "TPDF noise power {}", power);
+
+    // Shaping moves noise out of the low band at the cost of more in total
+    let shaped = error(&quantize(Dither::Shaped));
+    let (flat_low, flat_high) = band_powers(&flat, 44_100.0, 4000.0);
+    let (shaped_low, shaped_high) = band_powers(&shaped, 44_100.0, 4000.0);
+    let gain_db = 10.0 * (shaped_low / flat_low).log10();
+    assert!(gain_db < -10.0, "shaping only lowered noise below 4 kHz by {} dB", -gain_db);
+    assert!(shaped_low + shaped_high > flat_low + flat_high);
+}
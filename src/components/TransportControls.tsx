@@ -10,6 +10,7 @@ import {
 } from "lucide-react"
 import { Button } from "@/components/ui/Button"
 import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from "@/components/ui/Select"
+import { Switch } from "@/components/ui/Switch"
 import { Tooltip, TooltipContent, TooltipTrigger } from "@/components/ui/Tooltip"
 import { useAppStore } from "@/stores/app-store"
 import { getAudioEngine } from "@/lib/audio-engine"
@@ -17,7 +18,18 @@ import { useAudioEngine } from "@/hooks/useAudioEngine"
 import { saveAudioFile, encodeWavDirect } from "@/lib/audio-export"
 import { save } from "@tauri-apps/plugin-dialog"
 import { writeFile } from "@tauri-apps/plugin-fs"
-import { audioExport, audioExportFormat } from "@/lib/native-audio"
+import {
+  audioExport,
+  audioExportFormat,
+  audioExportMp3,
+  audioExportWav,
+  audioSetMetadata,
+  audioSetNormalization,
+  audioSyncVocalTracks,
+  onAudioLoudness,
+  type AudioLoudnessEvent,
+  type WavExportOptions,
+} from "@/lib/native-audio"
 import { formatTime, cn } from "@/lib/utils"
 import { useState, useEffect, useRef } from "react"
 import { debugLog } from "@/lib/debug-logger"
@@ -31,11 +43,68 @@ const TRACK_LENGTHS = [
 
 const AUDIO_FORMATS = [
   { value: "wav", label: "WAV (16-bit)" },
-  { value: "flac", label: "FLAC (24-bit)" },
+  { value: "wav-cd", label: "WAV (CD, 44.1kHz)" },
+  { value: "wav-24", label: "WAV (24-bit)" },
+  { value: "wav-96", label: "WAV (96kHz/24-bit)" },
+  { value: "wav-float", label: "WAV (32-bit float)" },
+  { value: "flac", label: "FLAC (lossless)" },
   { value: "mp3-320", label: "MP3 (320kbps)" },
+  { value: "mp3-v0", label: "MP3 (VBR V0)" },
   { value: "mp3-128", label: "MP3 (128kbps)" },
+  { value: "opus", label: "Opus (160kbps)" },
+  { value: "ogg", label: "Ogg Vorbis (192kbps)" },
 ]
 
+type SaveFormat =
+  | "wav" | "wav-cd" | "wav-24" | "wav-96" | "wav-float"
+  | "flac" | "mp3-320" | "mp3-v0" | "mp3-128" | "opus" | "ogg"
+
+// Rate and depth of each WAV deliverable; shaped dither for CD, where 16 bits is final
+const WAV_EXPORT: Partial<Record<SaveFormat, WavExportOptions>> = {
+  "wav-cd": { sample_rate: 44100, bits_per_sample: 16, dither: "shaped" },
+  "wav-24": { sample_rate: 48000, bits_per_sample: 24 },
+  "wav-96": { sample_rate: 96000, bits_per_sample: 24 },
+  "wav-float": { sample_rate: 48000, bits_per_sample: 32 },
+}
+
+// Streaming-platform loudness target used by the "Normalize" switch
+const NORMALIZATION = { target_lufs: -14, true_peak_ceiling_dbtp: -1 }
+
+function formatLufs(value: number | null): string {
+  return value === null ? "n/a" : value.toFixed(1)
+}
+
+// Loudness line for the save confirmation, when the export was normalized
+function describeLoudness(event?: AudioLoudnessEvent): string {
+  const result = event?.normalization
+  if (!event || !result) return ""
+  const limited = result.reached_target ? "" : " (limited by true-peak ceiling)"
+  return `\nLoudness: ${formatLufs(event.report.integrated_lufs)} → ${formatLufs(result.integrated_lufs)} LUFS, ` +
+    `true peak ${formatLufs(result.true_peak_dbtp)} dBTP${limited}`
+}
+
+// Filename suffix for each MP3 setting
+const MP3_LABELS: Partial<Record<SaveFormat, string>> = {
+  "mp3-320": "-320kbps",
+  "mp3-v0": "-v0",
+  "mp3-128": "-128kbps",
+}
+
+// File extension, dialog filter name and bitrate for native export
+const NATIVE_EXPORT: Record<SaveFormat, { ext: string; name: string; bitrate: number }> = {
+  wav: { ext: "wav", name: "WAV Audio", bitrate: 0 },
+  "wav-cd": { ext: "wav", name: "WAV Audio", bitrate: 0 },
+  "wav-24": { ext: "wav", name: "WAV Audio", bitrate: 0 },
+  "wav-96": { ext: "wav", name: "WAV Audio", bitrate: 0 },
+  "wav-float": { ext: "wav", name: "WAV Audio", bitrate: 0 },
+  flac: { ext: "flac", name: "FLAC Audio", bitrate: 0 },
+  "mp3-320": { ext: "mp3", name: "MP3 Audio", bitrate: 320 },
+  "mp3-v0": { ext: "mp3", name: "MP3 Audio", bitrate: 0 },
+  "mp3-128": { ext: "mp3", name: "MP3 Audio", bitrate: 128 },
+  opus: { ext: "opus", name: "Opus Audio", bitrate: 160 },
+  ogg: { ext: "ogg", name: "Ogg Vorbis Audio", bitrate: 192 },
+}
+
 export function TransportControls() {
   const { 
     elapsedTime,
@@ -69,7 +138,8 @@ export function TransportControls() {
   } = useAudioEngine()
 
   const [isSaving, setIsSaving] = useState(false)
-  const [saveFormat, setSaveFormat] = useState<"wav" | "flac" | "mp3-320" | "mp3-128">("wav")
+  const [saveFormat, setSaveFormat] = useState<SaveFormat>("wav")
+  const [normalize, setNormalize] = useState(false)
   const [isCustomLength, setIsCustomLength] = useState(false)
   const [customLengthInput, setCustomLengthInput] = useState("")
   const [isTestRunning, setIsTestRunning] = useState(false)
@@ -134,10 +204,8 @@ export function TransportControls() {
         debugLog.info("[Save] Using native export (Rust-based)")
         
         // Determine file extension and dialog filter based on format
-        const ext = saveFormat.startsWith("mp3") ? "mp3" : saveFormat === "flac" ? "flac" : "wav"
-        const formatName = ext === "mp3" ? "MP3 Audio" : ext === "flac" ? "FLAC Audio" : "WAV Audio"
-        const bitrate = saveFormat === "mp3-320" ? 320 : saveFormat === "mp3-128" ? 128 : 0
-        const bitrateLabel = saveFormat === "mp3-320" ? "-320kbps" : saveFormat === "mp3-128" ? "-128kbps" : ""
+        const { ext, name: formatName, bitrate } = NATIVE_EXPORT[saveFormat]
+        const bitrateLabel = MP3_LABELS[saveFormat] ?? ""
         
         const defaultName = `lyria-${new Date().toISOString().slice(0, 10)}-${Date.now()}${bitrateLabel}.${ext}`
         
@@ -159,25 +227,41 @@ export function TransportControls() {
         
         debugLog.info(`[Save] Exporting to: ${finalPath} (format: ${saveFormat}, bitrate: ${bitrate})`)
         
-        // Use format-aware export for MP3/FLAC, legacy export for WAV
-        if (saveFormat === "wav") {
-          await audioExport(finalPath)
-        } else {
-          await audioExportFormat(finalPath, ext, bitrate)
+        const trackName = finalPath.split('/').pop()?.replace(/\.(wav|mp3|flac|opus|ogg)$/, '') || 'track'
+        await audioSetMetadata({ title: trackName })
+        await audioSetNormalization(normalize ? NORMALIZATION : null)
+        await audioSyncVocalTracks(useAppStore.getState().vocalTracks)
+
+        const loudness: { event?: AudioLoudnessEvent } = {}
+        const unlistenLoudness = await onAudioLoudness((event) => { loudness.event = event })
+        try {
+          // Use format-aware export for compressed formats, legacy export for WAV
+          const wavOptions = WAV_EXPORT[saveFormat]
+          if (saveFormat === "wav") {
+            await audioExport(finalPath)
+          } else if (wavOptions) {
+            await audioExportWav(finalPath, wavOptions)
+          } else if (saveFormat === "mp3-v0") {
+            await audioExportMp3(finalPath, { mode: "vbr", vbr_quality: 0 })
+          } else {
+            await audioExportFormat(finalPath, ext, bitrate)
+          }
+        } finally {
+          unlistenLoudness()
         }
         debugLog.info("[Save] Native export complete!")
         
         const duration = nativeChunks * 2 // ~2 sec per chunk
         addSavedTrack({
           id: `${Date.now()}`,
-          name: finalPath.split('/').pop()?.replace(/\.(wav|mp3|flac)$/, '') || 'track',
+          name: trackName,
           path: finalPath,
           duration,
           createdAt: Date.now(),
         })
         
         // Don't clear audio - user may want to save in multiple formats
-        alert(`Saved: ${finalPath.split('/').pop()}`)
+        alert(`Saved: ${finalPath.split('/').pop()}${describeLoudness(loudness.event)}`)
         return
       }
       
@@ -194,14 +278,20 @@ export function TransportControls() {
     
       const ext = saveFormat.startsWith("mp3") ? "mp3" : "wav"
       // Include bitrate in filename for MP3 formats
-      const bitrateLabel = saveFormat === "mp3-320" ? "-320kbps" : saveFormat === "mp3-128" ? "-128kbps" : ""
+      const bitrateLabel = MP3_LABELS[saveFormat] ?? ""
       const defaultName = `lyria-${new Date().toISOString().slice(0, 10)}-${Date.now()}${bitrateLabel}.${ext}`
       
       debugLog.info(`[Save] Converting ${audioData.length} samples to ${saveFormat}...`)
       
       // Wrap encoding in a timeout to prevent hanging
       let blob: Blob
-      const encodePromise = saveAudioFile(audioData, saveFormat)
+      // The JS encoders have no Opus, Vorbis, VBR or high-resolution WAV support, so
+      // those fall back here
+      const jsFormat =
+        saveFormat === "mp3-v0" ? "mp3-320"
+          : saveFormat === "mp3-320" || saveFormat === "mp3-128" || saveFormat === "flac" ? saveFormat
+            : "wav"
+      const encodePromise = saveAudioFile(audioData, jsFormat)
       const encodeTimeout = new Promise<null>((resolve) => {
         setTimeout(() => resolve(null), 5000) // 5 second timeout for encoding
       })
@@ -388,7 +478,7 @@ export function TransportControls() {
             <div>
               <Select 
                 value={saveFormat} 
-                onValueChange={(v) => setSaveFormat(v as "wav" | "flac" | "mp3-320" | "mp3-128")}
+                onValueChange={(v) => setSaveFormat(v as SaveFormat)}
               >
                 <SelectTrigger className="w-36 h-12 text-sm">
                   <SelectValue />
@@ -407,6 +497,18 @@ export function TransportControls() {
             Select audio format for export
           </TooltipContent>
         </Tooltip>
+
+        <Tooltip>
+          <TooltipTrigger asChild>
+            <label className="flex items-center gap-1.5 px-1 text-xs text-text-muted cursor-pointer">
+              <Switch checked={normalize} onCheckedChange={setNormalize} />
+              LUFS
+            </label>
+          </TooltipTrigger>
+          <TooltipContent>
+            Normalize saved files to {NORMALIZATION.target_lufs} LUFS with a {NORMALIZATION.true_peak_ceiling_dbtp} dBTP true-peak ceiling
+          </TooltipContent>
+        </Tooltip>
         
         <Tooltip>
           <TooltipTrigger asChild>
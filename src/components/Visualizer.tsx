@@ -1,6 +1,12 @@
 import { useRef, useEffect } from "react"
 import { useAppStore } from "@/stores/app-store"
 import { cn } from "@/lib/utils"
+import { audioGetPeaks, audioGetStatus, onAudioChunk, onAudioMeter, type AudioMeterEvent, type AudioPeaks } from "@/lib/native-audio"
+
+// Upper bound on the overview width requested from Rust
+const MAX_OVERVIEW_PIXELS = 2048
+// Native meter readings older than this are treated as stopped playback
+const METER_STALE_MS = 250
 
 interface VisualizerProps {
   className?: string
@@ -10,10 +16,62 @@ export function Visualizer({ className }: VisualizerProps) {
   const canvasRef = useRef<HTMLCanvasElement>(null)
   const animationRef = useRef<number | null>(null)
   const startTimeRef = useRef<number>(Date.now())
+  const overviewRef = useRef<AudioPeaks | null>(null)
+  const meterRef = useRef<{ reading: AudioMeterEvent; at: number } | null>(null)
   const isActive = useAppStore((state) => state.isGenerating || state.isPlaying || state.isPlayingBack)
   const connectionStatus = useAppStore((state) => state.connectionStatus)
   const connectionError = useAppStore((state) => state.connectionError)
 
+  // Overview of the native session, refreshed as chunks arrive
+  useEffect(() => {
+    if (!("__TAURI_INTERNALS__" in window)) return
+
+    let cancelled = false
+    let unlisten: (() => void) | undefined
+    const refresh = async () => {
+      try {
+        const status = await audioGetStatus()
+        if (status.recordedDuration <= 0) {
+          overviewRef.current = null
+          return
+        }
+        const width = canvasRef.current?.getBoundingClientRect().width ?? 512
+        const pixels = Math.max(1, Math.min(MAX_OVERVIEW_PIXELS, Math.round(width)))
+        overviewRef.current = await audioGetPeaks(0, status.recordedDuration, pixels)
+      } catch {
+        overviewRef.current = null
+      }
+    }
+
+    refresh()
+    onAudioChunk(() => { refresh() }).then((fn) => {
+      if (cancelled) fn()
+      else unlisten = fn
+    })
+    return () => {
+      cancelled = true
+      unlisten?.()
+    }
+  }, [])
+
+  // Spectrum and levels of native playback, computed in Rust
+  useEffect(() => {
+    if (!("__TAURI_INTERNALS__" in window)) return
+
+    let cancelled = false
+    let unlisten: (() => void) | undefined
+    onAudioMeter((reading) => {
+      meterRef.current = { reading, at: performance.now() }
+    }).then((fn) => {
+      if (cancelled) fn()
+      else unlisten = fn
+    })
+    return () => {
+      cancelled = true
+      unlisten?.()
+    }
+  }, [])
+
   useEffect(() => {
     const canvas = canvasRef.current
     if (!canvas) return
@@ -63,6 +121,18 @@ export function Visualizer({ className }: VisualizerProps) {
         ctx.fillStyle = isDark ? "rgb(18, 18, 28)" : "rgb(248, 248, 252)"
         ctx.fillRect(0, 0, rect.width, rect.height)
 
+        const overview = overviewRef.current
+        if (overview && overview.max.length > 0) {
+          ctx.fillStyle = isDark ? "rgba(147, 112, 219, 0.15)" : "rgba(124, 58, 237, 0.12)"
+          const mid = rect.height / 2
+          const step = rect.width / overview.max.length
+          for (let i = 0; i < overview.max.length; i++) {
+            const top = mid - overview.max[i] * mid
+            const bottom = mid - overview.min[i] * mid
+            ctx.fillRect(i * step, top, Math.max(step, 1), Math.max(bottom - top, 1))
+          }
+        }
+
         if (waveformData && currentlyActive && !isBuffering) {
           ctx.beginPath()
           ctx.strokeStyle = isDark ? "rgba(147, 112, 219, 0.4)" : "rgba(147, 112, 219, 0.6)"
@@ -86,7 +156,36 @@ export function Visualizer({ className }: VisualizerProps) {
           ctx.stroke()
         }
 
-        if (analyzerData && currentlyActive && !isBuffering) {
+        const meter = meterRef.current
+        const nativeMeter = meter && timestamp - meter.at < METER_STALE_MS ? meter.reading : null
+
+        if (nativeMeter && !analyzerData && currentlyActive) {
+          const bands = nativeMeter.spectrum
+          const barWidth = (rect.width / bands.length) * 0.7
+          const gap = (rect.width / bands.length) * 0.3
+          ctx.fillStyle = isDark ? "rgba(139, 92, 246, 0.8)" : "rgba(124, 58, 237, 0.8)"
+
+          for (let i = 0; i < bands.length; i++) {
+            const normalizedValue = Math.max(0, (bands[i] + 100) / 100)
+            const barHeight = Math.max(2, normalizedValue * rect.height * 0.8)
+            const x = i * (barWidth + gap) + gap / 2
+            ctx.beginPath()
+            ctx.roundRect(x, rect.height - barHeight, barWidth, barHeight, 2)
+            ctx.fill()
+          }
+
+          // Peak and RMS level per channel along the right edge
+          const { peak_db, rms_db } = nativeMeter.levels
+          peak_db.forEach((peak, channel) => {
+            const x = rect.width - 8 * (peak_db.length - channel)
+            const peakHeight = Math.max(0, (peak + 60) / 60) * rect.height
+            const rmsHeight = Math.max(0, (rms_db[channel] + 60) / 60) * rect.height
+            ctx.fillStyle = isDark ? "rgba(34, 197, 94, 0.35)" : "rgba(22, 163, 74, 0.35)"
+            ctx.fillRect(x, rect.height - peakHeight, 6, peakHeight)
+            ctx.fillStyle = peak > -1 ? "rgba(239, 68, 68, 0.9)" : isDark ? "rgba(34, 197, 94, 0.9)" : "rgba(22, 163, 74, 0.9)"
+            ctx.fillRect(x, rect.height - rmsHeight, 6, rmsHeight)
+          })
+        } else if (analyzerData && currentlyActive && !isBuffering) {
           const barCount = 64
           const barWidth = (rect.width / barCount) * 0.7
           const gap = (rect.width / barCount) * 0.3
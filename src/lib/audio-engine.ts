@@ -9,20 +9,29 @@ import {
   audioWriteChunkBase64,
   audioStartPlayback, 
   audioStopPlayback, 
-  audioGetStatus, 
+  onAudioPlaybackState,
+  onAudioPosition,
   audioClear,
   audioExport,
   audioGetSamples,
+  audioSetMetadata,
   convertStereoToInt16,
   type AudioStatus 
 } from "./native-audio"
 import {
   startRustGeneration,
   stopRustGeneration,
-  getRustGenerationStatus,
   isRustGenerating,
+  onRustGenerationStatus,
   type GenerationStatus
 } from "./rust-lyria"
+import type { UnlistenFn } from "@tauri-apps/api/event"
+
+const LYRIA_MODEL_NAMES: Record<LyriaModelType, string> = {
+  realtime: "models/lyria-realtime-exp",
+  lyria2: "lyria-002",
+  lyria3: "lyria-003",
+}
 
 export class AudioEngine {
   private audioContext: AudioContext | null = null
@@ -58,12 +67,13 @@ export class AudioEngine {
   // Native audio mode for long tracks
   private useNativeAudio = false
   private nativeChunkCount = 0
-  private nativeStatusInterval: number | null = null
+  private nativeListeners: Promise<UnlistenFn>[] = []
+  private nativeIsPlaying = false
   private nativeTrackLength = 0  // Cache to avoid repeated store access
   
   // Rust-native generation mode (completely bypasses JavaScript)
   private useRustGeneration = false
-  private rustStatusInterval: number | null = null
+  private rustListeners: Promise<UnlistenFn>[] = []
 
   async initialize(): Promise<void> {
     this.audioContext = new AudioContext({ sampleRate: 48000 })
@@ -410,7 +420,7 @@ export class AudioEngine {
         
         try {
           await audioStartPlayback()
-          this.startNativeStatusPolling()
+          this.listenToNativePlayback()
         } catch (e) {
           console.error("[Audio] Failed to start native playback:", e)
           this.onError?.("Native playback failed: " + e)
@@ -458,100 +468,92 @@ export class AudioEngine {
       // Delay playback start to ensure chunks are written
       setTimeout(() => {
         audioStartPlayback().then(() => {
-          this.startNativeStatusPolling()
+          this.listenToNativePlayback()
         }).catch(() => {})
       }, 500)
     }
   }
   
-  private startNativeStatusPolling(): void {
-    if (this.nativeStatusInterval) {
-      clearInterval(this.nativeStatusInterval)
-    }
-    
-    this.nativeStatusInterval = window.setInterval(async () => {
-      try {
-        const status = await audioGetStatus()
-        
-        // Update playback position for UI
-        if (status.isPlaying) {
-          this.lastPlayedTime = status.position
-        }
-        
-        // Check if playback finished
-        if (!status.isPlaying && this.isPlayingFromQueue) {
+  // Follow native playback through its events rather than polling its status
+  private listenToNativePlayback(): void {
+    this.stopListeningToNativePlayback()
+    this.nativeIsPlaying = true
+    this.nativeListeners = [
+      onAudioPosition(({ heard_position }) => {
+        this.lastPlayedTime = heard_position
+      }),
+      onAudioPlaybackState(({ is_playing }) => {
+        this.nativeIsPlaying = is_playing
+        if (!is_playing && this.isPlayingFromQueue) {
           console.log("[Audio] Native playback finished")
-          this.stopNativeStatusPolling()
+          this.stopListeningToNativePlayback()
           this.onStatusChange?.("Playback complete")
         }
-      } catch (e) {
-        // Ignore polling errors
-      }
-    }, 250) // Poll every 250ms
+      }),
+    ]
   }
   
-  private stopNativeStatusPolling(): void {
-    if (this.nativeStatusInterval) {
-      clearInterval(this.nativeStatusInterval)
-      this.nativeStatusInterval = null
+  private stopListeningToNativePlayback(): void {
+    for (const listener of this.nativeListeners) {
+      listener.then((unlisten) => unlisten()).catch(() => {})
     }
+    this.nativeListeners = []
+    this.nativeIsPlaying = false
+  }
+  
+  // Rust generation reports every state change and throttled progress as events
+  private listenToRustGeneration(): void {
+    this.stopListeningToRustGeneration()
+    this.rustListeners = [
+      onRustGenerationStatus((status) => this.handleRustStatus(status)),
+      onAudioPosition(({ heard_position }) => {
+        this.lastPlayedTime = heard_position
+      }),
+    ]
   }
   
-  // Rust generation status polling
-  private startRustStatusPolling(): void {
-    if (this.rustStatusInterval) {
-      clearInterval(this.rustStatusInterval)
+  private handleRustStatus(status: GenerationStatus): void {
+    console.log("[Rust Status]", status.state, status.chunks_received, "chunks,", status.duration_seconds.toFixed(1) + "s")
+    
+    // Update duration for UI
+    this.generatedDuration = status.duration_seconds
+    
+    // Update status with progress
+    let statusText = ""
+    if (status.state === "connecting") {
+      statusText = "Connecting to Lyria API..."
+    } else if (status.state === "connected") {
+      statusText = "Connected - waiting for setup..."
+    } else if (status.state === "buffering") {
+      const percent = Math.round((status.chunks_received / 15) * 100)
+      statusText = `Buffering: ${status.chunks_received}/15 chunks (${Math.min(percent, 100)}%)`
+    } else if (status.state === "generating" || status.state === "playing") {
+      const percent = Math.round((status.duration_seconds / this.nativeTrackLength) * 100)
+      const remaining = Math.max(0, this.nativeTrackLength - status.duration_seconds)
+      statusText = `Generating: ${status.duration_seconds.toFixed(0)}s / ${this.nativeTrackLength}s (${percent}%) - ${remaining.toFixed(0)}s remaining`
+    } else if (status.state === "completed") {
+      this.stopListeningToRustGeneration()
+      statusText = "Generation complete - ready to save"
+      useAppStore.getState().setHasCapturedAudio(true)
+      useAppStore.getState().setIsGenerating(false)
+    } else if (status.state === "error") {
+      this.stopListeningToRustGeneration()
+      statusText = `Error: ${status.error || "Generation failed"}`
+      this.onError?.(status.error || "Generation failed")
+      useAppStore.getState().setIsGenerating(false)
+    } else {
+      statusText = `Status: ${status.state}`
     }
     
-    this.rustStatusInterval = window.setInterval(async () => {
-      try {
-        const status = await getRustGenerationStatus()
-        console.log("[Rust Status]", status.state, status.chunks_received, "chunks,", status.duration_seconds.toFixed(1) + "s")
-        
-        // Update duration for UI
-        this.generatedDuration = status.duration_seconds
-        this.lastPlayedTime = status.duration_seconds
-        
-        // Update status with progress
-        let statusText = ""
-        if (status.state === "connecting") {
-          statusText = "Connecting to Lyria API..."
-        } else if (status.state === "connected") {
-          statusText = "Connected - waiting for setup..."
-        } else if (status.state === "buffering") {
-          const percent = Math.round((status.chunks_received / 15) * 100)
-          statusText = `Buffering: ${status.chunks_received}/15 chunks (${Math.min(percent, 100)}%)`
-        } else if (status.state === "generating" || status.state === "playing") {
-          const percent = Math.round((status.duration_seconds / this.nativeTrackLength) * 100)
-          const remaining = Math.max(0, this.nativeTrackLength - status.duration_seconds)
-          statusText = `Generating: ${status.duration_seconds.toFixed(0)}s / ${this.nativeTrackLength}s (${percent}%) - ${remaining.toFixed(0)}s remaining`
-        } else if (status.state === "completed") {
-          this.stopRustStatusPolling()
-          statusText = "Generation complete - ready to save"
-          useAppStore.getState().setHasCapturedAudio(true)
-          useAppStore.getState().setIsGenerating(false)
-        } else if (status.state === "error") {
-          this.stopRustStatusPolling()
-          statusText = `Error: ${status.error || "Generation failed"}`
-          this.onError?.(status.error || "Generation failed")
-          useAppStore.getState().setIsGenerating(false)
-        } else {
-          statusText = `Status: ${status.state}`
-        }
-        
-        this.onStatusChange?.(statusText)
-        useAppStore.getState().setConnectionStatus(statusText)
-      } catch (e) {
-        console.error("[Rust Status] Polling error:", e)
-      }
-    }, 500)
+    this.onStatusChange?.(statusText)
+    useAppStore.getState().setConnectionStatus(statusText)
   }
   
-  private stopRustStatusPolling(): void {
-    if (this.rustStatusInterval) {
-      clearInterval(this.rustStatusInterval)
-      this.rustStatusInterval = null
+  private stopListeningToRustGeneration(): void {
+    for (const listener of this.rustListeners) {
+      listener.then((unlisten) => unlisten()).catch(() => {})
     }
+    this.rustListeners = []
   }
 
   private isPlayingFromQueue = false
@@ -791,6 +793,21 @@ export class AudioEngine {
       }
       
       const config = buildConfigFromStore()
+      // Record how the track was made so exports can carry it
+      audioSetMetadata({
+        provenance: {
+          model: LYRIA_MODEL_NAMES[selectedModel],
+          prompts: config.prompts,
+          bpm: config.bpm,
+          key: config.key,
+          scale: config.scale,
+          density: config.density,
+          brightness: config.brightness,
+          guidance: config.guidance,
+          temperature: config.temperature,
+          negative_prompt: config.negativePrompt || null,
+        },
+      }).catch(e => console.warn("[Audio] Failed to record provenance:", e))
       debugLog.info(`Lyria: starting generation with config`)
       await this.lyriaClient.startGeneration(config)
     }
@@ -820,7 +837,7 @@ export class AudioEngine {
     
     // Stop Rust generation if active
     if (this.useRustGeneration) {
-      this.stopRustStatusPolling()
+      this.stopListeningToRustGeneration()
       try {
         await stopRustGeneration()
       } catch (e) {}
@@ -851,7 +868,7 @@ export class AudioEngine {
     
     // Stop Rust generation if active
     if (this.useRustGeneration) {
-      this.stopRustStatusPolling()
+      this.stopListeningToRustGeneration()
       try {
         await stopRustGeneration()
       } catch (e) {}
@@ -859,7 +876,7 @@ export class AudioEngine {
     
     // Stop native audio if active
     if (this.useNativeAudio) {
-      this.stopNativeStatusPolling()
+      this.stopListeningToNativePlayback()
       try {
         await audioStopPlayback()
       } catch (e) {
@@ -901,7 +918,7 @@ export class AudioEngine {
     
     // Stop native audio if active
     if (this.useNativeAudio) {
-      this.stopNativeStatusPolling()
+      this.stopListeningToNativePlayback()
       try {
         await audioStopPlayback()
       } catch (e) {
@@ -1158,7 +1175,7 @@ export class AudioEngine {
     // Stop native playback if active
     if (this.nativeChunkCount > 0) {
       audioStopPlayback().catch(() => {})
-      this.stopNativeStatusPolling()
+      this.stopListeningToNativePlayback()
     }
     
     // Stop JS playback if active
@@ -1350,14 +1367,8 @@ export class AudioEngine {
     // In native/pre-generate mode, check Rust playback status
     const preGenMode = useAppStore.getState().preGenerateMode
     if (preGenMode && this.nativeChunkCount > 0) {
-      try {
-        const status = await audioGetStatus()
-        // Not complete if Rust is still playing
-        return !status.isPlaying
-      } catch {
-        // If we can't get status, assume complete
-        return true
-      }
+      // Not complete while Rust is still playing, as last reported by its state events
+      return !this.nativeIsPlaying
     }
     
     // For Web Audio mode (short tracks)
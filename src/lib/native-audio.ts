@@ -1,10 +1,24 @@
-import { invoke } from "@tauri-apps/api/core"
+import { convertFileSrc, invoke } from "@tauri-apps/api/core"
+import { listen, type UnlistenFn } from "@tauri-apps/api/event"
+import type { VocalTrack } from "@/stores/app-store"
 
 export interface AudioStatus {
   isPlaying: boolean
+  isPaused: boolean
   position: number
-  duration: number
+  // Position allowing for output latency; use this for visuals synced to what is heard
+  heardPosition: number
+  outputLatencyMs: number
+  duration: number // of the edited session, which positions are measured along
+  recordedDuration: number
   chunkCount: number
+  underruns: number
+  loopStart: number | null
+  loopEnd: number | null
+  normalization: Normalization | null
+  edits: EditList | null
+  analysis: AnalysisOptions | null
+  vocalTracks: NativeVocalTrack[]
 }
 
 export async function audioInit(): Promise<void> {
@@ -16,21 +30,10 @@ export async function audioWriteChunkBase64(base64Data: string): Promise<number>
   return await invoke<number>("audio_write_chunk_base64", { audioDataBase64: base64Data })
 }
 
-// Legacy: for Web Audio mode compatibility
+// Legacy: for Web Audio mode compatibility. Sent as a raw binary body, not JSON
 export async function audioWriteChunk(audioData: Int16Array): Promise<number> {
-  const uint8View = new Uint8Array(audioData.buffer, audioData.byteOffset, audioData.byteLength)
-  const base64 = uint8ArrayToBase64Chunked(uint8View)
-  return await invoke<number>("audio_write_chunk_base64", { audioDataBase64: base64 })
-}
-
-function uint8ArrayToBase64Chunked(bytes: Uint8Array): string {
-  const CHUNK_SIZE = 8192
-  let binary = ''
-  for (let i = 0; i < bytes.length; i += CHUNK_SIZE) {
-    const chunk = bytes.subarray(i, Math.min(i + CHUNK_SIZE, bytes.length))
-    binary += String.fromCharCode.apply(null, chunk as unknown as number[])
-  }
-  return btoa(binary)
+  const bytes = new Uint8Array(audioData.buffer, audioData.byteOffset, audioData.byteLength)
+  return await invoke<number>("audio_write_chunk_raw", bytes)
 }
 
 export async function audioStartPlayback(): Promise<void> {
@@ -41,6 +44,11 @@ export async function audioStopPlayback(): Promise<void> {
   await invoke("audio_stop_playback")
 }
 
+// Tell native playback no more chunks are coming so it stops at the end
+export async function audioEndStream(): Promise<void> {
+  await invoke("audio_end_stream")
+}
+
 export async function audioPausePlayback(): Promise<void> {
   await invoke("audio_pause_playback")
 }
@@ -49,27 +57,416 @@ export async function audioResumePlayback(): Promise<void> {
   await invoke("audio_resume_playback")
 }
 
+// Jump to a position; when stopped, playback starts there next time
+export async function audioSeek(seconds: number): Promise<void> {
+  await invoke("audio_seek", { seconds })
+}
+
+export async function audioSetLoop(start: number, end: number): Promise<void> {
+  await invoke("audio_set_loop", { start, end })
+}
+
+export async function audioClearLoop(): Promise<void> {
+  await invoke("audio_clear_loop")
+}
+
+export type FadeCurve = "linear" | "equal_power" | "log"
+
+export interface Fade {
+  seconds: number
+  curve?: FadeCurve
+}
+
+// Cut from `from` to `to` in the recording, overlapping the two by `seconds`
+export interface Crossfade {
+  from: number
+  to: number
+  seconds: number
+  curve?: FadeCurve
+}
+
+// Non-destructive; times are seconds of the recording
+export interface EditList {
+  trim_start?: number
+  trim_end?: number | null // follows the recording as it grows when null
+  fade_in?: Fade | null
+  fade_out?: Fade | null
+  crossfades?: Crossfade[]
+}
+
+export async function audioSetEdits(edits: EditList): Promise<void> {
+  await invoke("audio_set_edits", { edits })
+}
+
+export async function audioGetEdits(): Promise<EditList> {
+  return await invoke<EditList>("audio_get_edits")
+}
+
+export async function audioSetTrim(start: number, end: number | null): Promise<void> {
+  await invoke("audio_set_trim", { start, end })
+}
+
+export async function audioSetFadeIn(fade: Fade | null): Promise<void> {
+  await invoke("audio_set_fade_in", { fade })
+}
+
+export async function audioSetFadeOut(fade: Fade | null): Promise<void> {
+  await invoke("audio_set_fade_out", { fade })
+}
+
+export async function audioAddCrossfade(crossfade: Crossfade): Promise<void> {
+  await invoke("audio_add_crossfade", { crossfade })
+}
+
+export async function audioClearEdits(): Promise<void> {
+  await invoke("audio_clear_edits")
+}
+
+export async function audioSetOutputLatency(latencyMs: number): Promise<void> {
+  await invoke("audio_set_output_latency", { latencyMs })
+}
+
 export async function audioGetStatus(): Promise<AudioStatus> {
   return await invoke<AudioStatus>("audio_get_status")
 }
 
+// Per-pixel levels across both channels, scaled to -1..1
+export interface AudioPeaks {
+  min: number[]
+  max: number[]
+  rms: number[]
+}
+
+// Waveform overview of the recording (before edits) from start to end seconds
+export async function audioGetPeaks(start: number, end: number, pixels: number): Promise<AudioPeaks> {
+  return await invoke<AudioPeaks>("audio_get_peaks", { start, end, pixels })
+}
+
 export async function audioClear(): Promise<void> {
   await invoke("audio_clear")
 }
 
+export interface Provenance {
+  model: string
+  prompts: Array<{ text: string; weight: number }>
+  bpm?: number | null
+  key?: string | null
+  scale?: string | null
+  density?: number | null
+  brightness?: number | null
+  guidance?: number | null
+  temperature?: number | null
+  negative_prompt?: string | null
+  seed?: number | null
+  generated_at?: string // filled in by the backend when omitted
+  detection?: TempoKeyDetection | null
+}
+
+export interface TempoKeyDetection {
+  tempo: { bpm: number; confidence: number } | null // confidence 0-1
+  key: { key: string; mode: "major" | "minor"; confidence: number } | null
+  seconds: number
+  requested_bpm: number | null
+  requested_key: string | null
+  requested_scale: string | null
+  tempo_matches: boolean | null // within 4%, allowing half or double time; null if unknown
+  key_matches: boolean | null // same major/relative-minor pair; null if unknown
+  detected_at: string
+}
+
+// Measure the session's tempo and key and compare them with the requested BPM and
+// scale. The result is also kept in the provenance written into exports
+export async function audioDetectTempoKey(): Promise<TempoKeyDetection> {
+  return await invoke<TempoKeyDetection>("audio_detect_tempo_key")
+}
+
+export interface TrackMetadata {
+  title?: string | null
+  artist?: string | null
+  bpm?: number | null // defaults to the session's BPM
+  key?: string | null // e.g. "F#m"; defaults to the session's key
+  provenance?: Provenance | null // keeps the native session's provenance when omitted
+}
+
+// Tags written into every export format
+export async function audioSetMetadata(metadata: TrackMetadata): Promise<void> {
+  await invoke("audio_set_metadata", { metadata })
+}
+
+export async function audioGetMetadata(): Promise<TrackMetadata> {
+  return await invoke<TrackMetadata>("audio_get_metadata")
+}
+
+export interface LoudnessReport {
+  integrated_lufs: number | null // null when too short or quiet to measure
+  max_short_term_lufs: number | null
+  true_peak_dbtp: number | null
+}
+
+export interface Normalization {
+  target_lufs: number // -60 to 0
+  true_peak_ceiling_dbtp: number // -20 to 0
+}
+
+export interface NormalizationResult {
+  gain_db: number
+  integrated_lufs: number | null
+  true_peak_dbtp: number | null
+  reached_target: boolean // false when the true-peak ceiling held the level down
+}
+
+export interface AudioLoudnessEvent {
+  report: LoudnessReport
+  normalization: NormalizationResult | null
+}
+
+export async function audioMeasureLoudness(): Promise<LoudnessReport> {
+  return await invoke<LoudnessReport>("audio_measure_loudness")
+}
+
+// Applies to every export until changed; null exports at the recorded level
+export async function audioSetNormalization(normalization: Normalization | null): Promise<void> {
+  await invoke("audio_set_normalization", { normalization })
+}
+
+export interface AnalysisOptions {
+  enabled?: boolean
+  fft_size?: number // power of two, 256 to 16384
+  bands?: number // log-spaced from 20 Hz to Nyquist, 1 to 512
+}
+
+// Meters of native playback; changes apply during playback
+export async function audioSetAnalysis(options?: AnalysisOptions): Promise<void> {
+  await invoke("audio_set_analysis", { options })
+}
+
+export interface VocalTrackSettings {
+  volume?: number // linear, 0 to 4
+  pan?: number // -1 (left) to 1 (right)
+  muted?: boolean
+  start_time?: number // seconds into the edited session
+}
+
+export interface NativeVocalTrack extends Required<VocalTrackSettings> {
+  id: string
+  name: string
+  duration: number // at the session rate
+  source_sample_rate: number
+  source_channels: number
+  channels: number // 1 or 2; further channels are dropped
+}
+
+// Decode a WAV, MP3 or FLAC file to mix into native exports. Replaces the track
+// with the same id, keeping its settings
+export async function audioAddVocalTrack(id: string, file: File): Promise<NativeVocalTrack> {
+  const bytes = new Uint8Array(await file.arrayBuffer())
+  return await invoke<NativeVocalTrack>("audio_add_vocal_track", bytes, {
+    headers: { "x-track-id": id, "x-track-name": encodeURIComponent(file.name) },
+  })
+}
+
+export async function audioSetVocalTrack(id: string, settings?: VocalTrackSettings): Promise<void> {
+  await invoke("audio_set_vocal_track", { id, settings })
+}
+
+export async function audioRemoveVocalTrack(id: string): Promise<void> {
+  await invoke("audio_remove_vocal_track", { id })
+}
+
+export async function audioGetVocalTracks(): Promise<NativeVocalTrack[]> {
+  return await invoke<NativeVocalTrack[]>("audio_get_vocal_tracks")
+}
+
+// Files already decoded natively, so syncing only sends new or replaced ones
+const sentVocalFiles = new Map<string, File>()
+
+// Bring the native mix in line with the vocal tracks in the store, ahead of an export
+export async function audioSyncVocalTracks(tracks: VocalTrack[]): Promise<void> {
+  const loaded = tracks.filter((t): t is VocalTrack & { file: File } => t.file !== null)
+  const native = await audioGetVocalTracks()
+  for (const track of native) {
+    if (!loaded.some((t) => t.id === track.id)) {
+      await audioRemoveVocalTrack(track.id)
+      sentVocalFiles.delete(track.id)
+    }
+  }
+  for (const track of loaded) {
+    if (sentVocalFiles.get(track.id) !== track.file || !native.some((t) => t.id === track.id)) {
+      await audioAddVocalTrack(track.id, track.file)
+      sentVocalFiles.set(track.id, track.file)
+    }
+    await audioSetVocalTrack(track.id, {
+      volume: track.volume,
+      pan: track.pan,
+      muted: track.muted,
+      start_time: track.startTime,
+    })
+  }
+}
+
+export interface LoopSearch {
+  bpm?: number | null // defaults to the session BPM
+  bars?: number
+  beats_per_bar?: number
+  max_results?: number
+}
+
+export interface LoopCandidate {
+  start: number
+  end: number
+  bars: number
+  bpm: number
+  score: number // up to 1 for a seamless loop
+}
+
+export interface LoopExportOptions {
+  seam?: "crossfade" | "zero_crossing"
+  seam_ms?: number // crossfade length, or how far a zero-crossing seam may move
+}
+
+// Best bar-aligned loops first; positions are on the edited timeline
+export async function audioFindLoops(search?: LoopSearch): Promise<LoopCandidate[]> {
+  return await invoke<LoopCandidate[]>("audio_find_loops", { search: search ?? null })
+}
+
+// Writes a WAV with a smpl chunk marking the loop points
+// Vocal tracks, normalization and the WAV format apply as in audioExportWav
+export async function audioExportLoop(
+  outputPath: string,
+  start: number,
+  end: number,
+  options?: LoopExportOptions,
+  wav?: WavExportOptions
+): Promise<void> {
+  await invoke("audio_export_loop", { outputPath, start, end, options: options ?? null, wav: wav ?? null })
+}
+
 export async function audioGetSamples(): Promise<Int16Array> {
-  const samples = await invoke<number[]>("audio_get_samples")
-  return new Int16Array(samples)
+  return await audioReadPcm()
+}
+
+// Edited session from start to end seconds (default: all of it), transferred as raw bytes
+export async function audioReadPcm(start?: number, end?: number): Promise<Int16Array> {
+  const bytes = await invoke<ArrayBuffer>("audio_read_pcm", { start, end })
+  return new Int16Array(bytes)
+}
+
+// URL of the edited session served by the lyria-audio scheme, which supports
+// Range requests, so an <audio> element or fetch() can stream it while it records.
+// "pcm" is bare interleaved little-endian 16-bit stereo at 48 kHz.
+export function audioStreamUrl(format: "wav" | "pcm" = "wav"): string {
+  return convertFileSrc(`session.${format}`, "lyria-audio")
 }
 
 export async function audioExport(outputPath: string): Promise<void> {
   await invoke("audio_export", { outputPath })
 }
 
+export interface WavExportOptions {
+  sample_rate?: 44100 | 48000 | 88200 | 96000 // resampled from the 48 kHz session
+  bits_per_sample?: 16 | 24 | 32 // 32 is float
+  // Noise added when rounding to 16 or 24 bits; skipped when the session is
+  // exported unchanged, since its samples are already exact
+  dither?: "none" | "tpdf" | "shaped"
+}
+
+export async function audioExportWav(outputPath: string, options?: WavExportOptions): Promise<void> {
+  await invoke("audio_export_wav", { outputPath, options: options ?? null })
+}
+
 export async function audioExportFormat(outputPath: string, format: string, bitrate: number): Promise<void> {
   await invoke("audio_export_format", { outputPath, format, bitrate })
 }
 
+export interface Mp3ExportOptions {
+  mode?: "cbr" | "vbr" | "abr"
+  bitrate_kbps?: number // CBR bitrate or ABR average
+  vbr_quality?: number // 0 (best) to 9.999, VBR only
+  quality?: number // encoder effort, 0 (best) to 9
+}
+
+export async function audioExportMp3(outputPath: string, options?: Mp3ExportOptions): Promise<void> {
+  await invoke("audio_export_mp3", { outputPath, options: options ?? null })
+}
+
+export interface FlacExportOptions {
+  compression_level?: number // 0-8
+  block_size?: number
+  bits_per_sample?: 16 | 24
+  tags?: [string, string][]
+}
+
+export async function audioExportFlac(outputPath: string, options?: FlacExportOptions): Promise<void> {
+  await invoke("audio_export_flac", { outputPath, options: options ?? null })
+}
+
+export interface OpusExportOptions {
+  bitrate_kbps?: number // 6-510
+  complexity?: number // 0-10
+  vbr?: boolean
+  tags?: [string, string][]
+}
+
+export async function audioExportOpus(outputPath: string, options?: OpusExportOptions): Promise<void> {
+  await invoke("audio_export_opus", { outputPath, options: options ?? null })
+}
+
+export interface VorbisExportOptions {
+  quality?: number | null // -0.2 to 1.0, takes precedence over bitrate_kbps
+  bitrate_kbps?: number | null
+  tags?: [string, string][]
+}
+
+export async function audioExportVorbis(outputPath: string, options?: VorbisExportOptions): Promise<void> {
+  await invoke("audio_export_vorbis", { outputPath, options: options ?? null })
+}
+
+export interface AudioChunkEvent {
+  chunk_index: number
+  samples: number
+  total_samples: number
+  duration_seconds: number
+}
+
+export function onAudioChunk(callback: (chunk: AudioChunkEvent) => void): Promise<UnlistenFn> {
+  return listen<AudioChunkEvent>("audio:chunk", (event) => callback(event.payload))
+}
+
+export function onAudioPlaybackState(
+  callback: (state: { is_playing: boolean; is_paused: boolean }) => void
+): Promise<UnlistenFn> {
+  return listen<{ is_playing: boolean; is_paused: boolean }>("audio:state", (event) => callback(event.payload))
+}
+
+export interface AudioPositionEvent {
+  position: number
+  heard_position: number
+}
+
+export function onAudioPosition(callback: (position: AudioPositionEvent) => void): Promise<UnlistenFn> {
+  return listen<AudioPositionEvent>("audio:position", (event) => callback(event.payload))
+}
+
+// Sent when a normalized export measures the session
+export function onAudioLoudness(callback: (event: AudioLoudnessEvent) => void): Promise<UnlistenFn> {
+  return listen<AudioLoudnessEvent>("audio:loudness", (event) => callback(event.payload))
+}
+
+export interface AudioMeterEvent {
+  position: number // heard position of the reading
+  levels: {
+    peak_db: number[] // per channel, dBFS, since the previous reading
+    rms_db: number[]
+    correlation: number // -1 (out of phase) to 1 (mono)
+  }
+  spectrum: number[] // dBFS per band, lowest first, floored at -100
+}
+
+// Sent during native playback, in step with what is being heard
+export function onAudioMeter(callback: (event: AudioMeterEvent) => void): Promise<UnlistenFn> {
+  return listen<AudioMeterEvent>("audio:meter", (event) => callback(event.payload))
+}
+
 export function floatToInt16(floatData: Float32Array): Int16Array {
   const int16Data = new Int16Array(floatData.length)
   for (let i = 0; i < floatData.length; i++) {
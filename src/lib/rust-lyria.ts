@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core"
+import { listen, type UnlistenFn } from "@tauri-apps/api/event"
 
 export interface GenerationStatus {
   state: string
@@ -6,20 +7,78 @@ export interface GenerationStatus {
   total_samples: number
   duration_seconds: number
   error: string | null
+  reconnect_attempts: number
+}
+
+export interface RustGenerationConfig {
+  bpm?: number
+  key?: string
+  scale?: string
+  density?: number
+  brightness?: number
+  guidance?: number
+  temperature?: number
+  negative_prompt?: string
+  seed?: number
+}
+
+export interface RustReconnectPolicy {
+  max_attempts: number
+  initial_delay_ms: number
+  max_delay_ms: number
+}
+
+export interface RustConnectionConfig {
+  base_url?: string
+  api_version?: string
+  model?: string
+}
+
+export interface RustPromptWeight {
+  text: string
+  weight: number
 }
 
 export async function startRustGeneration(
   apiKey: string,
-  prompt: string,
-  durationSeconds: number
+  prompts: RustPromptWeight[],
+  durationSeconds: number,
+  config?: RustGenerationConfig,
+  reconnect?: RustReconnectPolicy,
+  connection?: RustConnectionConfig
 ): Promise<void> {
   await invoke("lyria_start_generation", {
     apiKey,
-    prompt,
+    prompts: prompts.map(({ text, weight }) => ({ text, weight })),
     durationSeconds,
+    config: config ?? null,
+    reconnect: reconnect ?? null,
+    connection: connection ?? null,
+  })
+}
+
+export async function updateRustPrompts(prompts: RustPromptWeight[]): Promise<void> {
+  await invoke("lyria_update_prompts", {
+    prompts: prompts.map(({ text, weight }) => ({ text, weight })),
   })
 }
 
+export async function updateRustConfig(config: RustGenerationConfig): Promise<void> {
+  await invoke("lyria_update_config", { config })
+}
+
+export async function pauseRustGeneration(): Promise<void> {
+  await invoke("lyria_pause")
+}
+
+export async function resumeRustGeneration(): Promise<void> {
+  await invoke("lyria_resume")
+}
+
+export async function resetRustContext(): Promise<void> {
+  await invoke("lyria_reset_context")
+}
+
 export async function stopRustGeneration(): Promise<void> {
   await invoke("lyria_stop_generation")
 }
@@ -31,3 +90,17 @@ export async function getRustGenerationStatus(): Promise<GenerationStatus> {
 export async function isRustGenerating(): Promise<boolean> {
   return await invoke<boolean>("lyria_is_generating")
 }
+
+export function onRustGenerationStatus(callback: (status: GenerationStatus) => void): Promise<UnlistenFn> {
+  return listen<GenerationStatus>("lyria:status", (event) => callback(event.payload))
+}
+
+export function onRustGenerationError(callback: (message: string) => void): Promise<UnlistenFn> {
+  return listen<{ message: string }>("lyria:error", (event) => callback(event.payload.message))
+}
+
+export function onRustFilteredPrompt(
+  callback: (prompt: { text: string | null; reason: string }) => void
+): Promise<UnlistenFn> {
+  return listen<{ text: string | null; reason: string }>("lyria:filtered-prompt", (event) => callback(event.payload))
+}